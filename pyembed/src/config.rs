@@ -17,6 +17,9 @@ use {
     },
 };
 
+#[cfg(feature = "config-overrides")]
+use super::config_overrides::apply_config_overrides_file;
+
 /// Defines an extra extension module to load.
 #[derive(Clone, Debug)]
 pub struct ExtensionModule {
@@ -27,6 +30,32 @@ pub struct ExtensionModule {
     pub init_func: unsafe extern "C" fn() -> *mut pyffi::PyObject,
 }
 
+/// Defines a source of packed resources data.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PackedResourcesSource<'a> {
+    /// A reference to raw resources data in memory.
+    ///
+    /// This is typically the result of an `include_bytes!(...)` of a file
+    /// generated by PyOxidizer.
+    Memory(&'a [u8]),
+
+    /// Load resources data from a filesystem path using memory mapped I/O.
+    ///
+    /// The file is memory mapped when the interpreter is initialized and
+    /// parsed without copying. The mapping is held for the lifetime of the
+    /// interpreter.
+    ///
+    /// `$ORIGIN` in the path is expanded to the directory of the current
+    /// executable.
+    MemoryMappedPath(PathBuf),
+}
+
+impl<'a> From<&'a [u8]> for PackedResourcesSource<'a> {
+    fn from(data: &'a [u8]) -> Self {
+        Self::Memory(data)
+    }
+}
+
 /// Configure a Python interpreter.
 ///
 /// This type defines the configuration of a Python interpreter. It is used
@@ -104,15 +133,62 @@ pub struct OxidizedPythonInterpreterConfig<'a> {
     /// Whether to install the default `PathFinder` meta path finder.
     pub filesystem_importer: bool,
 
-    /// Reference to packed resources data.
+    /// Sources of packed resources data.
     ///
     /// The referenced data contains Python module data. It likely comes from an
-    /// `include_bytes!(...)` of a file generated by PyOxidizer.
+    /// `include_bytes!(...)` of a file generated by PyOxidizer or from a
+    /// memory mapped file alongside the executable.
     ///
     /// The format of the data is defined by the ``python-packed-resources``
     /// crate. The data will be parsed as part of initializing the custom
     /// meta path importer during interpreter initialization.
-    pub packed_resources: Vec<&'a [u8]>,
+    pub packed_resources: Vec<PackedResourcesSource<'a>>,
+
+    /// Whether to verify the integrity of `packed_resources` data.
+    ///
+    /// If true, every blob section in every packed resources data must have
+    /// a recorded digest and interpreter initialization fails if a section
+    /// doesn't match its digest. This requires reading all packed resources
+    /// data at startup.
+    ///
+    /// Digests don't cover the header and indices of the data. So this
+    /// detects corruption of resource data but not tampering with the data.
+    /// Use `packed_resources_public_key` to detect tampering.
+    ///
+    /// Requires the `digests` feature. Interpreter initialization fails if
+    /// this is true and the feature is disabled.
+    pub verify_packed_resources_digests: bool,
+
+    /// ed25519 public key that `packed_resources` data must be signed with.
+    ///
+    /// If set, every entry in `packed_resources` must carry an ed25519 signature
+    /// (as appended by `python_packed_resources::writer::append_signature()`)
+    /// that verifies against this key. Interpreter initialization fails
+    /// otherwise. This is useful when packed resources data is loaded from
+    /// an external file. Such files are read into memory instead of being
+    /// memory mapped, so their content can't change after it is verified.
+    ///
+    /// Requires the `signatures` feature. Interpreter initialization fails if
+    /// this is set and the feature is disabled.
+    ///
+    /// PyOxidizer doesn't sign the packed resources data it produces and its
+    /// configuration files have no equivalent of this field. So signature
+    /// verification is only available to users of this crate who sign
+    /// packed resources data themselves.
+    pub packed_resources_public_key: Option<[u8; 32]>,
+
+    /// Directory in which to materialize in-memory package resources.
+    ///
+    /// If set, `ResourceReader.resource_path()` on the oxidized importer
+    /// writes requested in-memory package resources to a per-process
+    /// directory within this directory and returns the path to the written
+    /// file. The per-process directory is deleted on interpreter shutdown.
+    ///
+    /// If not set, in-memory package resources don't have a filesystem path.
+    ///
+    /// `$ORIGIN` in the path is expanded to the directory of the current
+    /// executable.
+    pub resources_materialization_directory: Option<PathBuf>,
 
     /// Extra extension modules to make available to the interpreter.
     ///
@@ -167,6 +243,62 @@ pub struct OxidizedPythonInterpreterConfig<'a> {
     /// the directory specified containing a ``\n`` delimited list of modules
     /// loaded in ``sys.modules``.
     pub write_modules_directory_env: Option<String>,
+
+    /// Environment variable holding the directory to write an import trace file.
+    ///
+    /// If this value is set and the environment variable it refers to is set,
+    /// the ``OxidizedFinder`` meta path importer records timings of modules it
+    /// finds and loads. On interpreter shutdown, we will write an
+    /// ``import-trace-<random>.json`` file to the directory specified
+    /// containing these records.
+    ///
+    /// Only has an effect if ``oxidized_importer`` is enabled.
+    pub write_import_trace_directory_env: Option<String>,
+
+    /// Name prefixes of modules whose execution should be deferred.
+    ///
+    /// Modules imported by the ``OxidizedFinder`` whose name equals one of
+    /// these values or starts with one of these values followed by a ``.``
+    /// are executed lazily, as if loaded by ``importlib.util.LazyLoader``:
+    /// the module body is only executed on first attribute access.
+    ///
+    /// Only applies to modules backed by source or bytecode. Only has an
+    /// effect if ``oxidized_importer`` is enabled.
+    pub lazy_module_prefixes: Option<Vec<String>>,
+
+    /// Path to a file defining run-time overrides of this configuration.
+    ///
+    /// If the file exists, `MainPythonInterpreter::new()` reads it and applies
+    /// the settings it defines before resolving the configuration. A missing
+    /// file is not an error. The file is parsed as JSON if its name ends in
+    /// `.json` and as TOML otherwise.
+    ///
+    /// Only fields listed in `config_overrides_allowed_fields` can be overridden.
+    ///
+    /// Requires the `config-overrides` feature. Interpreter initialization fails
+    /// if a file is found and the feature is disabled.
+    ///
+    /// `$ORIGIN` in the path is expanded to the directory of the current
+    /// executable.
+    pub config_overrides_path: Option<PathBuf>,
+
+    /// Environment variable holding the path to a configuration overrides file.
+    ///
+    /// If this value is set and the environment variable it refers to is set,
+    /// the file it points to is used instead of `config_overrides_path`. Unlike
+    /// with `config_overrides_path`, the file must exist.
+    ///
+    /// The environment variable is ignored if the interpreter is isolated or
+    /// doesn't use the environment.
+    pub config_overrides_path_env: Option<String>,
+
+    /// Names of fields that can be overridden by a configuration overrides file.
+    ///
+    /// Names are those of `PythonInterpreterConfig` and
+    /// `OxidizedPythonInterpreterConfig` fields. e.g. `module_search_paths` or
+    /// `run_module`. Overriding a field not in this list is an error. If `None`,
+    /// no fields can be overridden.
+    pub config_overrides_allowed_fields: Option<Vec<String>>,
 }
 
 impl<'a> Default for OxidizedPythonInterpreterConfig<'a> {
@@ -183,6 +315,9 @@ impl<'a> Default for OxidizedPythonInterpreterConfig<'a> {
             oxidized_importer: false,
             filesystem_importer: true,
             packed_resources: vec![],
+            verify_packed_resources_digests: false,
+            packed_resources_public_key: None,
+            resources_materialization_directory: None,
             extra_extension_modules: None,
             argv: None,
             argvb: false,
@@ -191,27 +326,110 @@ impl<'a> Default for OxidizedPythonInterpreterConfig<'a> {
             terminfo_resolution: TerminfoResolution::Dynamic,
             tcl_library: None,
             write_modules_directory_env: None,
+            write_import_trace_directory_env: None,
+            lazy_module_prefixes: None,
+            config_overrides_path: None,
+            config_overrides_path_env: None,
+            config_overrides_allowed_fields: None,
         }
     }
 }
 
 impl<'a> OxidizedPythonInterpreterConfig<'a> {
-    /// Create a new type with all values resolved.
-    pub fn resolve(self) -> Result<ResolvedOxidizedPythonInterpreterConfig<'a>, &'static str> {
-        let exe = if let Some(exe) = self.exe {
-            exe
+    /// Resolve the current executable and the path for `$ORIGIN`.
+    fn resolve_exe_and_origin(&self) -> Result<(PathBuf, PathBuf), &'static str> {
+        let exe = if let Some(exe) = &self.exe {
+            exe.clone()
         } else {
             std::env::current_exe().map_err(|_| "could not obtain current executable")?
         };
 
-        let origin = if let Some(origin) = self.origin {
-            origin
+        let origin = if let Some(origin) = &self.origin {
+            origin.clone()
         } else {
             exe.parent()
                 .ok_or("unable to obtain current executable parent directory")?
                 .to_path_buf()
         };
 
+        Ok((exe, origin))
+    }
+
+    /// Resolve the path of the configuration overrides file to apply, if any.
+    fn resolve_config_overrides_path(&self) -> Result<Option<PathBuf>, String> {
+        // Mirror how the interpreter resolves these settings.
+        let isolated = self
+            .interpreter_config
+            .isolated
+            .unwrap_or(self.interpreter_config.profile == PythonInterpreterProfile::Isolated);
+        let use_environment = !isolated && self.interpreter_config.use_environment != Some(false);
+
+        let env_path = if use_environment {
+            self.config_overrides_path_env
+                .as_ref()
+                .and_then(std::env::var_os)
+        } else {
+            None
+        };
+
+        let path = if let Some(path) = env_path {
+            PathBuf::from(path)
+        } else if let Some(path) = &self.config_overrides_path {
+            let (_, origin) = self.resolve_exe_and_origin()?;
+
+            let path = PathBuf::from(
+                path.display()
+                    .to_string()
+                    .replace("$ORIGIN", &origin.display().to_string()),
+            );
+
+            if !path.exists() {
+                return Ok(None);
+            }
+
+            path
+        } else {
+            return Ok(None);
+        };
+
+        Ok(Some(path))
+    }
+
+    /// Apply settings from a configuration overrides file, if present.
+    ///
+    /// See `config_overrides_path` for how the file is located. Returns the
+    /// path of the file that was applied, if any.
+    #[cfg(feature = "config-overrides")]
+    pub fn apply_config_overrides(&mut self) -> Result<Option<PathBuf>, String> {
+        let path = match self.resolve_config_overrides_path()? {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        apply_config_overrides_file(self, &path)?;
+
+        Ok(Some(path))
+    }
+
+    /// Apply settings from a configuration overrides file, if present.
+    ///
+    /// Always fails if a file is present, as support for configuration
+    /// overrides files is disabled.
+    #[cfg(not(feature = "config-overrides"))]
+    pub fn apply_config_overrides(&mut self) -> Result<Option<PathBuf>, String> {
+        match self.resolve_config_overrides_path()? {
+            Some(path) => Err(format!(
+                "{}: configuration overrides files require the config-overrides feature",
+                path.display()
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Create a new type with all values resolved.
+    pub fn resolve(self) -> Result<ResolvedOxidizedPythonInterpreterConfig<'a>, &'static str> {
+        let (exe, origin) = self.resolve_exe_and_origin()?;
+
         let origin_string = origin.display().to_string();
 
         let module_search_paths = match &self.interpreter_config.module_search_paths {
@@ -226,6 +444,19 @@ impl<'a> OxidizedPythonInterpreterConfig<'a> {
             None => None,
         };
 
+        let packed_resources = self
+            .packed_resources
+            .into_iter()
+            .map(|source| match source {
+                PackedResourcesSource::Memory(_) => source,
+                PackedResourcesSource::MemoryMappedPath(p) => {
+                    PackedResourcesSource::MemoryMappedPath(PathBuf::from(
+                        p.display().to_string().replace("$ORIGIN", &origin_string),
+                    ))
+                }
+            })
+            .collect::<Vec<_>>();
+
         let tcl_library = if let Some(tcl_library) = self.tcl_library {
             Some(PathBuf::from(
                 tcl_library
@@ -237,6 +468,17 @@ impl<'a> OxidizedPythonInterpreterConfig<'a> {
             None
         };
 
+        let resources_materialization_directory =
+            if let Some(path) = self.resources_materialization_directory {
+                Some(PathBuf::from(
+                    path.display()
+                        .to_string()
+                        .replace("$ORIGIN", &origin_string),
+                ))
+            } else {
+                None
+            };
+
         Ok(ResolvedOxidizedPythonInterpreterConfig {
             inner: Self {
                 exe: Some(exe),
@@ -245,6 +487,8 @@ impl<'a> OxidizedPythonInterpreterConfig<'a> {
                     module_search_paths,
                     ..self.interpreter_config
                 },
+                packed_resources,
+                resources_materialization_directory,
                 tcl_library,
                 ..self
             },
@@ -315,4 +559,12 @@ impl<'a> ResolvedOxidizedPythonInterpreterConfig<'a> {
             .as_ref()
             .expect("origin should have a value")
     }
+
+    /// Whether the importer should record import activity.
+    pub(crate) fn trace_imports(&self) -> bool {
+        match &self.inner.write_import_trace_directory_env {
+            Some(key) => std::env::var_os(key).is_some(),
+            None => false,
+        }
+    }
 }
@@ -0,0 +1,212 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Run-time overrides of interpreter configuration.
+
+A configuration overrides file is a JSON object or TOML table whose keys are
+names of configuration fields and whose values are the values to set. e.g.
+
+```toml
+module_search_paths = ["$ORIGIN/lib"]
+optimization_level = 2
+run_module = "myapp.main"
+```
+
+In JSON files, `null` resets a field to `None`.
+*/
+
+use {
+    super::config::OxidizedPythonInterpreterConfig,
+    python_packaging::resource::BytecodeOptimizationLevel,
+    serde_json::Value,
+    std::{convert::TryFrom, path::Path, path::PathBuf},
+};
+
+fn to_bool(key: &str, value: &Value) -> Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| format!("{} must be a boolean", key))
+}
+
+fn to_optional_bool(key: &str, value: &Value) -> Result<Option<bool>, String> {
+    if value.is_null() {
+        Ok(None)
+    } else {
+        Ok(Some(to_bool(key, value)?))
+    }
+}
+
+fn to_optional_string(key: &str, value: &Value) -> Result<Option<String>, String> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(s) => Ok(Some(s.clone())),
+        _ => Err(format!("{} must be a string", key)),
+    }
+}
+
+fn to_optional_pathbuf(key: &str, value: &Value) -> Result<Option<PathBuf>, String> {
+    Ok(to_optional_string(key, value)?.map(PathBuf::from))
+}
+
+fn to_optional_vec_string(key: &str, value: &Value) -> Result<Option<Vec<String>>, String> {
+    match value {
+        Value::Null => Ok(None),
+        Value::Array(values) => Ok(Some(
+            values
+                .iter()
+                .map(|v| {
+                    v.as_str()
+                        .map(|s| s.to_string())
+                        .ok_or_else(|| format!("{} must be a list of strings", key))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        )),
+        _ => Err(format!("{} must be a list of strings", key)),
+    }
+}
+
+fn to_optional_vec_pathbuf(key: &str, value: &Value) -> Result<Option<Vec<PathBuf>>, String> {
+    Ok(to_optional_vec_string(key, value)?
+        .map(|values| values.into_iter().map(PathBuf::from).collect::<Vec<_>>()))
+}
+
+fn to_optional_optimization_level(
+    key: &str,
+    value: &Value,
+) -> Result<Option<BytecodeOptimizationLevel>, String> {
+    if value.is_null() {
+        return Ok(None);
+    }
+
+    let level = value
+        .as_i64()
+        .ok_or_else(|| format!("{} must be an integer", key))?;
+
+    Ok(Some(
+        BytecodeOptimizationLevel::try_from(level as i32).map_err(|e| format!("{}: {}", key, e))?,
+    ))
+}
+
+/// Set the field named `key` on `config` to `value`.
+fn apply_value(
+    config: &mut OxidizedPythonInterpreterConfig,
+    key: &str,
+    value: &Value,
+) -> Result<(), String> {
+    let c = &mut config.interpreter_config;
+
+    match key {
+        "buffered_stdio" => c.buffered_stdio = to_optional_bool(key, value)?,
+        "development_mode" => c.development_mode = to_optional_bool(key, value)?,
+        "fault_handler" => c.fault_handler = to_optional_bool(key, value)?,
+        "filesystem_encoding" => c.filesystem_encoding = to_optional_string(key, value)?,
+        "filesystem_errors" => c.filesystem_errors = to_optional_string(key, value)?,
+        "home" => c.home = to_optional_pathbuf(key, value)?,
+        "import_time" => c.import_time = to_optional_bool(key, value)?,
+        "inspect" => c.inspect = to_optional_bool(key, value)?,
+        "interactive" => c.interactive = to_optional_bool(key, value)?,
+        "isolated" => c.isolated = to_optional_bool(key, value)?,
+        "module_search_paths" => c.module_search_paths = to_optional_vec_pathbuf(key, value)?,
+        "optimization_level" => c.optimization_level = to_optional_optimization_level(key, value)?,
+        "parser_debug" => c.parser_debug = to_optional_bool(key, value)?,
+        "program_name" => c.program_name = to_optional_pathbuf(key, value)?,
+        "pycache_prefix" => c.pycache_prefix = to_optional_pathbuf(key, value)?,
+        "quiet" => c.quiet = to_optional_bool(key, value)?,
+        "run_command" => c.run_command = to_optional_string(key, value)?,
+        "run_filename" => c.run_filename = to_optional_pathbuf(key, value)?,
+        "run_module" => c.run_module = to_optional_string(key, value)?,
+        "site_import" => c.site_import = to_optional_bool(key, value)?,
+        "stdio_encoding" => c.stdio_encoding = to_optional_string(key, value)?,
+        "stdio_errors" => c.stdio_errors = to_optional_string(key, value)?,
+        "tracemalloc" => c.tracemalloc = to_optional_bool(key, value)?,
+        "use_environment" => c.use_environment = to_optional_bool(key, value)?,
+        "user_site_directory" => c.user_site_directory = to_optional_bool(key, value)?,
+        "utf8_mode" => c.utf8_mode = to_optional_bool(key, value)?,
+        "verbose" => c.verbose = to_optional_bool(key, value)?,
+        "warn_options" => c.warn_options = to_optional_vec_string(key, value)?,
+        "write_bytecode" => c.write_bytecode = to_optional_bool(key, value)?,
+        "x_options" => c.x_options = to_optional_vec_string(key, value)?,
+
+        "filesystem_importer" => config.filesystem_importer = to_bool(key, value)?,
+        "lazy_module_prefixes" => config.lazy_module_prefixes = to_optional_vec_string(key, value)?,
+        "resources_materialization_directory" => {
+            config.resources_materialization_directory = to_optional_pathbuf(key, value)?
+        }
+        "sys_frozen" => config.sys_frozen = to_bool(key, value)?,
+        "sys_meipass" => config.sys_meipass = to_bool(key, value)?,
+        "tcl_library" => config.tcl_library = to_optional_pathbuf(key, value)?,
+        "write_import_trace_directory_env" => {
+            config.write_import_trace_directory_env = to_optional_string(key, value)?
+        }
+        "write_modules_directory_env" => {
+            config.write_modules_directory_env = to_optional_string(key, value)?
+        }
+
+        _ => return Err(format!("{} cannot be overridden at run-time", key)),
+    }
+
+    Ok(())
+}
+
+/// Parse the content of a configuration overrides file.
+///
+/// Files with a `.json` extension are parsed as JSON. Everything else is
+/// parsed as TOML.
+fn parse_config_overrides(path: &Path, data: &[u8]) -> Result<Value, String> {
+    if path.extension().and_then(|e| e.to_str()) == Some("json") {
+        serde_json::from_slice(data).map_err(|e| e.to_string())
+    } else {
+        let value = toml::from_slice::<toml::Value>(data).map_err(|e| e.to_string())?;
+
+        serde_json::to_value(value).map_err(|e| e.to_string())
+    }
+}
+
+/// Apply the configuration overrides file at `path` to `config`.
+///
+/// Every field set by the file must be listed in
+/// `config.config_overrides_allowed_fields`. No changes are made to `config`
+/// if an error occurs.
+pub(crate) fn apply_config_overrides_file(
+    config: &mut OxidizedPythonInterpreterConfig,
+    path: &Path,
+) -> Result<(), String> {
+    let data =
+        std::fs::read(path).map_err(|e| format!("error reading {}: {}", path.display(), e))?;
+
+    let overrides = parse_config_overrides(path, &data)
+        .map_err(|e| format!("error parsing {}: {}", path.display(), e))?;
+
+    let overrides = overrides.as_object().ok_or_else(|| {
+        format!(
+            "error parsing {}: expected a table of configuration fields",
+            path.display()
+        )
+    })?;
+
+    let allowed = config
+        .config_overrides_allowed_fields
+        .clone()
+        .unwrap_or_default();
+
+    let mut new_config = config.clone();
+
+    for (key, value) in overrides {
+        if !allowed.contains(key) {
+            return Err(format!(
+                "{}: {} is not allowed to be overridden",
+                path.display(),
+                key
+            ));
+        }
+
+        apply_value(&mut new_config, key, value)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+
+    *config = new_config;
+
+    Ok(())
+}
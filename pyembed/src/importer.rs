@@ -9,23 +9,30 @@ This module defines a Python meta path importer and associated functionality
 for importing Python modules from memory.
 */
 
+#[cfg(target_os = "linux")]
+use super::memory_so::{create_memory_file, load_shared_library_dependencies, memory_file_path};
 #[cfg(not(library_mode = "extension"))]
-use cpython::NoArgs;
+use super::pyalloc::raw_allocator_stats;
 use {
-    super::conversion::pyobject_to_pathbuf,
+    super::conversion::{path_to_pyobject, pyobject_to_pathbuf},
     super::python_resources::{
         pyobject_to_resource, resource_to_pyobject, ModuleFlavor, OptimizeLevel, OxidizedResource,
         PythonResourcesState,
     },
     super::resource_scanning::find_resources_in_path,
     cpython::buffer::PyBuffer,
-    cpython::exc::{FileNotFoundError, IOError, ImportError, ValueError},
+    cpython::exc::{
+        FileNotFoundError, IOError, ImportError, NotImplementedError, TypeError, ValueError,
+    },
     cpython::{
-        py_class, py_fn, ObjectProtocol, PyBytes, PyCapsule, PyClone, PyDict, PyErr, PyList,
-        PyModule, PyObject, PyResult, PyString, PyTuple, Python, PythonObject, ToPyObject,
+        py_class, py_fn, NoArgs, ObjectProtocol, PyBytes, PyCapsule, PyClone, PyDict, PyErr,
+        PyList, PyModule, PyObject, PyResult, PyString, PyTuple, Python, PythonObject, ToPyObject,
     },
     python3_sys as pyffi,
-    std::sync::Arc,
+    std::collections::{HashMap, HashSet},
+    std::path::Path,
+    std::sync::{Arc, Mutex},
+    std::time::{Duration, Instant},
 };
 #[cfg(windows)]
 use {
@@ -105,7 +112,56 @@ fn extension_module_shared_library_create_module(
     })
 }
 
-#[cfg(unix)]
+/// Implementation of `Loader.create_module()` for in-memory extension modules.
+///
+/// The library data is written to an anonymous, memory backed file and
+/// `_imp.create_dynamic()` is called with a copy of the module spec whose
+/// `origin` refers to that file. CPython then performs its regular extension
+/// module loading, including `dlopen()` of the file.
+#[cfg(target_os = "linux")]
+fn extension_module_shared_library_create_module(
+    resources_state: &PythonResourcesState<u8>,
+    py: Python,
+    _sys_modules: PyObject,
+    spec: &PyObject,
+    name_py: PyObject,
+    name: &str,
+    library_data: &[u8],
+) -> PyResult<PyObject> {
+    load_shared_library_dependencies(resources_state, name).map_err(|e| {
+        PyErr::new::<ImportError, _>(
+            py,
+            (
+                format!("unable to load shared library dependency: {}", e),
+                name,
+            ),
+        )
+    })?;
+
+    // The descriptor is intentionally never closed. See the `memory_so`
+    // module for why.
+    let fd = create_memory_file(name, library_data).map_err(|e| {
+        PyErr::new::<ImportError, _>(
+            py,
+            (
+                format!("unable to write extension module library to memory: {}", e),
+                name,
+            ),
+        )
+    })?;
+
+    let kwargs = PyDict::new(py);
+    kwargs.set_item(py, "origin", memory_file_path(fd))?;
+    let load_spec =
+        spec.get_type(py)
+            .call(py, (name_py, spec.getattr(py, "loader")?), Some(&kwargs))?;
+
+    let imp_module = py.import("_imp")?;
+
+    imp_module.call(py, "create_dynamic", (load_spec,), None)
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
 fn extension_module_shared_library_create_module(
     _resources_state: &PythonResourcesState<u8>,
     _py: Python,
@@ -115,7 +171,7 @@ fn extension_module_shared_library_create_module(
     _name: &str,
     _library_data: &[u8],
 ) -> PyResult<PyObject> {
-    panic!("should only be called on Windows");
+    panic!("should only be called on Windows and Linux");
 }
 
 /// Reimplementation of `_PyImport_LoadDynamicModuleWithSpec()`.
@@ -244,6 +300,86 @@ fn load_dynamic_library(
     }
 }
 
+/// Import activity recorded for a single module.
+struct ImportTraceEntry {
+    /// Name of the module.
+    name: String,
+    /// Where the module was found. See `ImportablePythonModule::location()`.
+    location: &'static str,
+    /// Time spent in `find_spec()`.
+    find_duration: Duration,
+    /// Time spent in `create_module()` and `exec_module()`.
+    ///
+    /// This includes time spent importing modules imported by this module.
+    /// `None` if the module was found but never loaded.
+    exec_duration: Option<Duration>,
+}
+
+/// Records import activity of an `OxidizedFinder`.
+#[derive(Default)]
+struct ImportTrace {
+    /// Whether import activity is currently being recorded.
+    enabled: bool,
+    /// Recorded entries, in the order modules were first found.
+    entries: Vec<ImportTraceEntry>,
+    /// Index into `entries` by module name.
+    indices: HashMap<String, usize>,
+}
+
+impl ImportTrace {
+    fn record_find(&mut self, name: &str, location: &'static str, duration: Duration) {
+        if !self.enabled {
+            return;
+        }
+
+        if let Some(index) = self.indices.get(name) {
+            let entry = &mut self.entries[*index];
+            entry.location = location;
+            entry.find_duration += duration;
+        } else {
+            self.indices.insert(name.to_string(), self.entries.len());
+            self.entries.push(ImportTraceEntry {
+                name: name.to_string(),
+                location,
+                find_duration: duration,
+                exec_duration: None,
+            });
+        }
+    }
+
+    fn record_exec(&mut self, name: &str, duration: Duration) {
+        if !self.enabled {
+            return;
+        }
+
+        if let Some(index) = self.indices.get(name) {
+            let entry = &mut self.entries[*index];
+            entry.exec_duration = Some(entry.exec_duration.unwrap_or_default() + duration);
+        }
+    }
+
+    /// Convert recorded entries to a Python list of dicts.
+    fn to_pyobject(&self, py: Python) -> PyResult<PyObject> {
+        let result = PyList::new(py, &[]);
+
+        for entry in &self.entries {
+            let d = PyDict::new(py);
+            d.set_item(py, "name", &entry.name)?;
+            d.set_item(py, "location", entry.location)?;
+            d.set_item(py, "find_seconds", entry.find_duration.as_secs_f64())?;
+            d.set_item(
+                py,
+                "exec_seconds",
+                entry.exec_duration.map(|d| d.as_secs_f64()),
+            )?;
+
+            result.append(py, d.into_object());
+        }
+
+        Ok(result.into_object())
+    }
+}
+
 /// Holds state for the custom MetaPathFinder.
 pub(crate) struct ImporterState {
     /// `imp` Python module.
@@ -264,6 +400,8 @@ pub(crate) struct ImporterState {
     module_spec_type: PyObject,
     /// Our `decode_source()` function.
     decode_source: PyObject,
+    /// Our `OxidizedTraversable` class.
+    traversable_type: PyObject,
     /// `builtins.exec` function.
     exec_fn: PyObject,
     /// Bytecode optimization level currently in effect.
@@ -293,6 +431,25 @@ pub(crate) struct ImporterState {
     /// We need to hold a reference to this instance because resources_state
     /// was constructed from a &[u8] backed by it.
     _resources_mmap: Option<Box<memmap::Mmap>>,
+
+    /// Python objects supplying resources data indexed after construction.
+    ///
+    /// Like `_resources_py_object`, these must live as long as resources_state.
+    indexed_py_objects: Mutex<Vec<PyObject>>,
+
+    /// Memory mapped files supplying resources data indexed after construction.
+    ///
+    /// Like `_resources_mmap`, these must live as long as resources_state.
+    indexed_mmaps: Mutex<Vec<memmap::Mmap>>,
+
+    /// Records of import activity, when import tracing is enabled.
+    import_trace: Mutex<ImportTrace>,
+
+    /// Name prefixes of modules whose execution is deferred until first use.
+    lazy_module_prefixes: Mutex<Vec<String>>,
+
+    /// Names of modules whose execution has been deferred but not yet performed.
+    lazy_pending_modules: Mutex<HashSet<String>>,
 }
 
 impl ImporterState {
@@ -300,12 +457,13 @@ impl ImporterState {
         py: Python,
         importer_module: &PyModule,
         bootstrap_module: &PyModule,
-        resources_state: &'a PythonResourcesState<'a, u8>,
+        resources_state: &PythonResourcesState<'a, u8>,
         resources_state_owned: bool,
         resources_py_object: Option<PyObject>,
         resources_mmap: Option<Box<memmap::Mmap>>,
     ) -> Result<Self, PyErr> {
         let decode_source = importer_module.get(py, "decode_source")?;
+        let traversable_type = importer_module.get(py, "_OxidizedTraversable")?;
 
         let io_module = py.import("_io")?;
         let marshal_module = py.import("marshal")?;
@@ -399,12 +557,18 @@ impl ImporterState {
             call_with_frames_removed,
             module_spec_type,
             decode_source,
+            traversable_type,
             exec_fn,
             optimize_level,
             resources_state: capsule,
             resources_state_owned,
             _resources_py_object: resources_py_object,
             _resources_mmap: resources_mmap,
+            indexed_py_objects: Mutex::new(vec![]),
+            indexed_mmaps: Mutex::new(vec![]),
+            import_trace: Mutex::new(ImportTrace::default()),
+            lazy_module_prefixes: Mutex::new(vec![]),
+            lazy_pending_modules: Mutex::new(HashSet::new()),
         })
     }
 
@@ -438,6 +602,39 @@ impl ImporterState {
 
         unsafe { &mut *(ptr as *mut PythonResourcesState<u8>) }
     }
+
+    /// Whether execution of the named module should be deferred until first use.
+    ///
+    /// Deferred modules are executed when `exec_module()` is called on them
+    /// again, which `importlib.util.LazyLoader` does on first attribute access.
+    fn defer_module_exec(&self, name: &str) -> bool {
+        let mut pending = self.lazy_pending_modules.lock().unwrap();
+
+        if pending.remove(name) {
+            return false;
+        }
+
+        // Deferring `importlib` would prevent us from obtaining `LazyLoader`.
+        if name == "importlib" || name.starts_with("importlib.") {
+            return false;
+        }
+
+        let matches = self
+            .lazy_module_prefixes
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|prefix| {
+                name == prefix
+                    || (name.starts_with(prefix.as_str()) && name[prefix.len()..].starts_with('.'))
+            });
+
+        if matches {
+            pending.insert(name.to_string());
+        }
+
+        matches
+    }
 }
 
 impl Drop for ImporterState {
@@ -564,8 +761,98 @@ py_class!(class OxidizedFinder |py| {
     def serialize_indexed_resources(&self, ignore_builtin: bool = true, ignore_frozen: bool = true) -> PyResult<PyObject> {
         self.serialize_indexed_resources_impl(py, ignore_builtin, ignore_frozen)
     }
+
+    def index_bytes(&self, data: PyObject) -> PyResult<PyObject> {
+        self.index_bytes_impl(py, data)
+    }
+
+    def index_file_memory_mapped(&self, path: PyObject) -> PyResult<PyObject> {
+        self.index_file_memory_mapped_impl(py, path)
+    }
+
+    def enable_import_tracing(&self) -> PyResult<PyObject> {
+        self.enable_import_tracing_impl(py)
+    }
+
+    def disable_import_tracing(&self) -> PyResult<PyObject> {
+        self.disable_import_tracing_impl(py)
+    }
+
+    def import_trace(&self) -> PyResult<PyObject> {
+        self.import_trace_impl(py)
+    }
+
+    def set_lazy_module_prefixes(&self, prefixes: Vec<String>) -> PyResult<PyObject> {
+        self.set_lazy_module_prefixes_impl(py, prefixes)
+    }
+
+    def path_hook(&self, path: PyObject) -> PyResult<PyObject> {
+        self.path_hook_impl(py, path)
+    }
 });
 
+// Path entry finder for `sys.path` entries recognized by `OxidizedFinder.path_hook()`.
+//
+// Implements importlib.abc.PathEntryFinder. Only modules that are direct
+// children of `package` are found. An empty `package` refers to top-level
+// modules. Loading is performed by the `OxidizedFinder`.
+py_class!(class OxidizedPathEntryFinder |py| {
+    data finder: OxidizedFinder;
+    data package: String;
+
+    def find_spec(&self, fullname: &PyString, target: Option<PyObject> = None) -> PyResult<PyObject> {
+        self.find_spec_impl(py, fullname, target)
+    }
+
+    def invalidate_caches(&self) -> PyResult<PyObject> {
+        Ok(py.None())
+    }
+
+    def iter_modules(&self, prefix: Option<PyString> = None) -> PyResult<PyObject> {
+        self.iter_modules_impl(py, prefix)
+    }
+});
+
+impl OxidizedPathEntryFinder {
+    fn find_spec_impl(
+        &self,
+        py: Python,
+        fullname: &PyString,
+        target: Option<PyObject>,
+    ) -> PyResult<PyObject> {
+        let name = fullname.to_string(py)?;
+
+        let parent = match name.rfind('.') {
+            Some(pos) => &name[0..pos],
+            None => "",
+        };
+
+        if parent != self.package(py) {
+            return Ok(py.None());
+        }
+
+        self.finder(py)
+            .find_spec_impl(py, fullname, &py.None(), target)
+    }
+
+    fn iter_modules_impl(&self, py: Python, prefix: Option<PyString>) -> PyResult<PyObject> {
+        let state = self.finder(py).state(py);
+
+        let prefix = if let Some(prefix) = prefix {
+            Some(prefix.to_string(py)?.to_string())
+        } else {
+            None
+        };
+
+        state.get_resources_state().pkgutil_modules_infos(
+            py,
+            Some(self.package(py)),
+            prefix,
+            state.optimize_level,
+        )
+    }
+}
+
 // importlib.abc.MetaPathFinder interface.
 impl OxidizedFinder {
     fn find_spec_impl(
@@ -578,15 +865,20 @@ impl OxidizedFinder {
         let state = self.state(py);
         let key = fullname.to_string(py)?;
 
+        let start = Instant::now();
+
         let module = match state
             .get_resources_state()
             .resolve_importable_module(&key, state.optimize_level)
         {
             Some(module) => module,
+            None if key == "pkg_resources" => {
+                return self.find_pkg_resources_spec(py, fullname, path, target)
+            }
             None => return Ok(py.None()),
         };
 
-        match module.flavor {
+        let spec = match module.flavor {
             ModuleFlavor::Extension | ModuleFlavor::SourceBytecode => module.resolve_module_spec(
                 py,
                 &state.module_spec_type,
@@ -605,7 +897,64 @@ impl OxidizedFinder {
                     .frozen_importer
                     .call_method(py, "find_spec", (fullname, path, target), None)
             }
+        };
+
+        state.import_trace.lock().unwrap().record_find(
+            &key,
+            module.location(state.optimize_level),
+            start.elapsed(),
+        );
+
+        spec
+    }
+
+    /// Find the spec of `pkg_resources` using the meta path finders after this one.
+    ///
+    /// `pkg_resources` has no mechanism to discover providers for custom
+    /// loaders. So the loader of the found spec is wrapped in order to
+    /// register ours once the module is loaded.
+    fn find_pkg_resources_spec(
+        &self,
+        py: Python,
+        fullname: &PyString,
+        path: &PyObject,
+        target: Option<PyObject>,
+    ) -> PyResult<PyObject> {
+        let state = self.state(py);
+        let meta_path = state.sys_module.as_object().getattr(py, "meta_path")?;
+        let target = target.unwrap_or_else(|| py.None());
+
+        let mut after_self = false;
+
+        for finder in meta_path.iter(py)? {
+            let finder = finder?;
+
+            if finder.as_ptr() == self.as_object().as_ptr() {
+                after_self = true;
+                continue;
+            }
+
+            if !after_self || !finder.hasattr(py, "find_spec")? {
+                continue;
+            }
+
+            let spec = finder.call_method(py, "find_spec", (fullname, path, &target), None)?;
+
+            if spec == py.None() {
+                continue;
+            }
+
+            let loader = spec.getattr(py, "loader")?;
+
+            if loader.hasattr(py, "exec_module")? {
+                let loader = OxidizedPkgResourcesLoader::create_instance(py, loader)?;
+                spec.setattr(py, "loader", loader)?;
+            }
+
+            return Ok(spec);
         }
+
+        Ok(py.None())
     }
 
     fn invalidate_caches_impl(&self, py: Python) -> PyResult<PyObject> {
@@ -637,9 +986,29 @@ impl OxidizedFinder {
         let name = spec.getattr(py, "name")?;
         let key = name.extract::<String>(py)?;
 
+        let start = Instant::now();
+        let result = self.create_module_resolved(py, spec, name, &key);
+        state
+            .import_trace
+            .lock()
+            .unwrap()
+            .record_exec(&key, start.elapsed());
+
+        result
+    }
+
+    fn create_module_resolved(
+        &self,
+        py: Python,
+        spec: &PyObject,
+        name: PyObject,
+        key: &str,
+    ) -> PyResult<PyObject> {
+        let state = self.state(py);
+
         let module = match state
             .get_resources_state()
-            .resolve_importable_module(&key, state.optimize_level)
+            .resolve_importable_module(key, state.optimize_level)
         {
             Some(module) => module,
             None => return Ok(py.None()),
@@ -655,7 +1024,16 @@ impl OxidizedFinder {
             // If we ever implement our own lazy module importer, we could
             // potentially work around this and move all extension module
             // initialization into `exec_module()`.
-            if let Some(library_data) = &module.in_memory_extension_module_shared_library() {
+            let library_data = module
+                .in_memory_extension_module_shared_library()
+                .map_err(|e| {
+                    PyErr::new::<ImportError, _>(
+                        py,
+                        (format!("error resolving extension module: {}", e), key),
+                    )
+                })?;
+
+            if let Some(library_data) = &library_data {
                 let sys_modules = state.sys_module.as_object().getattr(py, "modules")?;
 
                 extension_module_shared_library_create_module(
@@ -664,7 +1042,7 @@ impl OxidizedFinder {
                     sys_modules,
                     spec,
                     name,
-                    &key,
+                    key,
                     library_data,
                 )
             } else {
@@ -685,6 +1063,8 @@ impl OxidizedFinder {
         let name = module.getattr(py, "__name__")?;
         let key = name.extract::<String>(py)?;
 
+        let start = Instant::now();
+
         let mut entry = match state
             .get_resources_state()
             .resolve_importable_module(&key, state.optimize_level)
@@ -697,7 +1077,17 @@ impl OxidizedFinder {
             }
         };
 
-        if let Some(bytecode) = entry.resolve_bytecode(
+        let deferred =
+            entry.flavor == ModuleFlavor::SourceBytecode && state.defer_module_exec(&key);
+
+        let result = if deferred {
+            // `LazyLoader` replaces the module's class with one that calls
+            // back into our `exec_module()` on first attribute access.
+            let importlib_util = py.import("importlib.util")?;
+            let lazy_loader = importlib_util.call(py, "LazyLoader", (self.as_object(),), None)?;
+
+            lazy_loader.call_method(py, "exec_module", (module,), None)
+        } else if let Some(bytecode) = entry.resolve_bytecode(
             py,
             state.optimize_level,
             &state.decode_source,
@@ -726,7 +1116,21 @@ impl OxidizedFinder {
                 .call(py, (&exec_dynamic, module), None)
         } else {
             Ok(py.None())
+        };
+
+        state
+            .import_trace
+            .lock()
+            .unwrap()
+            .record_exec(&key, start.elapsed());
+
+        // `pkg_resources` has no mechanism to discover providers for custom
+        // loaders. So register ours as soon as it is loaded.
+        if result.is_ok() && !deferred && key == "pkg_resources" {
+            register_pkg_resources_provider(py, module)?;
         }
+
+        result
     }
 }
 
@@ -896,7 +1300,49 @@ impl OxidizedFinder {
             None
         };
 
-        resources_state.pkgutil_modules_infos(py, prefix, state.optimize_level)
+        resources_state.pkgutil_modules_infos(py, None, prefix, state.optimize_level)
+    }
+}
+
+// sys.path_hooks support.
+impl OxidizedFinder {
+    /// Obtain an `OxidizedPathEntryFinder` for a `sys.path` entry.
+    ///
+    /// The path of the current executable refers to top-level modules. Paths
+    /// beneath it refer to the package with the same name, mirroring the
+    /// `__path__` of in-memory packages. `ImportError` is raised for all other
+    /// paths, as required by the `sys.path_hooks` protocol.
+    fn path_hook_impl(&self, py: Python, path: PyObject) -> PyResult<PyObject> {
+        let state = self.state(py);
+        let resources_state = state.get_resources_state();
+
+        let not_ours = || PyErr::new::<ImportError, _>(py, "path not recognized by OxidizedFinder");
+
+        // `os.fsencode()` rejects values that aren't str, bytes or path-like.
+        let path = pyobject_to_pathbuf(py, path).map_err(|_| not_ours())?;
+
+        let relative = path
+            .strip_prefix(&resources_state.current_exe)
+            .map_err(|_| not_ours())?;
+
+        let package = relative
+            .components()
+            .map(|c| c.as_os_str().to_str())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(not_ours)?
+            .join(".");
+
+        if !package.is_empty() {
+            match resources_state.get_resource(&package) {
+                Some(resource) if resource.is_package => {}
+                _ => return Err(not_ours()),
+            }
+        }
+
+        Ok(
+            OxidizedPathEntryFinder::create_instance(py, self.clone_ref(py), package)?
+                .into_object(),
+        )
     }
 }
 
@@ -979,13 +1425,7 @@ fn oxidized_finder_new(
     } else if let Some(resources_file) = resources_file {
         let path = pyobject_to_pathbuf(py, resources_file)?;
 
-        let f = std::fs::File::open(&path).map_err(|e| {
-            PyErr::new::<IOError, _>(py, format!("unable to open resources file: {}", e))
-        })?;
-
-        let mapped = Box::new(unsafe { memmap::Mmap::map(&f) }.map_err(|e| {
-            PyErr::new::<IOError, _>(py, format!("unable to memory map resources file: {}", e))
-        })?);
+        let mapped = memory_map_resources_file(py, &path)?;
 
         // We "leak" a pointer to the memory mapped data and create a slice from it
         // so we don't have a reference to a borrowed value, which the borrow checker
@@ -1022,14 +1462,22 @@ fn oxidized_finder_new(
     Ok(importer)
 }
 
+/// Memory map a file containing packed resources data.
+fn memory_map_resources_file(py: Python, path: &Path) -> PyResult<Box<memmap::Mmap>> {
+    let f = std::fs::File::open(path).map_err(|e| {
+        PyErr::new::<IOError, _>(py, format!("unable to open resources file: {}", e))
+    })?;
+
+    Ok(Box::new(unsafe { memmap::Mmap::map(&f) }.map_err(|e| {
+        PyErr::new::<IOError, _>(py, format!("unable to memory map resources file: {}", e))
+    })?))
+}
+
 impl OxidizedFinder {
     fn indexed_resources_impl(&self, py: Python) -> PyResult<PyObject> {
         let resources_state: &PythonResourcesState<u8> = self.state(py).get_resources_state();
 
-        let mut resources = resources_state
-            .resources
-            .values()
-            .collect::<Vec<&python_packed_resources::data::Resource<u8>>>();
+        let mut resources = resources_state.all_resources();
 
         resources.sort_by_key(|r| &r.name);
 
@@ -1083,6 +1531,85 @@ impl OxidizedFinder {
 
         Ok(PyBytes::new(py, &data).into_object())
     }
+
+    /// Index resources from packed resources data in a bytes-like object.
+    fn index_bytes_impl(&self, py: Python, data: PyObject) -> PyResult<PyObject> {
+        let state = self.state(py);
+
+        let buffer = PyBuffer::get(py, &data)?;
+
+        let data_slice = unsafe {
+            std::slice::from_raw_parts::<u8>(buffer.buf_ptr() as *const _, buffer.len_bytes())
+        };
+
+        state
+            .get_resources_state_mut()
+            .load_resources(data_slice)
+            .map_err(|err| PyErr::new::<ValueError, _>(py, err))?;
+
+        // Resources reference the memory backing the object. So keep it alive.
+        state.indexed_py_objects.lock().unwrap().push(data);
+
+        Ok(py.None())
+    }
+
+    /// Index resources from a file containing packed resources data.
+    ///
+    /// The file is memory mapped and resources data is read on demand.
+    fn index_file_memory_mapped_impl(&self, py: Python, path: PyObject) -> PyResult<PyObject> {
+        let state = self.state(py);
+
+        let path = pyobject_to_pathbuf(py, path)?;
+
+        let mapped = memory_map_resources_file(py, &path)?;
+
+        // See the comment in oxidized_finder_new() for why this is safe.
+        let data = unsafe { std::slice::from_raw_parts::<u8>(mapped.as_ptr(), mapped.len()) };
+
+        state
+            .get_resources_state_mut()
+            .load_resources(data)
+            .map_err(|err| PyErr::new::<ValueError, _>(py, err))?;
+
+        state.indexed_mmaps.lock().unwrap().push(*mapped);
+
+        Ok(py.None())
+    }
+
+    /// Start recording timings of modules found and loaded by this instance.
+    ///
+    /// Previously recorded entries are retained.
+    fn enable_import_tracing_impl(&self, py: Python) -> PyResult<PyObject> {
+        self.state(py).import_trace.lock().unwrap().enabled = true;
+
+        Ok(py.None())
+    }
+
+    /// Stop recording import timings.
+    fn disable_import_tracing_impl(&self, py: Python) -> PyResult<PyObject> {
+        self.state(py).import_trace.lock().unwrap().enabled = false;
+
+        Ok(py.None())
+    }
+
+    /// Defer execution of modules having any of the given name prefixes.
+    ///
+    /// A prefix matches the module of that name and all modules beneath it.
+    /// Replaces previously set prefixes.
+    fn set_lazy_module_prefixes_impl(
+        &self,
+        py: Python,
+        prefixes: Vec<String>,
+    ) -> PyResult<PyObject> {
+        *self.state(py).lazy_module_prefixes.lock().unwrap() = prefixes;
+
+        Ok(py.None())
+    }
+
+    /// Obtain recorded import activity as a list of dicts.
+    fn import_trace_impl(&self, py: Python) -> PyResult<PyObject> {
+        self.state(py).import_trace.lock().unwrap().to_pyobject(py)
+    }
 }
 
 // Implements in-memory reading of resource data.
@@ -1107,6 +1634,10 @@ py_class!(class OxidizedResourceReader |py| {
     def contents(&self) -> PyResult<PyObject> {
         self.contents_impl(py)
     }
+
+    def files(&self) -> PyResult<PyObject> {
+        self.files_impl(py)
+    }
 });
 
 impl OxidizedResourceReader {
@@ -1130,13 +1661,27 @@ impl OxidizedResourceReader {
 
     /// Returns the file system path to the resource.
     ///
-    /// If the resource does not concretely exist on the file system, raise
-    /// FileNotFoundError.
-    fn resource_path_impl(&self, py: Python, _resource: &PyString) -> PyResult<PyObject> {
-        Err(PyErr::new::<FileNotFoundError, _>(
-            py,
-            "in-memory resources do not have filesystem paths",
-        ))
+    /// In-memory resources are written to the filesystem if a materialization
+    /// directory is configured. If the resource does not concretely exist on
+    /// the file system, raise FileNotFoundError.
+    fn resource_path_impl(&self, py: Python, resource: &PyString) -> PyResult<PyObject> {
+        let state = self.state(py);
+        let package = self.package(py);
+
+        match state
+            .get_resources_state()
+            .resolve_package_resource_path(package, &resource.to_string(py)?)
+        {
+            Ok(Some(path)) => path_to_pyobject(py, &path),
+            Ok(None) => Err(PyErr::new::<FileNotFoundError, _>(
+                py,
+                "resource does not have a filesystem path",
+            )),
+            Err(err) => Err(PyErr::new::<IOError, _>(
+                py,
+                format!("error materializing resource: {}", err),
+            )),
+        }
     }
 
     /// Returns True if the named name is considered a resource. FileNotFoundError
@@ -1173,15 +1718,36 @@ impl OxidizedResourceReader {
             .get_resources_state()
             .package_resource_names(py, &package)
     }
+
+    /// Returns an `importlib.abc.Traversable` for the package's resources.
+    fn files_impl(&self, py: Python) -> PyResult<PyObject> {
+        PyOxidizerTraversable::create_wrapped(
+            py,
+            self.state(py).clone(),
+            self.package(py).clone(),
+            "".to_string(),
+        )
+    }
 }
 
 // Path-like object facilitating Python resource access.
 //
-// This implements importlib.abc.Traversable.
+// This implements importlib.abc.Traversable over the resources of a package.
+// Resource names containing `/` are exposed as virtual subdirectories.
+// Instances are exposed to Python wrapped by `OxidizedTraversable`, which
+// provides the `/` operator.
 py_class!(class PyOxidizerTraversable |py| {
     data state: Arc<ImporterState>;
+    data package: String;
+    // Path relative to the package, using `/` as a separator. Empty for the
+    // package itself.
     data path: String;
 
+    // The base name of this object without any parent references.
+    @property def name(&self) -> PyResult<PyObject> {
+        self.name_impl(py)
+    }
+
     // Yield Traversable objects in self.
     def iterdir(&self) -> PyResult<PyObject> {
         self.iterdir_impl(py)
@@ -1193,8 +1759,8 @@ py_class!(class PyOxidizerTraversable |py| {
     }
 
     // Read contents of self as text.
-    def read_text(&self) -> PyResult<PyObject> {
-        self.read_text_impl(py)
+    def read_text(&self, encoding: Option<PyObject> = None) -> PyResult<PyObject> {
+        self.read_text_impl(py, encoding)
     }
 
     // Return True if self is a dir.
@@ -1208,13 +1774,12 @@ py_class!(class PyOxidizerTraversable |py| {
     }
 
     // Return Traversable child in self.
-    def joinpath(&self, child: &PyObject) -> PyResult<PyObject> {
-        self.joinpath_impl(py, child)
-    }
+    def joinpath(&self, *args, **kwargs) -> PyResult<PyObject> {
+        if kwargs.map(|kwargs| kwargs.len(py)).unwrap_or(0) > 0 {
+            return Err(PyErr::new::<TypeError, _>(py, "joinpath() takes no keyword arguments"));
+        }
 
-    /// Return Traversable child in self.
-    def __truediv__(&self, child: &PyObject) -> PyResult<PyObject> {
-        self.joinpath_impl(py, child)
+        self.joinpath_impl(py, args.as_slice(py))
     }
 
     // mode may be 'r' or 'rb' to open as text or binary. Return a handle
@@ -1225,57 +1790,534 @@ py_class!(class PyOxidizerTraversable |py| {
     def open(&self, *args, **kwargs) -> PyResult<PyObject> {
         self.open_impl(py, args, kwargs)
     }
+
+    def __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "<PyOxidizerTraversable package={:?} path={:?}>",
+            self.package(py),
+            self.path(py)
+        ))
+    }
 });
 
 impl PyOxidizerTraversable {
-    fn iterdir_impl(&self, _py: Python) -> PyResult<PyObject> {
-        unimplemented!();
+    /// Create an instance wrapped by `OxidizedTraversable`.
+    fn create_wrapped(
+        py: Python,
+        state: Arc<ImporterState>,
+        package: String,
+        path: String,
+    ) -> PyResult<PyObject> {
+        let traversable_type = state.traversable_type.clone_ref(py);
+        let traversable = PyOxidizerTraversable::create_instance(py, state, package, path)?;
+
+        traversable_type.call(py, (traversable,), None)
     }
 
-    fn read_bytes_impl(&self, _py: Python) -> PyResult<PyObject> {
-        unimplemented!();
+    /// Obtain the path of a child of this object.
+    fn child_path(&self, py: Python, name: &str) -> String {
+        let path = self.path(py);
+
+        if path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", path, name)
+        }
     }
 
-    fn read_text_impl(&self, _py: Python) -> PyResult<PyObject> {
-        unimplemented!();
+    /// Obtain the names of the immediate children of this object.
+    ///
+    /// Resources in nested directories contribute the name of the first
+    /// directory beneath this object.
+    fn child_names(&self, py: Python) -> Vec<String> {
+        let state = self.state(py);
+        let path = self.path(py);
+
+        let prefix = if path.is_empty() {
+            "".to_string()
+        } else {
+            format!("{}/", path)
+        };
+
+        let mut names = state
+            .get_resources_state()
+            .package_resource_names_all(self.package(py))
+            .into_iter()
+            .filter_map(|name| name.strip_prefix(&prefix))
+            .filter_map(|name| name.split('/').next())
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+
+        names.dedup();
+
+        names
     }
 
-    fn is_dir_impl(&self, py: Python) -> PyResult<PyObject> {
+    fn name_impl(&self, py: Python) -> PyResult<PyObject> {
+        let path = self.path(py);
+
+        let name = if path.is_empty() {
+            self.package(py).rsplit('.').next()
+        } else {
+            path.rsplit('/').next()
+        };
+
+        Ok(name.unwrap_or("").to_py_object(py).into_object())
+    }
+
+    fn iterdir_impl(&self, py: Python) -> PyResult<PyObject> {
+        if !self.is_directory(py) {
+            return Err(PyErr::new::<FileNotFoundError, _>(
+                py,
+                "resource directory not found",
+            ));
+        }
+
+        let children = self
+            .child_names(py)
+            .iter()
+            .map(|name| {
+                PyOxidizerTraversable::create_wrapped(
+                    py,
+                    self.state(py).clone(),
+                    self.package(py).clone(),
+                    self.child_path(py, name),
+                )
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let builtins = py.import("builtins")?;
+        builtins.call(py, "iter", (PyList::new(py, &children),), None)
+    }
+
+    /// Obtain a file-like object for binary reading of this resource.
+    fn open_binary(&self, py: Python) -> PyResult<PyObject> {
+        let state = self.state(py);
+
+        match state.get_resources_state().get_package_resource_file(
+            py,
+            self.package(py),
+            self.path(py),
+        )? {
+            Some(file) => Ok(file),
+            None => Err(PyErr::new::<FileNotFoundError, _>(py, "resource not found")),
+        }
+    }
+
+    fn read_bytes_impl(&self, py: Python) -> PyResult<PyObject> {
+        let file = self.open_binary(py)?;
+        let data = file.call_method(py, "read", NoArgs, None)?;
+        file.call_method(py, "close", NoArgs, None)?;
+
+        Ok(data)
+    }
+
+    fn read_text_impl(&self, py: Python, encoding: Option<PyObject>) -> PyResult<PyObject> {
+        let kwargs = PyDict::new(py);
+        if let Some(encoding) = encoding {
+            kwargs.set_item(py, "encoding", encoding)?;
+        }
+
+        let file = self.open_text(py, &PyTuple::empty(py), &kwargs)?;
+        file.call_method(py, "read", NoArgs, None)
+    }
+
+    /// Whether this object is a known directory.
+    ///
+    /// The package itself and every directory component of its resource
+    /// names are directories.
+    fn is_directory(&self, py: Python) -> bool {
         let state = self.state(py);
         let path = self.path(py);
 
-        // We are a directory if the current path is a known package.
-        // TODO We may need to expand this definition in the future to cover
-        // virtual subdirectories in addressable resources. But this will require
-        // changes to the resources data format to capture said annotations.
-        if let Some(entry) = state
+        if path.is_empty() {
+            return match state.get_resources_state().get_resource(self.package(py)) {
+                Some(entry) => entry.is_package,
+                None => false,
+            };
+        }
+
+        let prefix = format!("{}/", path);
+
+        state
             .get_resources_state()
-            .resolve_importable_module(&path, state.optimize_level)
-        {
-            if entry.is_package {
-                return Ok(py.True().into_object());
+            .package_resource_names_all(self.package(py))
+            .iter()
+            .any(|name| name.starts_with(&prefix))
+    }
+
+    fn is_dir_impl(&self, py: Python) -> PyResult<PyObject> {
+        Ok(self.is_directory(py).to_py_object(py).into_object())
+    }
+
+    fn is_file_impl(&self, py: Python) -> PyResult<PyObject> {
+        let path = self.path(py);
+
+        let is_file = !path.is_empty()
+            && self
+                .state(py)
+                .get_resources_state()
+                .is_package_resource(self.package(py), path);
+
+        Ok(is_file.to_py_object(py).into_object())
+    }
+
+    fn joinpath_impl(&self, py: Python, children: &[PyObject]) -> PyResult<PyObject> {
+        let mut path = self.path(py).clone();
+
+        for child in children {
+            let child = child.str(py)?.to_string(py)?.to_string();
+
+            // Resource names are normalized to POSIX separators.
+            for part in child.replace('\\', "/").split('/') {
+                match part {
+                    "" | "." => {}
+                    ".." => {
+                        return Err(PyErr::new::<ValueError, _>(
+                            py,
+                            "resource paths cannot reference parent directories",
+                        ));
+                    }
+                    part => {
+                        if !path.is_empty() {
+                            path.push('/');
+                        }
+                        path.push_str(part);
+                    }
+                }
             }
         }
 
+        PyOxidizerTraversable::create_wrapped(
+            py,
+            self.state(py).clone(),
+            self.package(py).clone(),
+            path,
+        )
+    }
+
+    /// Open this resource for text reading.
+    ///
+    /// Arguments are passed to `io.TextIOWrapper`.
+    fn open_text(&self, py: Python, args: &PyTuple, kwargs: &PyDict) -> PyResult<PyObject> {
+        let data = self.read_bytes_impl(py)?;
+
+        let io_module = py.import("io")?;
+        let bytes_io = io_module.call(py, "BytesIO", (data,), None)?;
+
+        let mut wrapper_args = vec![bytes_io];
+        wrapper_args.extend(args.iter(py).map(|arg| arg.clone_ref(py)));
+
+        io_module.call(
+            py,
+            "TextIOWrapper",
+            PyTuple::new(py, &wrapper_args),
+            Some(kwargs),
+        )
+    }
+
+    fn open_impl(&self, py: Python, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<PyObject> {
+        let kwargs = match kwargs {
+            Some(kwargs) => kwargs.copy(py)?,
+            None => PyDict::new(py),
+        };
+
+        let (mode, args) = if args.len(py) > 0 {
+            (
+                Some(args.get_item(py, 0)),
+                PyTuple::new(py, &args.as_slice(py)[1..]),
+            )
+        } else {
+            let mode = kwargs.get_item(py, "mode");
+            if mode.is_some() {
+                kwargs.del_item(py, "mode")?;
+            }
+
+            (mode, PyTuple::empty(py))
+        };
+
+        let mode = match mode {
+            Some(mode) => mode.extract::<String>(py)?,
+            None => "r".to_string(),
+        };
+
+        match mode.as_str() {
+            "r" => self.open_text(py, &args, &kwargs),
+            "rb" => {
+                if args.len(py) > 0 || kwargs.len(py) > 0 {
+                    Err(PyErr::new::<ValueError, _>(
+                        py,
+                        "binary mode doesn't take encoding arguments",
+                    ))
+                } else {
+                    self.open_binary(py)
+                }
+            }
+            _ => Err(PyErr::new::<ValueError, _>(
+                py,
+                format!("invalid mode: {}", mode),
+            )),
+        }
+    }
+}
+
+/// Python source defining the `OxidizedTraversable` class.
+///
+/// `py_class!` doesn't support `__truediv__` and its types can't be
+/// subclassed. So `PyOxidizerTraversable` instances are exposed to Python
+/// wrapped by this class, which implements `/` via `joinpath()`.
+const OXIDIZED_TRAVERSABLE_SOURCE: &str = r#"
+class OxidizedTraversable:
+    __slots__ = ("_traversable",)
+
+    def __init__(self, traversable):
+        self._traversable = traversable
+
+    def __getattr__(self, name):
+        return getattr(self._traversable, name)
+
+    def __truediv__(self, child):
+        return self._traversable.joinpath(child)
+
+    def __repr__(self):
+        return repr(self._traversable)
+"#;
+
+/// Create the `OxidizedTraversable` class.
+fn create_traversable_type(py: Python, m: &PyModule) -> PyResult<PyObject> {
+    let globals = PyDict::new(py);
+    globals.set_item(py, "__builtins__", py.import("builtins")?)?;
+    globals.set_item(py, "__name__", m.name(py)?)?;
+
+    py.run(OXIDIZED_TRAVERSABLE_SOURCE, Some(&globals), None)?;
+
+    globals
+        .get_item(py, "OxidizedTraversable")
+        .ok_or_else(|| PyErr::new::<ValueError, _>(py, "unable to define OxidizedTraversable"))
+}
+
+// Loader of `pkg_resources` when it is found by another meta path finder.
+//
+// This wraps the loader of that finder and registers
+// `OxidizedPkgResourcesProvider` once it has executed the module. The wrapped
+// loader is restored on the module and its spec beforehand, so the module
+// appears to be loaded by it.
+py_class!(class OxidizedPkgResourcesLoader |py| {
+    data loader: PyObject;
+
+    def create_module(&self, spec: PyObject) -> PyResult<PyObject> {
+        self.create_module_impl(py, spec)
+    }
+
+    def exec_module(&self, module: PyObject) -> PyResult<PyObject> {
+        self.exec_module_impl(py, module)
+    }
+});
+
+impl OxidizedPkgResourcesLoader {
+    fn create_module_impl(&self, py: Python, spec: PyObject) -> PyResult<PyObject> {
+        let loader = self.loader(py);
+
+        if loader.hasattr(py, "create_module")? {
+            loader.call_method(py, "create_module", (spec,), None)
+        } else {
+            Ok(py.None())
+        }
+    }
+
+    fn exec_module_impl(&self, py: Python, module: PyObject) -> PyResult<PyObject> {
+        let loader = self.loader(py);
+
+        module
+            .getattr(py, "__spec__")?
+            .setattr(py, "loader", loader)?;
+        module.setattr(py, "__loader__", loader)?;
+
+        loader.call_method(py, "exec_module", (&module,), None)?;
+
+        register_pkg_resources_provider(py, &module)?;
+
+        Ok(py.None())
+    }
+}
+
+// pkg_resources resource provider for modules loaded by `OxidizedFinder`.
+//
+// This implements `pkg_resources.IResourceProvider` and
+// `pkg_resources.IMetadataProvider` over the resources of a module's
+// package. Instances are constructed by `pkg_resources.get_provider()`
+// once the type is registered by `register_pkg_resources_provider()`.
+//
+// Like providers of `pkg_resources` for modules not belonging to an egg,
+// this doesn't expose distribution metadata: `has_metadata()` always
+// returns False and the other metadata methods return empty values.
+// Running scripts from metadata via `run_script()` is not supported and
+// raises NotImplementedError.
+py_class!(class OxidizedPkgResourcesProvider |py| {
+    data state: Arc<ImporterState>;
+    data package: String;
+
+    def __new__(_cls, module: PyObject) -> PyResult<OxidizedPkgResourcesProvider> {
+        oxidized_pkg_resources_provider_new(py, module)
+    }
+
+    def has_metadata(&self, name: &PyString) -> PyResult<PyObject> {
         Ok(py.False().into_object())
     }
 
-    fn is_file_impl(&self, _py: Python) -> PyResult<PyObject> {
-        unimplemented!();
+    def get_metadata(&self, name: &PyString) -> PyResult<PyObject> {
+        Ok(PyString::new(py, "").into_object())
+    }
+
+    def get_metadata_lines(&self, name: &PyString) -> PyResult<PyObject> {
+        Ok(PyList::new(py, &[]).into_object())
+    }
+
+    def metadata_isdir(&self, name: &PyString) -> PyResult<PyObject> {
+        Ok(py.False().into_object())
+    }
+
+    def metadata_listdir(&self, name: &PyString) -> PyResult<PyObject> {
+        Ok(PyList::new(py, &[]).into_object())
+    }
+
+    def run_script(&self, script_name: &PyString, namespace: PyObject) -> PyResult<PyObject> {
+        Err(PyErr::new::<NotImplementedError, _>(py, "run_script() is not supported"))
+    }
+
+    def get_resource_filename(&self, manager: PyObject, resource_name: &PyString) -> PyResult<PyObject> {
+        self.get_resource_filename_impl(py, resource_name)
+    }
+
+    def get_resource_stream(&self, manager: PyObject, resource_name: &PyString) -> PyResult<PyObject> {
+        self.resource(py, resource_name)?.open_binary(py)
+    }
+
+    def get_resource_string(&self, manager: PyObject, resource_name: &PyString) -> PyResult<PyObject> {
+        self.resource(py, resource_name)?.read_bytes_impl(py)
     }
 
-    fn joinpath_impl(&self, _py: Python, _child: &PyObject) -> PyResult<PyObject> {
-        unimplemented!();
+    def has_resource(&self, resource_name: &PyString) -> PyResult<PyObject> {
+        self.has_resource_impl(py, resource_name)
     }
 
-    fn open_impl(
+    def resource_isdir(&self, resource_name: &PyString) -> PyResult<PyObject> {
+        self.resource(py, resource_name)?.is_dir_impl(py)
+    }
+
+    def resource_listdir(&self, resource_name: &PyString) -> PyResult<PyObject> {
+        self.resource_listdir_impl(py, resource_name)
+    }
+});
+
+/// OxidizedPkgResourcesProvider.__new__(module)
+fn oxidized_pkg_resources_provider_new(
+    py: Python,
+    module: PyObject,
+) -> PyResult<OxidizedPkgResourcesProvider> {
+    let loader = module.getattr(py, "__loader__")?;
+    let finder = loader
+        .cast_as::<OxidizedFinder>(py)
+        .map_err(|_| PyErr::new::<TypeError, _>(py, "module was not loaded by OxidizedFinder"))?;
+
+    // Resources of a non-package module are those of its parent package.
+    let spec = module.getattr(py, "__spec__")?;
+    let package = spec.getattr(py, "parent")?.extract::<String>(py)?;
+
+    OxidizedPkgResourcesProvider::create_instance(py, finder.state(py).clone(), package)
+}
+
+impl OxidizedPkgResourcesProvider {
+    /// Obtain a `PyOxidizerTraversable` for a `/` delimited resource name.
+    fn resource(&self, py: Python, resource_name: &PyString) -> PyResult<PyOxidizerTraversable> {
+        let resource_name = resource_name.to_string(py)?;
+
+        let path = resource_name
+            .split('/')
+            .filter(|part| !part.is_empty() && *part != ".")
+            .collect::<Vec<_>>()
+            .join("/");
+
+        PyOxidizerTraversable::create_instance(
+            py,
+            self.state(py).clone(),
+            self.package(py).clone(),
+            path,
+        )
+    }
+
+    /// Obtain a filesystem path to a resource.
+    ///
+    /// In-memory resources are only available if a materialization directory
+    /// is configured. Otherwise FileNotFoundError is raised.
+    fn get_resource_filename_impl(
         &self,
-        _py: Python,
-        _args: &PyTuple,
-        _kwargs: Option<&PyDict>,
+        py: Python,
+        resource_name: &PyString,
     ) -> PyResult<PyObject> {
-        unimplemented!();
+        let resource = self.resource(py, resource_name)?;
+
+        match self
+            .state(py)
+            .get_resources_state()
+            .resolve_package_resource_path(self.package(py), resource.path(py))
+        {
+            Ok(Some(path)) => path_to_pyobject(py, &path),
+            Ok(None) => Err(PyErr::new::<FileNotFoundError, _>(
+                py,
+                "resource does not have a filesystem path",
+            )),
+            Err(err) => Err(PyErr::new::<IOError, _>(
+                py,
+                format!("error materializing resource: {}", err),
+            )),
+        }
+    }
+
+    fn has_resource_impl(&self, py: Python, resource_name: &PyString) -> PyResult<PyObject> {
+        let resource = self.resource(py, resource_name)?;
+
+        let exists = resource.is_directory(py)
+            || self
+                .state(py)
+                .get_resources_state()
+                .is_package_resource(self.package(py), resource.path(py));
+
+        Ok(exists.to_py_object(py).into_object())
     }
+
+    /// Obtain the names of entries in a resource directory.
+    ///
+    /// Like `os.listdir()`, names are not prefixed by the directory.
+    fn resource_listdir_impl(&self, py: Python, resource_name: &PyString) -> PyResult<PyObject> {
+        let resource = self.resource(py, resource_name)?;
+
+        let names = resource
+            .child_names(py)
+            .iter()
+            .map(|name| name.to_py_object(py).into_object())
+            .collect::<Vec<_>>();
+
+        Ok(PyList::new(py, &names).into_object())
+    }
+}
+
+/// Register `OxidizedPkgResourcesProvider` as the `pkg_resources` provider for `OxidizedFinder`.
+///
+/// `pkg_resources` is the already imported `pkg_resources` module.
+fn register_pkg_resources_provider(py: Python, pkg_resources: &PyObject) -> PyResult<()> {
+    pkg_resources.call_method(
+        py,
+        "register_loader_type",
+        (
+            py.get_type::<OxidizedFinder>(),
+            py.get_type::<OxidizedPkgResourcesProvider>(),
+        ),
+        None,
+    )?;
+
+    Ok(())
 }
 
 const DOC: &[u8] = b"A highly-performant importer implemented in Rust\0";
@@ -1427,6 +2469,10 @@ fn module_init(py: Python, m: &PyModule) -> PyResult<()> {
         "find_resources_in_path",
         py_fn!(py, find_resources_in_path(path: PyObject)),
     )?;
+    #[cfg(not(library_mode = "extension"))]
+    m.add(py, "raw_allocator_stats", py_fn!(py, raw_allocator_stats()))?;
+
+    m.add(py, "_OxidizedTraversable", create_traversable_type(py, m)?)?;
 
     m.add(py, "OxidizedFinder", py.get_type::<OxidizedFinder>())?;
     m.add(py, "OxidizedResource", py.get_type::<OxidizedResource>())?;
@@ -1440,6 +2486,16 @@ fn module_init(py: Python, m: &PyModule) -> PyResult<()> {
         "OxidizedResourceReader",
         py.get_type::<OxidizedResourceReader>(),
     )?;
+    m.add(
+        py,
+        "OxidizedPathEntryFinder",
+        py.get_type::<OxidizedPathEntryFinder>(),
+    )?;
+    m.add(
+        py,
+        "OxidizedPkgResourcesProvider",
+        py.get_type::<OxidizedPkgResourcesProvider>(),
+    )?;
     m.add(
         py,
         "PythonModuleSource",
@@ -1469,16 +2525,47 @@ fn module_init(py: Python, m: &PyModule) -> PyResult<()> {
     Ok(())
 }
 
+/// Obtain import trace records of all `OxidizedFinder` on `sys.meta_path`.
+///
+/// Returns a list of dicts, as returned by `OxidizedFinder.import_trace()`.
+#[cfg(not(library_mode = "extension"))]
+pub(crate) fn meta_path_import_trace(py: Python) -> PyResult<PyObject> {
+    let sys_module = py.import("sys")?;
+    let meta_path = sys_module.get(py, "meta_path")?;
+    let meta_path = meta_path.cast_as::<PyList>(py)?;
+
+    let records = PyList::new(py, &[]);
+
+    for finder in meta_path.iter(py) {
+        if let Ok(finder) = finder.cast_as::<OxidizedFinder>(py) {
+            let trace = finder.import_trace_impl(py)?;
+            records
+                .as_object()
+                .call_method(py, "extend", (trace,), None)?;
+        }
+    }
+
+    Ok(records.into_object())
+}
+
 /// Initialize the module/importer.
 ///
 /// This is called after PyInit_* to finish the initialization of the
 /// module. Its state struct is updated. A new instance of the meta path
-/// importer is constructed and registered on sys.meta_path.
+/// importer is constructed and registered on sys.meta_path. If `trace_imports`
+/// is true, import tracing is enabled on that importer. Execution of modules
+/// matching `lazy_module_prefixes` is deferred until first use.
+///
+/// A `pkg_resources` resource provider for the importer is registered once
+/// `pkg_resources` is imported, or immediately if it already has been. The
+/// importer's `path_hook()` is registered on `sys.path_hooks`.
 #[cfg(not(library_mode = "extension"))]
 pub(crate) fn initialize_importer<'a>(
     py: Python,
     m: &PyModule,
     resources_state: &PythonResourcesState<'a, u8>,
+    trace_imports: bool,
+    lazy_module_prefixes: &[String],
 ) -> PyResult<()> {
     let mut state = get_module_state(py, m)?;
 
@@ -1490,11 +2577,26 @@ pub(crate) fn initialize_importer<'a>(
     // during startup and the 2 default meta path importers are installed.
     let unified_importer = OxidizedFinder::new_from_module_and_resources(py, m, resources_state)?;
 
+    if trace_imports {
+        unified_importer.enable_import_tracing_impl(py)?;
+    }
+
+    unified_importer.set_lazy_module_prefixes_impl(py, lazy_module_prefixes.to_vec())?;
+
     let meta_path_object = sys_module.get(py, "meta_path")?;
 
     meta_path_object.call_method(py, "clear", NoArgs, None)?;
     meta_path_object.call_method(py, "append", (unified_importer.clone_ref(py),), None)?;
 
+    let path_hook = unified_importer.as_object().getattr(py, "path_hook")?;
+    let path_hooks = sys_module.get(py, "path_hooks")?;
+    path_hooks.call_method(py, "insert", (0, path_hook), None)?;
+
+    let modules = sys_module.get(py, "modules")?;
+    if let Some(pkg_resources) = modules.cast_as::<PyDict>(py)?.get_item(py, "pkg_resources") {
+        register_pkg_resources_provider(py, &pkg_resources)?;
+    }
+
     state.initialized = true;
 
     Ok(())
@@ -5,30 +5,35 @@
 //! Manage an embedded Python interpreter.
 
 use {
-    super::config::{OxidizedPythonInterpreterConfig, ResolvedOxidizedPythonInterpreterConfig},
-    super::conversion::osstring_to_bytes,
+    super::config::{
+        OxidizedPythonInterpreterConfig, PackedResourcesSource,
+        ResolvedOxidizedPythonInterpreterConfig,
+    },
+    super::conversion::{osstring_to_bytes, path_to_pyobject},
     super::importer::{
-        initialize_importer, PyInit_oxidized_importer, OXIDIZED_IMPORTER_NAME,
-        OXIDIZED_IMPORTER_NAME_STR,
+        initialize_importer, meta_path_import_trace, PyInit_oxidized_importer,
+        OXIDIZED_IMPORTER_NAME, OXIDIZED_IMPORTER_NAME_STR,
     },
     super::interpreter_config::python_interpreter_config_to_py_pre_config,
     super::osutils::resolve_terminfo_dirs,
     super::pyalloc::{make_raw_rust_memory_allocator, RawAllocator},
+    super::python_eval::{self, EvalError},
     super::python_resources::PythonResourcesState,
     cpython::{
-        GILGuard, NoArgs, ObjectProtocol, PyDict, PyErr, PyList, PyString, Python, ToPyObject,
+        GILGuard, NoArgs, ObjectProtocol, PyDict, PyErr, PyList, PyObject, PyResult, PyString,
+        Python, ToPyObject,
     },
     lazy_static::lazy_static,
     python3_sys as pyffi,
     python_packaging::interpreter::{MemoryAllocatorBackend, TerminfoResolution},
-    std::collections::BTreeSet,
+    std::collections::{BTreeMap, BTreeSet},
     std::convert::TryInto,
     std::env,
     std::ffi::{CStr, OsString},
     std::fmt::{Display, Formatter},
     std::fs,
     std::io::Write,
-    std::path::PathBuf,
+    std::path::{Path, PathBuf},
 };
 
 #[cfg(target_family = "unix")]
@@ -42,6 +47,10 @@ use std::os::windows::ffi::OsStrExt;
 
 #[cfg(feature = "jemalloc-sys")]
 use super::pyalloc::make_raw_jemalloc_allocator;
+#[cfg(feature = "libmimalloc-sys")]
+use super::pyalloc::make_raw_mimalloc_allocator;
+#[cfg(feature = "snmalloc-sys")]
+use super::pyalloc::make_raw_snmalloc_allocator;
 use python3_sys::PyMemAllocatorEx;
 
 lazy_static! {
@@ -58,6 +67,26 @@ fn raw_jemallocator() -> pyffi::PyMemAllocatorEx {
     panic!("jemalloc is not available in this build configuration");
 }
 
+#[cfg(feature = "libmimalloc-sys")]
+fn raw_mimallocator() -> pyffi::PyMemAllocatorEx {
+    make_raw_mimalloc_allocator()
+}
+
+#[cfg(not(feature = "libmimalloc-sys"))]
+fn raw_mimallocator() -> pyffi::PyMemAllocatorEx {
+    panic!("mimalloc is not available in this build configuration");
+}
+
+#[cfg(feature = "snmalloc-sys")]
+fn raw_snmallocator() -> pyffi::PyMemAllocatorEx {
+    make_raw_snmalloc_allocator()
+}
+
+#[cfg(not(feature = "snmalloc-sys"))]
+fn raw_snmallocator() -> pyffi::PyMemAllocatorEx {
+    panic!("snmalloc is not available in this build configuration");
+}
+
 /// Format a PyErr in a crude manner.
 ///
 /// This is meant to be called during interpreter initialization. We can't
@@ -272,6 +301,20 @@ pub struct MainPythonInterpreter<'python, 'interpreter: 'python, 'resources: 'in
     /// in this field. We also store the object in a box so it is on the
     /// heap and not dynamic.
     resources_state: Option<Box<PythonResourcesState<'resources, u8>>>,
+    /// Holds memory mapped files that packed resources data came from.
+    ///
+    /// `resources_state` holds slices backed by these mappings, so this
+    /// field must be declared after it in order to be dropped after it.
+    packed_resources_mmaps: Vec<memmap::Mmap>,
+    /// Holds packed resources data read from files.
+    ///
+    /// Like `packed_resources_mmaps`, this must be dropped after
+    /// `resources_state`.
+    packed_resources_buffers: Vec<Vec<u8>>,
+    /// Thread states of live sub-interpreters, keyed by their ID.
+    sub_interpreters: BTreeMap<usize, *mut pyffi::PyThreadState>,
+    /// ID to assign to the next sub-interpreter.
+    next_sub_interpreter_id: usize,
 }
 
 impl<'python, 'interpreter, 'resources> MainPythonInterpreter<'python, 'interpreter, 'resources> {
@@ -279,8 +322,12 @@ impl<'python, 'interpreter, 'resources> MainPythonInterpreter<'python, 'interpre
     ///
     /// The Python interpreter is initialized as a side-effect. The GIL is held.
     pub fn new(
-        config: OxidizedPythonInterpreterConfig<'resources>,
+        mut config: OxidizedPythonInterpreterConfig<'resources>,
     ) -> Result<MainPythonInterpreter<'python, 'interpreter, 'resources>, NewInterpreterError> {
+        config
+            .apply_config_overrides()
+            .map_err(NewInterpreterError::Dynamic)?;
+
         let config: ResolvedOxidizedPythonInterpreterConfig<'resources> = config
             .try_into()
             .map_err(|e| NewInterpreterError::Simple(e))?;
@@ -305,6 +352,10 @@ impl<'python, 'interpreter, 'resources> MainPythonInterpreter<'python, 'interpre
             gil: None,
             py: None,
             resources_state: None,
+            packed_resources_mmaps: vec![],
+            packed_resources_buffers: vec![],
+            sub_interpreters: BTreeMap::new(),
+            next_sub_interpreter_id: 0,
         };
 
         res.init()?;
@@ -312,6 +363,72 @@ impl<'python, 'interpreter, 'resources> MainPythonInterpreter<'python, 'interpre
         Ok(res)
     }
 
+    /// Resolve configured packed resources sources to raw data.
+    ///
+    /// Files are memory mapped and the mappings are retained on this instance
+    /// so the returned slices remain valid for the lifetime of the interpreter.
+    ///
+    /// If signatures are verified, files are read into memory instead. A
+    /// mapped file could be modified after its signature is verified.
+    fn resolve_packed_resources(&mut self) -> Result<Vec<&'resources [u8]>, NewInterpreterError> {
+        let mut res = vec![];
+
+        for source in &self.config.packed_resources {
+            match source {
+                PackedResourcesSource::Memory(data) => res.push(*data),
+                PackedResourcesSource::MemoryMappedPath(path)
+                    if self.config.packed_resources_public_key.is_some() =>
+                {
+                    let buffer = fs::read(path).map_err(|e| {
+                        NewInterpreterError::Dynamic(format!(
+                            "unable to read packed resources file {}: {}",
+                            path.display(),
+                            e
+                        ))
+                    })?;
+
+                    // As with mappings, the heap memory doesn't move when the Vec
+                    // does and we hold on to the Vec for the lifetime of this
+                    // instance.
+                    let data =
+                        unsafe { std::slice::from_raw_parts::<u8>(buffer.as_ptr(), buffer.len()) };
+
+                    self.packed_resources_buffers.push(buffer);
+                    res.push(data);
+                }
+                PackedResourcesSource::MemoryMappedPath(path) => {
+                    let f = fs::File::open(path).map_err(|e| {
+                        NewInterpreterError::Dynamic(format!(
+                            "unable to open packed resources file {}: {}",
+                            path.display(),
+                            e
+                        ))
+                    })?;
+
+                    let mapped = unsafe { memmap::Mmap::map(&f) }.map_err(|e| {
+                        NewInterpreterError::Dynamic(format!(
+                            "unable to memory map packed resources file {}: {}",
+                            path.display(),
+                            e
+                        ))
+                    })?;
+
+                    // We create a slice with an unbound lifetime from the mapping so
+                    // it can be stored in the resources state. The mapped memory
+                    // doesn't move when the Mmap does and we hold on to the Mmap
+                    // for the lifetime of this instance.
+                    let data =
+                        unsafe { std::slice::from_raw_parts::<u8>(mapped.as_ptr(), mapped.len()) };
+
+                    self.packed_resources_mmaps.push(mapped);
+                    res.push(data);
+                }
+            }
+        }
+
+        Ok(res)
+    }
+
     /// Initialize the interpreter.
     ///
     /// This mutates global state in the Python interpreter according to the
@@ -351,6 +468,42 @@ impl<'python, 'interpreter, 'resources> MainPythonInterpreter<'python, 'interpre
             std::env::set_var("TCL_LIBRARY", tcl_library);
         }
 
+        let packed_resources = self.resolve_packed_resources()?;
+
+        // Only the verified data is used from here on.
+        #[cfg(feature = "signatures")]
+        let packed_resources = match &self.config.packed_resources_public_key {
+            Some(public_key) => packed_resources
+                .into_iter()
+                .map(|data| python_packed_resources::parser::verify_signature(data, public_key))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(NewInterpreterError::Simple)?,
+            None => packed_resources,
+        };
+
+        #[cfg(not(feature = "signatures"))]
+        if self.config.packed_resources_public_key.is_some() {
+            return Err(NewInterpreterError::Simple(
+                "verifying packed resources signatures requires the signatures feature",
+            ));
+        }
+
+        #[cfg(feature = "digests")]
+        if self.config.verify_packed_resources_digests {
+            for data in &packed_resources {
+                python_packed_resources::parser::load_resources(data)
+                    .and_then(|resources| resources.verify_digests(true))
+                    .map_err(NewInterpreterError::Simple)?;
+            }
+        }
+
+        #[cfg(not(feature = "digests"))]
+        if self.config.verify_packed_resources_digests {
+            return Err(NewInterpreterError::Simple(
+                "verifying packed resources digests requires the digests feature",
+            ));
+        }
+
         set_pyimport_inittab(&self.config);
 
         // Pre-configure Python.
@@ -376,6 +529,12 @@ impl<'python, 'interpreter, 'resources> MainPythonInterpreter<'python, 'interpre
                 MemoryAllocatorBackend::Jemalloc => {
                     self.raw_allocator = Some(InterpreterRawAllocator::from(raw_jemallocator()));
                 }
+                MemoryAllocatorBackend::Mimalloc => {
+                    self.raw_allocator = Some(InterpreterRawAllocator::from(raw_mimallocator()));
+                }
+                MemoryAllocatorBackend::Snmalloc => {
+                    self.raw_allocator = Some(InterpreterRawAllocator::from(raw_snmallocator()));
+                }
                 MemoryAllocatorBackend::Rust => {
                     self.raw_allocator = Some(InterpreterRawAllocator::from(
                         make_raw_rust_memory_allocator(),
@@ -434,8 +593,11 @@ impl<'python, 'interpreter, 'resources> MainPythonInterpreter<'python, 'interpre
             ));
 
             if let Some(ref mut resources_state) = self.resources_state {
+                resources_state.materialization_directory =
+                    self.config.resources_materialization_directory.clone();
+
                 resources_state
-                    .load(&self.config.packed_resources)
+                    .load(&packed_resources)
                     .map_err(|err| NewInterpreterError::Simple(err))?;
 
                 let oxidized_importer = py.import(OXIDIZED_IMPORTER_NAME_STR).map_err(|err| {
@@ -446,7 +608,14 @@ impl<'python, 'interpreter, 'resources> MainPythonInterpreter<'python, 'interpre
                     )
                 })?;
 
-                initialize_importer(py, &oxidized_importer, resources_state).map_err(|err| {
+                initialize_importer(
+                    py,
+                    &oxidized_importer,
+                    resources_state,
+                    self.config.trace_imports(),
+                    self.config.lazy_module_prefixes.as_deref().unwrap_or(&[]),
+                )
+                .map_err(|err| {
                     NewInterpreterError::new_from_pyerr(
                         py,
                         err,
@@ -492,6 +661,25 @@ impl<'python, 'interpreter, 'resources> MainPythonInterpreter<'python, 'interpre
                 })?;
         }
 
+        // Expose embedded resources to tools walking `sys.path`, such as
+        // `pkgutil.iter_modules()`. The path hook registered by
+        // `initialize_importer()` services this entry. This is done after main
+        // initialization because that replaces `sys.path`.
+        if let Some(resources_state) = &self.resources_state {
+            let sys_module = py.import("sys").map_err(|err| {
+                NewInterpreterError::new_from_pyerr(py, err, "obtaining sys module")
+            })?;
+            let sys_path = sys_module.get(py, "path").map_err(|err| {
+                NewInterpreterError::new_from_pyerr(py, err, "obtaining sys.path")
+            })?;
+            let exe = path_to_pyobject(py, &resources_state.current_exe).map_err(|err| {
+                NewInterpreterError::new_from_pyerr(py, err, "converting current executable")
+            })?;
+            sys_path
+                .call_method(py, "append", (exe,), None)
+                .map_err(|err| NewInterpreterError::new_from_pyerr(py, err, "sys.path.append()"))?;
+        }
+
         /* Pre-initialization functions we could support:
          *
          * PyObject_SetArenaAllocator()
@@ -610,9 +798,14 @@ impl<'python, 'interpreter, 'resources> MainPythonInterpreter<'python, 'interpre
     /// the interpreter and only gives you an exit code: there is no opportunity
     /// to inspect the return value or handle an uncaught exception. If you want
     /// to keep the interpreter alive or inspect the evaluation result, consider
-    /// calling a function in the `python_eval` module.
+    /// calling `run_module()`, `run_file()`, or `run_code()` or a function in
+    /// the `python_eval` module.
     pub fn run_as_main(&mut self) -> i32 {
         if self.config.uses_py_runmain() {
+            // Py_RunMain() finalizes the interpreter, which aborts the process
+            // if sub-interpreters remain.
+            self.end_sub_interpreters();
+
             let res = unsafe { pyffi::Py_RunMain() };
 
             // Py_RunMain() finalizes the interpreter. So drop our refs and state.
@@ -627,6 +820,203 @@ impl<'python, 'interpreter, 'resources> MainPythonInterpreter<'python, 'interpre
             0
         }
     }
+
+    /// Run a Python module as the `__main__` module.
+    ///
+    /// This is equivalent to `python -m <name>`. The interpreter stays alive
+    /// afterwards. Returns the globals of the executed module.
+    pub fn run_module(&mut self, name: &str) -> Result<PyObject, EvalError> {
+        let py = self.acquire_gil().map_err(EvalError::Interpreter)?;
+
+        python_eval::run_module_as_main(py, name).map_err(|err| EvalError::new_from_pyerr(py, err))
+    }
+
+    /// Run a Python file as the `__main__` module.
+    ///
+    /// This is equivalent to `python <path>`. The interpreter stays alive
+    /// afterwards. Returns the globals of the executed module.
+    pub fn run_file(&mut self, path: &Path) -> Result<PyObject, EvalError> {
+        let py = self.acquire_gil().map_err(EvalError::Interpreter)?;
+
+        python_eval::run_file(py, path).map_err(|err| EvalError::new_from_pyerr(py, err))
+    }
+
+    /// Run Python code in the context of the `__main__` module.
+    ///
+    /// If `code` is a single expression, its value is returned. Otherwise
+    /// `None` is returned. The interpreter stays alive afterwards.
+    pub fn run_code(&mut self, code: &str) -> Result<PyObject, EvalError> {
+        let py = self.acquire_gil().map_err(EvalError::Interpreter)?;
+
+        python_eval::run_code(py, code).map_err(|err| EvalError::new_from_pyerr(py, err))
+    }
+
+    /// Create a new sub-interpreter via `Py_NewInterpreter()`.
+    ///
+    /// Sub-interpreters have their own modules, including `sys`, and run
+    /// largely isolated from the main interpreter. If the main interpreter
+    /// uses the oxidized importer, the sub-interpreter gets its own
+    /// `OxidizedFinder` backed by the same resources data.
+    ///
+    /// Returns an ID to use with `with_sub_interpreter()` and
+    /// `end_sub_interpreter()`. Sub-interpreters still alive when this
+    /// instance is finalized are ended automatically.
+    pub fn new_sub_interpreter(&mut self) -> Result<SubInterpreterId, NewInterpreterError> {
+        self.acquire_gil()?;
+
+        let previous = unsafe { pyffi::PyThreadState_Get() };
+        let thread_state = unsafe { pyffi::Py_NewInterpreter() };
+
+        if thread_state.is_null() {
+            unsafe { pyffi::PyThreadState_Swap(previous) };
+            return Err(NewInterpreterError::Simple(
+                "unable to create sub-interpreter",
+            ));
+        }
+
+        // The new interpreter's thread state is now the current one.
+        let py = unsafe { Python::assume_gil_acquired() };
+
+        if let Err(err) = self.initialize_sub_interpreter(py) {
+            let err = NewInterpreterError::new_from_pyerr(py, err, "initializing sub-interpreter");
+
+            unsafe {
+                pyffi::Py_EndInterpreter(thread_state);
+                pyffi::PyThreadState_Swap(previous);
+            }
+
+            return Err(err);
+        }
+
+        unsafe { pyffi::PyThreadState_Swap(previous) };
+
+        let id = self.next_sub_interpreter_id;
+        self.next_sub_interpreter_id += 1;
+        self.sub_interpreters.insert(id, thread_state);
+
+        Ok(SubInterpreterId(id))
+    }
+
+    /// Install the oxidized importer in the current sub-interpreter.
+    fn initialize_sub_interpreter(&self, py: Python) -> PyResult<()> {
+        let resources_state = match &self.resources_state {
+            Some(resources_state) => resources_state,
+            None => return Ok(()),
+        };
+
+        let sys_module = py.import("sys")?;
+        let oxidized_importer = py.import(OXIDIZED_IMPORTER_NAME_STR)?;
+
+        initialize_importer(
+            py,
+            &oxidized_importer,
+            resources_state,
+            self.config.trace_imports(),
+            self.config.lazy_module_prefixes.as_deref().unwrap_or(&[]),
+        )?;
+
+        // Sub-interpreters are fully initialized before we get a chance to
+        // install our importer. So the filesystem importer registered during
+        // initialization was removed above and needs to be restored.
+        if self.config.filesystem_importer {
+            let path_finder = py
+                .import("_frozen_importlib_external")?
+                .get(py, "PathFinder")?;
+
+            sys_module
+                .get(py, "meta_path")?
+                .call_method(py, "append", (path_finder,), None)?;
+        }
+
+        sys_module.get(py, "path")?.call_method(
+            py,
+            "append",
+            (path_to_pyobject(py, &resources_state.current_exe)?,),
+            None,
+        )?;
+
+        Ok(())
+    }
+
+    /// Run a function in the context of a sub-interpreter.
+    ///
+    /// The sub-interpreter's thread state is current while `f` runs and the
+    /// `Python` passed to `f` refers to the sub-interpreter. Functions in the
+    /// `python_eval` module can be used to run code. Python objects belong to
+    /// the interpreter that created them, so `f` should convert results to Rust
+    /// values instead of returning Python objects.
+    pub fn with_sub_interpreter<T, F>(
+        &mut self,
+        id: SubInterpreterId,
+        f: F,
+    ) -> Result<T, &'static str>
+    where
+        F: FnOnce(Python) -> T,
+    {
+        let py = self.acquire_gil()?;
+
+        let thread_state = *self
+            .sub_interpreters
+            .get(&id.0)
+            .ok_or("unknown sub-interpreter")?;
+
+        let _swap = ThreadStateSwap::new(thread_state);
+
+        Ok(f(py))
+    }
+
+    /// End a sub-interpreter created by `new_sub_interpreter()`.
+    ///
+    /// All state associated with the sub-interpreter is destroyed.
+    pub fn end_sub_interpreter(&mut self, id: SubInterpreterId) -> Result<(), &'static str> {
+        self.acquire_gil()?;
+
+        let thread_state = self
+            .sub_interpreters
+            .remove(&id.0)
+            .ok_or("unknown sub-interpreter")?;
+
+        unsafe {
+            let previous = pyffi::PyThreadState_Swap(thread_state);
+            pyffi::Py_EndInterpreter(thread_state);
+            pyffi::PyThreadState_Swap(previous);
+        }
+
+        Ok(())
+    }
+
+    /// End all live sub-interpreters.
+    fn end_sub_interpreters(&mut self) {
+        let ids = self.sub_interpreters.keys().cloned().collect::<Vec<_>>();
+
+        for id in ids {
+            if let Err(msg) = self.end_sub_interpreter(SubInterpreterId(id)) {
+                eprintln!("error ending sub-interpreter: {}", msg);
+            }
+        }
+    }
+}
+
+/// Identifies a sub-interpreter of a `MainPythonInterpreter`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubInterpreterId(usize);
+
+/// Makes a thread state current until dropped.
+///
+/// The previously current thread state is restored on drop, even if the
+/// code using the thread state panics.
+struct ThreadStateSwap(*mut pyffi::PyThreadState);
+
+impl ThreadStateSwap {
+    fn new(thread_state: *mut pyffi::PyThreadState) -> Self {
+        Self(unsafe { pyffi::PyThreadState_Swap(thread_state) })
+    }
+}
+
+impl Drop for ThreadStateSwap {
+    fn drop(&mut self) {
+        unsafe { pyffi::PyThreadState_Swap(self.0) };
+    }
 }
 
 static mut ORIGINAL_BUILTIN_EXTENSIONS: Option<Vec<pyffi::_inittab>> = None;
@@ -741,10 +1131,42 @@ fn write_modules_to_directory(py: Python, path: &PathBuf) -> Result<(), &'static
     Ok(())
 }
 
+/// Write import trace records to a directory.
+///
+/// Given a Python interpreter and a path to a directory, this will create a
+/// file in that directory named ``import-trace-<UUID>.json`` containing the
+/// records of every ``OxidizedFinder`` registered on ``sys.meta_path``.
+fn write_import_trace_to_directory(py: Python, path: &PathBuf) -> Result<(), &'static str> {
+    fs::create_dir_all(path).map_err(|_| "could not create directory for import trace")?;
+
+    let rand = uuid::Uuid::new_v4();
+
+    let path = path.join(format!("import-trace-{}.json", rand));
+
+    let records = meta_path_import_trace(py).map_err(|_| "could not obtain import trace")?;
+
+    let json = py
+        .import("json")
+        .map_err(|_| "could not obtain json module")?;
+    let data = json
+        .call(py, "dumps", (records,), None)
+        .map_err(|_| "could not serialize import trace")?
+        .extract::<String>(py)
+        .map_err(|_| "serialized import trace is not a str")?;
+
+    fs::write(path, data).map_err(|_| "could not write import trace file")?;
+
+    Ok(())
+}
+
 impl<'python, 'interpreter, 'resources> Drop
     for MainPythonInterpreter<'python, 'interpreter, 'resources>
 {
     fn drop(&mut self) {
+        if self.interpreter_state == InterpreterState::Initialized {
+            self.end_sub_interpreters();
+        }
+
         if let Some(key) = &self.config.write_modules_directory_env {
             if let Ok(path) = env::var(key) {
                 let path = PathBuf::from(path);
@@ -756,6 +1178,17 @@ impl<'python, 'interpreter, 'resources> Drop
             }
         }
 
+        if let Some(key) = &self.config.write_import_trace_directory_env {
+            if let Ok(path) = env::var(key) {
+                let path = PathBuf::from(path);
+                let py = self.acquire_gil().unwrap();
+
+                if let Err(msg) = write_import_trace_to_directory(py, &path) {
+                    eprintln!("error writing import trace file: {}", msg);
+                }
+            }
+        }
+
         let _ = unsafe { pyffi::Py_FinalizeEx() };
     }
 }
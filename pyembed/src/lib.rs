@@ -29,6 +29,8 @@ and [`MainPythonInterpreter`](struct.MainPythonInterpreter.html). An
 `OxidizedPythonInterpreterConfig` defines how a Python interpreter is to
 behave. A `MainPythonInterpreter` creates and manages that interpreter and
 serves as a high-level interface for running code in the interpreter.
+The [`python_eval`](python_eval/index.html) module contains functions for
+running code and obtaining its result.
 
 # Dependencies
 
@@ -39,10 +41,12 @@ interfacing.
 **It is an explicit goal of this crate to rely on as few external dependencies
 as possible.** This is because we want to minimize bloat in produced binaries.
 At this time, we have required direct dependencies on published versions of the
-`anyhow`, `lazy_static`, `libc`, `memmap`, `python-packed-resources`, and `uuid`
-crates. On Windows, this list is extended by `memory-module-sys` and `winapi`,
-which are required to support loading DLLs from memory. We also have an optional
-direct dependency on the `jemalloc-sys` crate.
+`anyhow`, `lazy_static`, `libc`, `memmap`, `once_cell`,
+`python-packed-resources`, and `uuid` crates.
+On Windows, this list is extended by `memory-module-sys` and `winapi`,
+which are required to support loading DLLs from memory. We also have optional
+direct dependencies on the `jemalloc-sys`, `libmimalloc-sys`, `serde_json`,
+`snmalloc-sys`, and `toml` crates.
 
 This crate requires linking against a library providing CPython C symbols.
 (This dependency is via the `python3-sys` crate.) On Windows, this library
@@ -56,6 +60,25 @@ from Python is a run-time configuration option controlled by the
 `OxidizedPythonInterpreterConfig` type and having `jemalloc` compiled into the
 binary does not mean it is being used!
 
+The optional `mimalloc` and `snmalloc` features likewise control support for
+using [mimalloc](https://github.com/microsoft/mimalloc) and
+[snmalloc](https://github.com/microsoft/snmalloc) as Python's memory allocator.
+
+The optional `compression`, `digests`, and `signatures` features control
+support for zstd compressed module source and packed resources data having
+zstd compressed blob sections,
+verifying blob section digests (`verify_packed_resources_digests`, which
+don't cover the header and indices of the data), and
+verifying ed25519 signatures (`packed_resources_public_key`), respectively.
+They enable the features of the same name of `python-packed-resources`, which
+pull in the `zstd`, `sha2`, and `ed25519-dalek` crates. PyOxidizer never
+enables `signatures` because it doesn't sign the data it produces.
+
+The optional `config-overrides` feature controls support for run-time
+configuration overrides files (`config_overrides_path` and
+`config_overrides_path_env`). It pulls in the `serde_json` and `toml` crates,
+which are used to parse these files.
+
 There exist mutually exclusive `build-mode-*` features to control how the
 `build.rs` build script works.
 
@@ -85,6 +108,8 @@ That crate's build script will attempt to find a `libpython` from the
 
 #[cfg(not(library_mode = "extension"))]
 mod config;
+#[cfg(all(not(library_mode = "extension"), feature = "config-overrides"))]
+mod config_overrides;
 mod conversion;
 #[allow(clippy::transmute_ptr_to_ptr, clippy::zero_ptr)]
 mod importer;
@@ -94,12 +119,16 @@ mod interpreter;
 mod interpreter_config;
 #[cfg(windows)]
 mod memory_dll;
+#[cfg(target_os = "linux")]
+mod memory_so;
 #[cfg(not(library_mode = "extension"))]
 mod osutils;
 #[allow(clippy::transmute_ptr_to_ptr, clippy::zero_ptr)]
 mod package_metadata;
 #[cfg(not(library_mode = "extension"))]
 mod pyalloc;
+#[cfg(not(library_mode = "extension"))]
+pub mod python_eval;
 #[allow(unused_variables, clippy::transmute_ptr_to_ptr, clippy::zero_ptr)]
 mod python_resource_collector;
 #[allow(clippy::transmute_ptr_to_ptr, clippy::zero_ptr)]
@@ -114,11 +143,19 @@ mod test;
 
 #[cfg(not(library_mode = "extension"))]
 #[allow(unused_imports)]
-pub use crate::config::{ExtensionModule, OxidizedPythonInterpreterConfig};
+pub use crate::config::{ExtensionModule, OxidizedPythonInterpreterConfig, PackedResourcesSource};
+
+#[cfg(not(library_mode = "extension"))]
+#[allow(unused_imports)]
+pub use crate::interpreter::{MainPythonInterpreter, NewInterpreterError, SubInterpreterId};
+
+#[cfg(not(library_mode = "extension"))]
+#[allow(unused_imports)]
+pub use crate::python_eval::EvalError;
 
 #[cfg(not(library_mode = "extension"))]
 #[allow(unused_imports)]
-pub use crate::interpreter::{MainPythonInterpreter, NewInterpreterError};
+pub use crate::pyalloc::{raw_rust_allocator_stats, RawAllocatorStats};
 
 #[cfg(library_mode = "extension")]
 pub use crate::importer::PyInit_oxidized_importer;
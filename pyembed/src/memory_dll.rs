@@ -14,6 +14,7 @@ use {
     memory_module_sys::{
         MemoryFreeLibrary, MemoryGetProcAddress, MemoryLoadLibraryEx, HCUSTOMMODULE,
     },
+    python_packed_resources::data::ResourceField,
     std::collections::HashMap,
     std::ffi::{c_void, CStr},
     std::sync::atomic::{AtomicUsize, Ordering},
@@ -149,9 +150,15 @@ extern "C" fn custom_load_library(filename: LPCSTR, user_data: *mut c_void) -> H
             .unwrap()
     };
 
-    if let Some(entry) = resources_state.resources.get(name.as_ref()) {
+    if let Some(entry) = resources_state.get_resource(name.as_ref()) {
         if let Some(library_data) = &entry.in_memory_shared_library {
-            let res = unsafe { load_library_memory(resources_state, library_data) };
+            let library_data =
+                match entry.field_data(ResourceField::InMemorySharedLibrary, library_data) {
+                    Ok(data) => data,
+                    Err(_) => return std::ptr::null() as HCUSTOMMODULE,
+                };
+
+            let res = unsafe { load_library_memory(resources_state, &library_data) };
 
             // If we loaded a module, store its state. Otherwise return its failure (NULL).
             if !res.is_null() {
@@ -0,0 +1,155 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Functionality for loading Linux shared libraries from memory.
+
+Linux doesn't provide an API for loading a shared library from a memory
+address. However, `memfd_create()` gives us an anonymous, memory backed file
+and `dlopen()` can load that file via its `/proc/self/fd/N` path. So we copy
+library data into such a file and have the system loader do the rest.
+
+The loader resolves `DT_NEEDED` entries against already loaded libraries
+by their `DT_SONAME`. So shared library dependencies that are available in
+memory are loaded before the library depending on them.
+
+File descriptors backing loaded libraries are never closed. The loader
+remembers the path a library was opened from and returns the existing
+library when that path is opened again. Since file descriptor numbers are
+reused after close, closing a descriptor could result in a later load
+resolving to the wrong library.
+*/
+
+use {
+    super::python_resources::PythonResourcesState,
+    lazy_static::lazy_static,
+    python_packed_resources::data::ResourceField,
+    std::collections::HashMap,
+    std::ffi::{c_void, CStr, CString},
+    std::fs::File,
+    std::io::Write,
+    std::os::unix::io::{FromRawFd, IntoRawFd, RawFd},
+    std::sync::Mutex,
+};
+
+/// Holds state for shared libraries loaded from memory.
+struct MemoryLibraries {
+    /// Handles of loaded in-memory shared libraries, indexed by resource name.
+    libraries: HashMap<String, *const c_void>,
+}
+
+unsafe impl Send for MemoryLibraries {}
+
+lazy_static! {
+    static ref MEMORY_LIBRARIES: Mutex<MemoryLibraries> = {
+        Mutex::new(MemoryLibraries {
+            libraries: HashMap::new(),
+        })
+    };
+}
+
+/// Write data to a new anonymous, memory backed file.
+///
+/// Returns the file descriptor of the file. The file is closed on `exec()`.
+pub(crate) fn create_memory_file(name: &str, data: &[u8]) -> std::io::Result<RawFd> {
+    let memfd_name =
+        CString::new(name).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    // Not every libc we target exposes a `memfd_create()` wrapper. So
+    // invoke the system call directly.
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_memfd_create,
+            memfd_name.as_ptr(),
+            libc::MFD_CLOEXEC,
+        )
+    } as RawFd;
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mut fh = unsafe { File::from_raw_fd(fd) };
+    fh.write_all(data)?;
+
+    Ok(fh.into_raw_fd())
+}
+
+/// Obtain the filesystem path through which a file descriptor can be opened.
+pub(crate) fn memory_file_path(fd: RawFd) -> String {
+    format!("/proc/self/fd/{}", fd)
+}
+
+/// Obtain the message describing the last `dlopen()` failure.
+fn dlerror_message() -> String {
+    let message = unsafe { libc::dlerror() };
+
+    if message.is_null() {
+        "unknown error".to_string()
+    } else {
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+/// Ensure the named shared library is loaded if it is available in memory.
+///
+/// Libraries not available in memory are left for the system loader to find.
+fn load_shared_library(
+    resources_state: &PythonResourcesState<u8>,
+    name: &str,
+) -> Result<(), String> {
+    let mut state = MEMORY_LIBRARIES.lock().unwrap();
+
+    if state.libraries.contains_key(name) {
+        return Ok(());
+    }
+
+    let library_data = match resources_state.get_resource(name) {
+        Some(entry) => match &entry.in_memory_shared_library {
+            Some(data) => entry
+                .field_data(ResourceField::InMemorySharedLibrary, data)
+                .map_err(|e| e.to_string())?,
+            None => return Ok(()),
+        },
+        None => return Ok(()),
+    };
+
+    let fd = create_memory_file(name, &library_data).map_err(|e| e.to_string())?;
+    let path = CString::new(memory_file_path(fd)).unwrap();
+
+    let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+
+    if handle.is_null() {
+        let message = dlerror_message();
+        unsafe {
+            libc::close(fd);
+        }
+        return Err(message);
+    }
+
+    state.libraries.insert(name.to_string(), handle);
+
+    Ok(())
+}
+
+/// Load in-memory shared library dependencies of the named resource.
+///
+/// Dependencies are resolved from the resource's shared library dependency
+/// names. This should be called before loading the resource's library so
+/// the loader finds the dependencies already loaded.
+pub(crate) fn load_shared_library_dependencies(
+    resources_state: &PythonResourcesState<u8>,
+    name: &str,
+) -> Result<(), String> {
+    if let Some(entry) = resources_state.get_resource(name) {
+        if let Some(depends) = &entry.shared_library_dependency_names {
+            for dependency in depends {
+                load_shared_library(resources_state, dependency)
+                    .map_err(|e| format!("unable to load {}: {}", dependency, e))?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -4,14 +4,13 @@
 
 use {
     super::importer::ImporterState,
-    cpython::exc::{IOError, NotImplementedError},
+    cpython::exc::{FileNotFoundError, IOError, ValueError},
     cpython::{
-        py_class, NoArgs, ObjectProtocol, PyBytes, PyErr, PyList, PyObject, PyResult, PyString,
-        Python, PythonObject, ToPyObject,
+        py_class, NoArgs, ObjectProtocol, PyBytes, PyClone, PyDict, PyErr, PyList, PyObject,
+        PyResult, PyString, PyTuple, Python, PythonObject, ToPyObject,
     },
-    python_packed_resources::data::Resource,
+    python_packed_resources::data::{Resource, ResourceField},
     std::borrow::Cow,
-    std::collections::HashMap,
     std::path::Path,
     std::sync::Arc,
 };
@@ -44,6 +43,10 @@ py_class!(class OxidizedDistribution |py| {
     @property def requires(&self) -> PyResult<PyObject> {
         self.requires_impl(py)
     }
+
+    def locate_file(&self, path: &PyObject) -> PyResult<PyObject> {
+        self.locate_file_impl(py, path)
+    }
 });
 
 impl OxidizedDistribution {
@@ -55,9 +58,8 @@ impl OxidizedDistribution {
         let filename = filename.to_string_lossy(py);
 
         let data = resolve_package_distribution_resource(
-            &resources_state.resources,
+            resources_state.get_resource(package),
             &resources_state.origin,
-            package,
             &filename,
         )
         .map_err(|e| {
@@ -91,9 +93,8 @@ impl OxidizedDistribution {
         let resources_state = state.get_resources_state();
 
         let data = resolve_package_distribution_resource(
-            &resources_state.resources,
+            resources_state.get_resource(package),
             &resources_state.origin,
-            package,
             "METADATA",
         )
         .map_err(|e| {
@@ -104,9 +105,8 @@ impl OxidizedDistribution {
             data
         } else {
             resolve_package_distribution_resource(
-                &resources_state.resources,
+                resources_state.get_resource(package),
                 &resources_state.origin,
-                package,
                 "PKG-INFO",
             )
             .map_err(|e| {
@@ -139,8 +139,79 @@ impl OxidizedDistribution {
         entry_point.call_method(py, "_from_text", (text,), None)
     }
 
+    /// Return the files in this distribution.
+    ///
+    /// Files are read from the `RECORD` distribution resource, falling back to
+    /// `SOURCES.txt`. Returns None if neither is present.
     fn files_impl(&self, py: Python) -> PyResult<PyObject> {
-        Err(PyErr::new::<NotImplementedError, _>(py, NoArgs))
+        let text = self.read_text_impl(py, &"RECORD".to_py_object(py))?;
+
+        let lines = if text != py.None() {
+            text.call_method(py, "splitlines", NoArgs, None)?
+        } else {
+            let text = self.read_text_impl(py, &"SOURCES.txt".to_py_object(py))?;
+
+            if text == py.None() {
+                return Ok(py.None());
+            }
+
+            // SOURCES.txt entries are bare paths. Quote them so they parse as
+            // a single CSV column.
+            let lines = text
+                .extract::<String>(py)?
+                .lines()
+                .map(|line| format!("\"{}\"", line).to_py_object(py).into_object())
+                .collect::<Vec<_>>();
+
+            PyList::new(py, &lines).into_object()
+        };
+
+        let importlib_metadata = py.import("importlib.metadata")?;
+        let package_path = importlib_metadata.get(py, "PackagePath")?;
+        let file_hash = importlib_metadata.get(py, "FileHash")?;
+
+        let csv = py.import("csv")?;
+        let reader = csv.call(py, "reader", (lines,), None)?;
+
+        let mut files = Vec::new();
+
+        for row in reader.iter(py)? {
+            let row = row?.extract::<Vec<String>>(py)?;
+
+            let name = match row.first() {
+                Some(name) => name,
+                None => continue,
+            };
+
+            let path = package_path.call(py, (name,), None)?;
+
+            let hash = match row.get(1) {
+                Some(hash) if !hash.is_empty() => file_hash.call(py, (hash,), None)?,
+                _ => py.None(),
+            };
+
+            let size = match row.get(2) {
+                Some(size) if !size.is_empty() => size
+                    .parse::<u64>()
+                    .map_err(|_| {
+                        PyErr::new::<ValueError, _>(
+                            py,
+                            format!("invalid size in RECORD entry for {}: {}", name, size),
+                        )
+                    })?
+                    .to_py_object(py)
+                    .into_object(),
+                _ => py.None(),
+            };
+
+            path.setattr(py, "hash", hash)?;
+            path.setattr(py, "size", size)?;
+            path.setattr(py, "dist", self.as_object())?;
+
+            files.push(path);
+        }
+
+        Ok(PyList::new(py, &files).into_object())
     }
 
     fn requires_impl(&self, py: Python) -> PyResult<PyObject> {
@@ -173,6 +244,233 @@ impl OxidizedDistribution {
             Ok(res)
         }
     }
+
+    /// Return an object for reading a file in this distribution.
+    ///
+    /// `path` is relative to the directory containing the package, as in
+    /// `RECORD` files.
+    fn locate_file_impl(&self, py: Python, path: &PyObject) -> PyResult<PyObject> {
+        let path = path.str(py)?.to_string(py)?.replace('\\', "/");
+
+        Ok(OxidizedDistributionFile::create_instance(
+            py,
+            self.state(py).clone(),
+            self.package(py).clone(),
+            path,
+        )?
+        .into_object())
+    }
+}
+
+// A file belonging to a package distribution.
+//
+// Instances are returned by OxidizedDistribution.locate_file() and read file
+// content through the oxidized finder's resources.
+py_class!(class OxidizedDistributionFile |py| {
+    data state: Arc<ImporterState>;
+    data package: String;
+    // Path relative to the directory containing the package, using `/` as a
+    // separator.
+    data path: String;
+
+    @property def name(&self) -> PyResult<PyObject> {
+        Ok(self.path(py).rsplit('/').next().unwrap_or("").to_py_object(py).into_object())
+    }
+
+    def exists(&self) -> PyResult<PyObject> {
+        Ok(self.resolve_data(py)?.is_some().to_py_object(py).into_object())
+    }
+
+    def read_bytes(&self) -> PyResult<PyObject> {
+        self.read_bytes_impl(py)
+    }
+
+    def read_text(&self, encoding: Option<PyObject> = None) -> PyResult<PyObject> {
+        let kwargs = PyDict::new(py);
+        if let Some(encoding) = encoding {
+            kwargs.set_item(py, "encoding", encoding)?;
+        }
+
+        self.open_text(py, &PyTuple::empty(py), &kwargs)?
+            .call_method(py, "read", NoArgs, None)
+    }
+
+    // mode may be 'r' or 'rb' to open as text or binary. When opening as
+    // text, accepts encoding parameters such as those accepted by
+    // io.TextIOWrapper.
+    def open(&self, *args, **kwargs) -> PyResult<PyObject> {
+        self.open_impl(py, args, kwargs)
+    }
+
+    def __str__(&self) -> PyResult<String> {
+        Ok(self.path(py).clone())
+    }
+
+    def __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "<OxidizedDistributionFile package={:?} path={:?}>",
+            self.package(py),
+            self.path(py)
+        ))
+    }
+});
+
+impl OxidizedDistributionFile {
+    /// Resolve the content of this file as a `bytes`.
+    ///
+    /// Files in the `.dist-info` or `.egg-info` directory are distribution
+    /// resources. `.py` files are module sources. Everything else is
+    /// looked up as a resource of the longest matching package.
+    fn resolve_data(&self, py: Python) -> PyResult<Option<PyObject>> {
+        let state: &Arc<ImporterState> = self.state(py);
+        let resources_state = state.get_resources_state();
+        let path = self.path(py).trim_start_matches("./");
+
+        let parts = path.split('/').collect::<Vec<_>>();
+
+        if parts.len() > 1 && (parts[0].ends_with(".dist-info") || parts[0].ends_with(".egg-info"))
+        {
+            let data = resolve_package_distribution_resource(
+                resources_state.get_resource(self.package(py)),
+                &resources_state.origin,
+                &parts[1..].join("/"),
+            )
+            .map_err(|e| {
+                PyErr::new::<IOError, _>(py, format!("error when resolving resource: {}", e))
+            })?;
+
+            return Ok(data.map(|data| PyBytes::new(py, &data).into_object()));
+        }
+
+        if let Some(stem) = path.strip_suffix(".py") {
+            let mut module_parts = stem.split('/').collect::<Vec<_>>();
+            if module_parts.len() > 1 && module_parts.last() == Some(&"__init__") {
+                module_parts.pop();
+            }
+
+            if let Some(entry) = resources_state.get_resource(&module_parts.join(".")) {
+                if let Some(source) = &entry.in_memory_source {
+                    let source = entry
+                        .field_data(ResourceField::InMemorySource, source)
+                        .map_err(|e| {
+                            PyErr::new::<IOError, _>(
+                                py,
+                                format!("error when resolving resource: {}", e),
+                            )
+                        })?;
+
+                    return Ok(Some(PyBytes::new(py, &source).into_object()));
+                }
+
+                if let Some(relative_path) = &entry.relative_path_module_source {
+                    let source = std::fs::read(resources_state.origin.join(relative_path))
+                        .map_err(|e| {
+                            PyErr::new::<IOError, _>(
+                                py,
+                                format!("error reading module source: {}", e),
+                            )
+                        })?;
+
+                    return Ok(Some(PyBytes::new(py, &source).into_object()));
+                }
+            }
+        }
+
+        for split in (1..parts.len()).rev() {
+            let package = parts[0..split].join(".");
+            let resource_name = parts[split..].join("/");
+
+            if resources_state.is_package_resource(&package, &resource_name) {
+                if let Some(file) =
+                    resources_state.get_package_resource_file(py, &package, &resource_name)?
+                {
+                    let data = file.call_method(py, "read", NoArgs, None)?;
+                    file.call_method(py, "close", NoArgs, None)?;
+
+                    return Ok(Some(data));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn read_bytes_impl(&self, py: Python) -> PyResult<PyObject> {
+        match self.resolve_data(py)? {
+            Some(data) => Ok(data),
+            None => Err(PyErr::new::<FileNotFoundError, _>(
+                py,
+                format!("distribution file not found: {}", self.path(py)),
+            )),
+        }
+    }
+
+    /// Open this file for text reading.
+    ///
+    /// Arguments are passed to `io.TextIOWrapper`.
+    fn open_text(&self, py: Python, args: &PyTuple, kwargs: &PyDict) -> PyResult<PyObject> {
+        let data = self.read_bytes_impl(py)?;
+
+        let io_module = py.import("io")?;
+        let bytes_io = io_module.call(py, "BytesIO", (data,), None)?;
+
+        let mut wrapper_args = vec![bytes_io];
+        wrapper_args.extend(args.iter(py).map(|arg| arg.clone_ref(py)));
+
+        io_module.call(
+            py,
+            "TextIOWrapper",
+            PyTuple::new(py, &wrapper_args),
+            Some(kwargs),
+        )
+    }
+
+    fn open_impl(&self, py: Python, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<PyObject> {
+        let kwargs = match kwargs {
+            Some(kwargs) => kwargs.copy(py)?,
+            None => PyDict::new(py),
+        };
+
+        let (mode, args) = if args.len(py) > 0 {
+            (
+                Some(args.get_item(py, 0)),
+                PyTuple::new(py, &args.as_slice(py)[1..]),
+            )
+        } else {
+            let mode = kwargs.get_item(py, "mode");
+            if mode.is_some() {
+                kwargs.del_item(py, "mode")?;
+            }
+
+            (mode, PyTuple::empty(py))
+        };
+
+        let mode = match mode {
+            Some(mode) => mode.extract::<String>(py)?,
+            None => "r".to_string(),
+        };
+
+        match mode.as_str() {
+            "r" => self.open_text(py, &args, &kwargs),
+            "rb" => {
+                if args.len(py) > 0 || kwargs.len(py) > 0 {
+                    Err(PyErr::new::<ValueError, _>(
+                        py,
+                        "binary mode doesn't take encoding arguments",
+                    ))
+                } else {
+                    let data = self.read_bytes_impl(py)?;
+                    let io_module = py.import("io")?;
+
+                    io_module.call(py, "BytesIO", (data,), None)
+                }
+            }
+            _ => Err(PyErr::new::<ValueError, _>(
+                py,
+                format!("invalid mode: {}", mode),
+            )),
+        }
+    }
 }
 
 /// Find package metadata distributions given search criteria.
@@ -182,15 +480,13 @@ pub(crate) fn find_distributions(
     name: Option<PyObject>,
     _path: Option<PyObject>,
 ) -> PyResult<PyObject> {
-    let resources = &state.get_resources_state().resources;
+    let resources_state = state.get_resources_state();
 
     let distributions = if let Some(name) = name {
         // Python normalizes the name. We do the same.
         let name = name.str(py)?.to_string(py)?.to_string();
         let name = name.to_lowercase().replace('-', "_");
-        let name_cow = Cow::Borrowed::<str>(&name);
-
-        if let Some(resource) = resources.get(&name_cow) {
+        if let Some(resource) = resources_state.get_resource(&name) {
             if resource.is_package
                 && (resource.in_memory_distribution_resources.is_some()
                     || resource.relative_path_distribution_resources.is_some())
@@ -206,13 +502,13 @@ pub(crate) fn find_distributions(
         // Return all distributions.
         let mut distributions = Vec::new();
 
-        for (k, v) in resources.iter() {
+        for v in resources_state.all_resources() {
             if v.is_package
                 && (v.in_memory_distribution_resources.is_some()
                     || v.relative_path_distribution_resources.is_some())
             {
                 distributions.push(
-                    OxidizedDistribution::create_instance(py, state.clone(), k.to_string())?
+                    OxidizedDistribution::create_instance(py, state.clone(), v.name.to_string())?
                         .into_object(),
                 );
             }
@@ -225,15 +521,18 @@ pub(crate) fn find_distributions(
 }
 
 fn resolve_package_distribution_resource<'a>(
-    resources: &'a HashMap<Cow<'a, str>, Resource<'a, u8>>,
+    entry: Option<&'a Resource<'a, u8>>,
     origin: &Path,
-    package: &str,
     name: &str,
 ) -> anyhow::Result<Option<Cow<'a, [u8]>>> {
-    if let Some(entry) = resources.get(package) {
+    if let Some(entry) = entry {
         if let Some(resources) = &entry.in_memory_distribution_resources {
             if let Some(data) = resources.get(name) {
-                return Ok(Some(Cow::Borrowed(data.as_ref())));
+                return Ok(Some(
+                    entry
+                        .field_data(ResourceField::InMemoryDistributionResource, data)
+                        .map_err(|e| anyhow::anyhow!(e))?,
+                ));
             }
         }
 
@@ -5,34 +5,144 @@
 //! Custom Python memory allocators.
 
 use {
+    cpython::{PyDict, PyObject, PyResult, Python, PythonObject},
     libc::{c_void, size_t},
     python3_sys as pyffi,
     std::alloc,
-    std::collections::HashMap,
+    std::sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
 #[cfg(feature = "jemalloc-sys")]
-use {jemalloc_sys as jemallocffi, std::ptr::null_mut};
+use jemalloc_sys as jemallocffi;
+
+#[cfg(feature = "libmimalloc-sys")]
+use libmimalloc_sys as mimallocffi;
+
+#[cfg(feature = "snmalloc-sys")]
+use snmalloc_sys as snmallocffi;
+
+#[cfg(any(
+    feature = "jemalloc-sys",
+    feature = "libmimalloc-sys",
+    feature = "snmalloc-sys"
+))]
+use std::ptr::null_mut;
 
 const MIN_ALIGN: usize = 16;
 
-type RawAllocatorState = HashMap<*mut u8, alloc::Layout>;
+/// Size of the header preceding each allocation made by the Rust allocator.
+///
+/// The header holds the requested size of the allocation. This allows us to
+/// reconstruct the `Layout` Rust's `dealloc()` and `realloc()` require without
+/// tracking allocations in a side table. Using `MIN_ALIGN` bytes preserves the
+/// alignment of the memory handed to Python.
+const HEADER_SIZE: usize = MIN_ALIGN;
+
+// Counters for allocations made by the Rust raw allocator.
+//
+// Python's raw allocator domain is process global and can be called without
+// the GIL. So these are process global and atomic.
+static RUST_ALLOCATOR_INSTALLED: AtomicBool = AtomicBool::new(false);
+static RUST_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static RUST_TOTAL_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static RUST_BYTES: AtomicUsize = AtomicUsize::new(0);
+static RUST_PEAK_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Statistics about memory allocated by the Rust raw allocator.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RawAllocatorStats {
+    /// Number of allocations that haven't been freed.
+    pub allocations: usize,
+    /// Number of allocations ever made. Reallocations aren't counted.
+    pub total_allocations: usize,
+    /// Bytes requested by allocations that haven't been freed.
+    pub bytes: usize,
+    /// Highest value `bytes` has reached.
+    pub peak_bytes: usize,
+}
 
-/// Holds state for the raw memory allocator.
+/// Obtain statistics about the Rust raw allocator.
 ///
-/// Ideally we wouldn't need to track state. But Rust's dealloc() API
-/// requires passing in a Layout that matches the allocation. This means
-/// we need to track the Layout for each allocation. This data structure
-/// facilitates that.
+/// Returns `None` if the Rust raw allocator hasn't been used in this process.
+pub fn raw_rust_allocator_stats() -> Option<RawAllocatorStats> {
+    if !RUST_ALLOCATOR_INSTALLED.load(Ordering::SeqCst) {
+        return None;
+    }
+
+    Some(RawAllocatorStats {
+        allocations: RUST_ALLOCATIONS.load(Ordering::SeqCst),
+        total_allocations: RUST_TOTAL_ALLOCATIONS.load(Ordering::SeqCst),
+        bytes: RUST_BYTES.load(Ordering::SeqCst),
+        peak_bytes: RUST_PEAK_BYTES.load(Ordering::SeqCst),
+    })
+}
+
+/// raw_allocator_stats()
 ///
-/// TODO HashMap isn't thread safe and the Python raw allocator doesn't
-/// hold the GIL. So we need a thread safe map or a mutex guarding access.
+/// Python-callable wrapper of `raw_rust_allocator_stats()`. Returns a dict or
+/// `None`.
+pub(crate) fn raw_allocator_stats(py: Python) -> PyResult<PyObject> {
+    let stats = match raw_rust_allocator_stats() {
+        Some(stats) => stats,
+        None => return Ok(py.None()),
+    };
+
+    let res = PyDict::new(py);
+    res.set_item(py, "allocations", stats.allocations)?;
+    res.set_item(py, "total_allocations", stats.total_allocations)?;
+    res.set_item(py, "bytes", stats.bytes)?;
+    res.set_item(py, "peak_bytes", stats.peak_bytes)?;
+
+    Ok(res.into_object())
+}
+
+/// Holds the raw memory allocator.
+///
+/// The allocator doesn't have any state of its own: allocation sizes are
+/// stored in a header in front of each allocation and statistics are
+/// process global.
 pub struct RawAllocator {
     pub allocator: pyffi::PyMemAllocatorEx,
-    _state: Box<RawAllocatorState>,
 }
 
-extern "C" fn raw_rust_malloc(ctx: *mut c_void, size: size_t) -> *mut c_void {
+/// Obtain the `Layout` of an allocation holding `size` bytes plus the header.
+///
+/// Returns `None` if the size of the allocation would overflow.
+fn rust_layout(size: usize) -> Option<alloc::Layout> {
+    alloc::Layout::from_size_align(size.checked_add(HEADER_SIZE)?, MIN_ALIGN).ok()
+}
+
+/// Record a change in the number of live bytes.
+fn record_bytes(added: usize, removed: usize) {
+    if added > removed {
+        let current = RUST_BYTES.fetch_add(added - removed, Ordering::SeqCst) + added - removed;
+        RUST_PEAK_BYTES.fetch_max(current, Ordering::SeqCst);
+    } else {
+        RUST_BYTES.fetch_sub(removed - added, Ordering::SeqCst);
+    }
+}
+
+/// Write the allocation header and return the pointer handed to Python.
+///
+/// `base` is the start of the allocation as returned by Rust.
+unsafe fn finish_allocation(base: *mut u8, size: usize) -> *mut c_void {
+    if base.is_null() {
+        return std::ptr::null_mut();
+    }
+
+    (base as *mut usize).write(size);
+
+    base.add(HEADER_SIZE) as *mut c_void
+}
+
+/// Obtain the start of the allocation and its size from a pointer handed to Python.
+unsafe fn allocation_base(ptr: *mut c_void) -> (*mut u8, usize) {
+    let base = (ptr as *mut u8).sub(HEADER_SIZE);
+
+    (base, (base as *mut usize).read())
+}
+
+extern "C" fn raw_rust_malloc(_ctx: *mut c_void, size: size_t) -> *mut c_void {
     // PyMem_RawMalloc()'s docs say: Requesting zero bytes returns a distinct
     // non-NULL pointer if possible, as if PyMem_RawMalloc(1) had been called
     // instead.
@@ -41,38 +151,46 @@ extern "C" fn raw_rust_malloc(ctx: *mut c_void, size: size_t) -> *mut c_void {
         val => val,
     };
 
-    unsafe {
-        let state = ctx as *mut RawAllocatorState;
-        let layout = alloc::Layout::from_size_align_unchecked(size, MIN_ALIGN);
-        let res = alloc::alloc(layout);
+    let layout = match rust_layout(size) {
+        Some(layout) => layout,
+        None => return std::ptr::null_mut(),
+    };
 
-        (*state).insert(res, layout);
+    let res = unsafe { finish_allocation(alloc::alloc(layout), size) };
 
-        //println!("allocated {} bytes to {:?}", size, res);
-        res as *mut c_void
+    if !res.is_null() {
+        RUST_ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        RUST_TOTAL_ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        record_bytes(size, 0);
     }
+
+    res
 }
 
-extern "C" fn raw_rust_calloc(ctx: *mut c_void, nelem: size_t, elsize: size_t) -> *mut c_void {
+extern "C" fn raw_rust_calloc(_ctx: *mut c_void, nelem: size_t, elsize: size_t) -> *mut c_void {
     // PyMem_RawCalloc()'s docs say: Requesting zero elements or elements of
     // size zero bytes returns a distinct non-NULL pointer if possible, as if
     // PyMem_RawCalloc(1, 1) had been called instead.
-    let size = match nelem * elsize {
-        0 => 1,
-        val => val,
+    let size = match nelem.checked_mul(elsize) {
+        Some(0) => 1,
+        Some(val) => val,
+        None => return std::ptr::null_mut(),
     };
 
-    unsafe {
-        let state = ctx as *mut RawAllocatorState;
-        let layout = alloc::Layout::from_size_align_unchecked(size, MIN_ALIGN);
-        let res = alloc::alloc_zeroed(layout);
-
-        (*state).insert(res, layout);
+    let layout = match rust_layout(size) {
+        Some(layout) => layout,
+        None => return std::ptr::null_mut(),
+    };
 
-        //println!("zero allocated {} bytes to {:?}", size, res);
+    let res = unsafe { finish_allocation(alloc::alloc_zeroed(layout), size) };
 
-        res as *mut c_void
+    if !res.is_null() {
+        RUST_ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        RUST_TOTAL_ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        record_bytes(size, 0);
     }
+
+    res
 }
 
 extern "C" fn raw_rust_realloc(
@@ -80,8 +198,6 @@ extern "C" fn raw_rust_realloc(
     ptr: *mut c_void,
     new_size: size_t,
 ) -> *mut c_void {
-    //println!("reallocating {:?} to {} bytes", ptr as *mut u8, new_size);
-
     // PyMem_RawRealloc()'s docs say: If p is NULL, the call is equivalent to
     // PyMem_RawMalloc(n); else if n is equal to zero, the memory block is
     // resized but is not freed, and the returned pointer is non-NULL.
@@ -94,61 +210,59 @@ extern "C" fn raw_rust_realloc(
         val => val,
     };
 
+    let new_layout = match rust_layout(new_size) {
+        Some(layout) => layout,
+        None => return std::ptr::null_mut(),
+    };
+
     unsafe {
-        let state = ctx as *mut RawAllocatorState;
-        let layout = alloc::Layout::from_size_align_unchecked(new_size, MIN_ALIGN);
+        let (base, old_size) = allocation_base(ptr);
 
-        let key = ptr as *mut u8;
-        let old_layout = (*state)
-            .remove(&key)
-            .expect("original memory address not tracked");
+        // The layout was valid when the allocation was made.
+        let old_layout = rust_layout(old_size).unwrap();
 
-        let res = alloc::realloc(ptr as *mut u8, old_layout, new_size);
+        let res = finish_allocation(
+            alloc::realloc(base, old_layout, new_layout.size()),
+            new_size,
+        );
 
-        (*state).insert(res, layout);
+        // On failure, the original allocation is left untouched.
+        if !res.is_null() {
+            record_bytes(new_size, old_size);
+        }
 
-        res as *mut c_void
+        res
     }
 }
 
-extern "C" fn raw_rust_free(ctx: *mut c_void, ptr: *mut c_void) {
+extern "C" fn raw_rust_free(_ctx: *mut c_void, ptr: *mut c_void) {
     if ptr.is_null() {
         return;
     }
 
-    //println!("freeing {:?}", ptr as *mut u8);
     unsafe {
-        let state = ctx as *mut RawAllocatorState;
+        let (base, size) = allocation_base(ptr);
 
-        let key = ptr as *mut u8;
-        let layout = (*state)
-            .get(&key)
-            .unwrap_or_else(|| panic!("could not find allocated memory record: {:?}", key));
+        // The layout was valid when the allocation was made.
+        alloc::dealloc(base, rust_layout(size).unwrap());
 
-        alloc::dealloc(key, *layout);
-        (*state).remove(&key);
+        RUST_ALLOCATIONS.fetch_sub(1, Ordering::SeqCst);
+        record_bytes(0, size);
     }
 }
 
 pub fn make_raw_rust_memory_allocator() -> RawAllocator {
-    // We need to allocate the HashMap on the heap so the pointer doesn't refer
-    // to the stack. We rebox and add the Box to our struct so lifetimes are
-    // managed.
-    let alloc = Box::new(HashMap::<*mut u8, alloc::Layout>::new());
-    let state = Box::into_raw(alloc);
+    RUST_ALLOCATOR_INSTALLED.store(true, Ordering::SeqCst);
 
     let allocator = pyffi::PyMemAllocatorEx {
-        ctx: state as *mut c_void,
+        ctx: std::ptr::null_mut(),
         malloc: Some(raw_rust_malloc),
         calloc: Some(raw_rust_calloc),
         realloc: Some(raw_rust_realloc),
         free: Some(raw_rust_free),
     };
 
-    RawAllocator {
-        allocator,
-        _state: unsafe { Box::from_raw(state) },
-    }
+    RawAllocator { allocator }
 }
 
 // Now let's define a raw memory allocator that interfaces directly with jemalloc.
@@ -220,3 +334,144 @@ pub fn make_raw_jemalloc_allocator() -> pyffi::PyMemAllocatorEx {
         free: Some(raw_jemalloc_free),
     }
 }
+
+// Raw memory allocators that interface directly with mimalloc and snmalloc.
+// Like jemalloc's, these avoid the overhead of going through Rust's allocation
+// layer. Both libraries track allocation sizes themselves, so the C API can be
+// used as-is.
+
+#[cfg(feature = "libmimalloc-sys")]
+extern "C" fn raw_mimalloc_malloc(_ctx: *mut c_void, size: size_t) -> *mut c_void {
+    // PyMem_RawMalloc()'s docs say: Requesting zero bytes returns a distinct
+    // non-NULL pointer if possible, as if PyMem_RawMalloc(1) had been called
+    // instead.
+    let size = match size {
+        0 => 1,
+        val => val,
+    };
+
+    unsafe { mimallocffi::mi_malloc(size) as *mut _ }
+}
+
+#[cfg(feature = "libmimalloc-sys")]
+extern "C" fn raw_mimalloc_calloc(_ctx: *mut c_void, nelem: size_t, elsize: size_t) -> *mut c_void {
+    // PyMem_RawCalloc()'s docs say: Requesting zero elements or elements of
+    // size zero bytes returns a distinct non-NULL pointer if possible, as if
+    // PyMem_RawCalloc(1, 1) had been called instead.
+    let size = match nelem.checked_mul(elsize) {
+        Some(0) => 1,
+        Some(val) => val,
+        None => return null_mut(),
+    };
+
+    unsafe { mimallocffi::mi_zalloc(size) as *mut _ }
+}
+
+#[cfg(feature = "libmimalloc-sys")]
+extern "C" fn raw_mimalloc_realloc(
+    ctx: *mut c_void,
+    ptr: *mut c_void,
+    new_size: size_t,
+) -> *mut c_void {
+    // PyMem_RawRealloc()'s docs say: If p is NULL, the call is equivalent to
+    // PyMem_RawMalloc(n); else if n is equal to zero, the memory block is
+    // resized but is not freed, and the returned pointer is non-NULL.
+    if ptr.is_null() {
+        return raw_mimalloc_malloc(ctx, new_size);
+    }
+
+    let new_size = match new_size {
+        0 => 1,
+        val => val,
+    };
+
+    unsafe { mimallocffi::mi_realloc(ptr as *mut _, new_size) as *mut _ }
+}
+
+#[cfg(feature = "libmimalloc-sys")]
+extern "C" fn raw_mimalloc_free(_ctx: *mut c_void, ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+
+    unsafe { mimallocffi::mi_free(ptr as *mut _) }
+}
+
+#[cfg(feature = "libmimalloc-sys")]
+pub fn make_raw_mimalloc_allocator() -> pyffi::PyMemAllocatorEx {
+    pyffi::PyMemAllocatorEx {
+        ctx: null_mut(),
+        malloc: Some(raw_mimalloc_malloc),
+        calloc: Some(raw_mimalloc_calloc),
+        realloc: Some(raw_mimalloc_realloc),
+        free: Some(raw_mimalloc_free),
+    }
+}
+
+#[cfg(feature = "snmalloc-sys")]
+extern "C" fn raw_snmalloc_malloc(_ctx: *mut c_void, size: size_t) -> *mut c_void {
+    // PyMem_RawMalloc()'s docs say: Requesting zero bytes returns a distinct
+    // non-NULL pointer if possible, as if PyMem_RawMalloc(1) had been called
+    // instead.
+    let size = match size {
+        0 => 1,
+        val => val,
+    };
+
+    unsafe { snmallocffi::sn_malloc(size) as *mut _ }
+}
+
+#[cfg(feature = "snmalloc-sys")]
+extern "C" fn raw_snmalloc_calloc(_ctx: *mut c_void, nelem: size_t, elsize: size_t) -> *mut c_void {
+    // PyMem_RawCalloc()'s docs say: Requesting zero elements or elements of
+    // size zero bytes returns a distinct non-NULL pointer if possible, as if
+    // PyMem_RawCalloc(1, 1) had been called instead.
+    let (nelem, elsize) = match (nelem, elsize) {
+        (0, _) | (_, 0) => (1, 1),
+        val => val,
+    };
+
+    // sn_calloc() returns NULL on overflow.
+    unsafe { snmallocffi::sn_calloc(nelem, elsize) as *mut _ }
+}
+
+#[cfg(feature = "snmalloc-sys")]
+extern "C" fn raw_snmalloc_realloc(
+    ctx: *mut c_void,
+    ptr: *mut c_void,
+    new_size: size_t,
+) -> *mut c_void {
+    // PyMem_RawRealloc()'s docs say: If p is NULL, the call is equivalent to
+    // PyMem_RawMalloc(n); else if n is equal to zero, the memory block is
+    // resized but is not freed, and the returned pointer is non-NULL.
+    if ptr.is_null() {
+        return raw_snmalloc_malloc(ctx, new_size);
+    }
+
+    let new_size = match new_size {
+        0 => 1,
+        val => val,
+    };
+
+    unsafe { snmallocffi::sn_realloc(ptr as *mut _, new_size) as *mut _ }
+}
+
+#[cfg(feature = "snmalloc-sys")]
+extern "C" fn raw_snmalloc_free(_ctx: *mut c_void, ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+
+    unsafe { snmallocffi::sn_free(ptr as *mut _) }
+}
+
+#[cfg(feature = "snmalloc-sys")]
+pub fn make_raw_snmalloc_allocator() -> pyffi::PyMemAllocatorEx {
+    pyffi::PyMemAllocatorEx {
+        ctx: null_mut(),
+        malloc: Some(raw_snmalloc_malloc),
+        calloc: Some(raw_snmalloc_calloc),
+        realloc: Some(raw_snmalloc_realloc),
+        free: Some(raw_snmalloc_free),
+    }
+}
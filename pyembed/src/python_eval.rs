@@ -0,0 +1,133 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Evaluate Python code in a running interpreter.
+
+Unlike `MainPythonInterpreter::run_as_main()`, the functions in this module
+leave the interpreter running and return the result of evaluation. This
+allows them to be called repeatedly.
+*/
+
+use {
+    super::conversion::path_to_pyobject,
+    cpython::exc::SyntaxError,
+    cpython::{
+        ObjectProtocol, PyClone, PyDict, PyErr, PyObject, PyResult, PyString, Python, PythonObject,
+    },
+    std::fmt::{Display, Formatter},
+    std::path::Path,
+};
+
+/// Represents an error encountered when evaluating Python code.
+#[derive(Debug)]
+pub enum EvalError {
+    /// The interpreter isn't in a state where code can be run.
+    Interpreter(&'static str),
+    /// A Python exception was raised.
+    ///
+    /// `traceback` holds the exception formatted like Python's default
+    /// exception handler would print it.
+    Python { err: PyErr, traceback: String },
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            EvalError::Interpreter(value) => value.fmt(f),
+            EvalError::Python { traceback, .. } => traceback.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+impl EvalError {
+    pub fn new_from_pyerr(py: Python, err: PyErr) -> Self {
+        let traceback = format_exception(py, &err)
+            .unwrap_or_else(|_| "unable to format Python exception".to_string());
+
+        EvalError::Python { err, traceback }
+    }
+}
+
+/// Format a Python exception and its traceback.
+///
+/// The returned string is what `traceback.print_exception()` would print.
+pub fn format_exception(py: Python, err: &PyErr) -> PyResult<String> {
+    let mut err = err.clone_ref(py);
+    err.normalize(py);
+
+    let traceback = py.import("traceback")?;
+    let lines = traceback.call(
+        py,
+        "format_exception",
+        (
+            err.ptype,
+            err.pvalue.unwrap_or_else(|| py.None()),
+            err.ptraceback.unwrap_or_else(|| py.None()),
+        ),
+        None,
+    )?;
+
+    PyString::new(py, "")
+        .as_object()
+        .call_method(py, "join", (lines,), None)?
+        .extract::<String>(py)
+}
+
+/// Run a Python module as the `__main__` module.
+///
+/// This is equivalent to `python -m <name>`. Returns the globals of the
+/// executed module.
+pub fn run_module_as_main(py: Python, name: &str) -> PyResult<PyObject> {
+    let runpy = py.import("runpy")?;
+
+    let kwargs = PyDict::new(py);
+    kwargs.set_item(py, "run_name", "__main__")?;
+    kwargs.set_item(py, "alter_sys", true)?;
+
+    runpy.call(py, "run_module", (name,), Some(&kwargs))
+}
+
+/// Run a Python file as the `__main__` module.
+///
+/// This is equivalent to `python <path>`. `path` can also refer to a
+/// directory or zip file containing a `__main__.py`. Returns the globals of
+/// the executed module.
+pub fn run_file(py: Python, path: &Path) -> PyResult<PyObject> {
+    let runpy = py.import("runpy")?;
+
+    let kwargs = PyDict::new(py);
+    kwargs.set_item(py, "run_name", "__main__")?;
+
+    runpy.call(
+        py,
+        "run_path",
+        (path_to_pyobject(py, path)?,),
+        Some(&kwargs),
+    )
+}
+
+/// Run Python code in the context of the `__main__` module.
+///
+/// If `code` is a single expression, its value is returned. Otherwise `code`
+/// is executed as a series of statements and `None` is returned.
+pub fn run_code(py: Python, code: &str) -> PyResult<PyObject> {
+    let builtins = py.import("builtins")?;
+
+    // Only compilation is retried so code is never executed twice.
+    let code = match builtins.call(py, "compile", (code, "<string>", "eval"), None) {
+        Ok(code) => code,
+        Err(err) if err.matches(py, py.get_type::<SyntaxError>()) => {
+            builtins.call(py, "compile", (code, "<string>", "exec"), None)?
+        }
+        Err(err) => return Err(err),
+    };
+
+    let main = py.import("__main__")?;
+    let globals = main.get(py, "__dict__")?;
+
+    builtins.call(py, "eval", (code, globals), None)
+}
@@ -13,16 +13,20 @@ use {
         pyobject_to_pathbuf_optional,
     },
     anyhow::Result,
-    cpython::exc::{ImportError, OSError, TypeError},
+    cpython::exc::{ImportError, OSError, TypeError, ValueError},
     cpython::{
         py_class, NoArgs, ObjectProtocol, PyBytes, PyDict, PyErr, PyList, PyModule, PyObject,
         PyResult, PyString, PyTuple, Python, PythonObject, ToPyObject,
     },
+    once_cell::unsync::OnceCell,
     python3_sys as pyffi,
-    python_packed_resources::data::Resource,
+    python_packed_resources::{
+        data::{Resource, ResourceField},
+        parser::ResourceParserIterator,
+    },
     std::borrow::Cow,
     std::cell::RefCell,
-    std::collections::HashMap,
+    std::collections::{hash_map::Entry, HashMap, HashSet},
     std::ffi::CStr,
     std::iter::FromIterator,
     std::path::{Path, PathBuf},
@@ -84,6 +88,9 @@ where
     /// Path from which relative paths should be interpreted.
     origin: &'a Path,
 
+    /// Cache of decompressed module source, keyed by module name.
+    decompressed_sources: &'a RefCell<HashMap<String, Vec<u8>>>,
+
     /// The type of importable module.
     pub flavor: ModuleFlavor,
     /// Whether this module is a package.
@@ -96,6 +103,10 @@ impl<'a> ImportablePythonModule<'a, u8> {
     /// Will return a PyErr if an error occurs resolving source. If there is no source,
     /// returns `Ok(None)`. Otherwise an `Ok(PyString)` cast into a `PyObject` is
     /// returned.
+    ///
+    /// Compressed source is only consulted if plain source isn't available. It is
+    /// decompressed on first use and the result is cached, as rendering a
+    /// traceback can request the source of the same module many times.
     pub fn resolve_source(
         &self,
         py: Python,
@@ -103,7 +114,20 @@ impl<'a> ImportablePythonModule<'a, u8> {
         io_module: &PyModule,
     ) -> PyResult<Option<PyObject>> {
         let bytes = if let Some(data) = &self.resource.in_memory_source {
-            Some(PyBytes::new(py, data))
+            let source = self
+                .resource
+                .field_data(ResourceField::InMemorySource, data)
+                .map_err(|e| {
+                    PyErr::new::<ImportError, _>(
+                        py,
+                        (
+                            format!("error resolving module source: {}", e),
+                            self.resource.name.clone(),
+                        ),
+                    )
+                })?;
+
+            Some(PyBytes::new(py, &source))
         } else if let Some(relative_path) = &self.resource.relative_path_module_source {
             let path = self.origin.join(relative_path);
 
@@ -118,6 +142,31 @@ impl<'a> ImportablePythonModule<'a, u8> {
             })?;
 
             Some(PyBytes::new(py, &source))
+        } else if self.resource.in_memory_compressed_source.is_some() {
+            let mut decompressed_sources = self.decompressed_sources.borrow_mut();
+
+            let source = match decompressed_sources.entry(self.resource.name.to_string()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let source = self
+                        .resource
+                        .decompressed_source()
+                        .map_err(|e| {
+                            PyErr::new::<ImportError, _>(
+                                py,
+                                (
+                                    format!("error decompressing module source: {}", e),
+                                    self.resource.name.clone(),
+                                ),
+                            )
+                        })?
+                        .expect("resource has compressed source");
+
+                    entry.insert(source)
+                }
+            };
+
+            Some(PyBytes::new(py, source))
         } else {
             None
         };
@@ -136,7 +185,7 @@ impl<'a> ImportablePythonModule<'a, u8> {
     /// module in this scenario, but it isn't known to the resources data structure
     /// (e.g. the case of frozen modules).
     ///
-    /// The returned `PyObject` will be an instance of `memoryview`.
+    /// The returned `PyObject` will be an instance of `memoryview` or `bytes`.
     pub fn resolve_bytecode(
         &mut self,
         py: Python,
@@ -144,11 +193,39 @@ impl<'a> ImportablePythonModule<'a, u8> {
         decode_source: &PyObject,
         io_module: &PyModule,
     ) -> PyResult<Option<PyObject>> {
-        if let Some(data) = match optimize_level {
-            OptimizeLevel::Zero => &self.resource.in_memory_bytecode,
-            OptimizeLevel::One => &self.resource.in_memory_bytecode_opt1,
-            OptimizeLevel::Two => &self.resource.in_memory_bytecode_opt2,
-        } {
+        let (field, data) = match optimize_level {
+            OptimizeLevel::Zero => (
+                ResourceField::InMemoryBytecode,
+                &self.resource.in_memory_bytecode,
+            ),
+            OptimizeLevel::One => (
+                ResourceField::InMemoryBytecodeOpt1,
+                &self.resource.in_memory_bytecode_opt1,
+            ),
+            OptimizeLevel::Two => (
+                ResourceField::InMemoryBytecodeOpt2,
+                &self.resource.in_memory_bytecode_opt2,
+            ),
+        };
+
+        if let Some(data) = data {
+            let data = self.resource.field_data(field, data).map_err(|e| {
+                PyErr::new::<ImportError, _>(
+                    py,
+                    (
+                        format!("error resolving bytecode: {}", e),
+                        self.resource.name.clone(),
+                    ),
+                )
+            })?;
+
+            // Decompressed bytecode isn't backed by the resources data. So it
+            // needs to be owned by the Python object.
+            let data = match data {
+                Cow::Borrowed(data) => data,
+                Cow::Owned(data) => return Ok(Some(PyBytes::new(py, &data).into_object())),
+            };
+
             let ptr = unsafe {
                 pyffi::PyMemoryView_FromMemory(
                     data.as_ptr() as _,
@@ -350,8 +427,115 @@ impl<'a> ImportablePythonModule<'a, u8> {
         }
     }
 
-    pub fn in_memory_extension_module_shared_library(&self) -> &'a Option<Cow<'a, [u8]>> {
-        &self.resource.in_memory_extension_module_shared_library
+    /// Resolve the data of the in-memory shared library of this extension module.
+    pub fn in_memory_extension_module_shared_library(
+        &self,
+    ) -> Result<Option<Cow<'a, [u8]>>, &'static str> {
+        match &self.resource.in_memory_extension_module_shared_library {
+            Some(data) => Ok(Some(
+                self.resource
+                    .field_data(ResourceField::InMemoryExtensionModuleSharedLibrary, data)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Describe where this module will be loaded from.
+    ///
+    /// Returns one of `builtin`, `frozen`, `in-memory`, or `filesystem-relative`.
+    /// The order of preference mirrors that of `resolve_bytecode()` and module
+    /// creation.
+    pub fn location(&self, optimize_level: OptimizeLevel) -> &'static str {
+        match self.flavor {
+            ModuleFlavor::Builtin => "builtin",
+            ModuleFlavor::Frozen => "frozen",
+            ModuleFlavor::Extension => {
+                if self
+                    .resource
+                    .in_memory_extension_module_shared_library
+                    .is_some()
+                {
+                    "in-memory"
+                } else {
+                    "filesystem-relative"
+                }
+            }
+            ModuleFlavor::SourceBytecode => {
+                let in_memory_bytecode = match optimize_level {
+                    OptimizeLevel::Zero => &self.resource.in_memory_bytecode,
+                    OptimizeLevel::One => &self.resource.in_memory_bytecode_opt1,
+                    OptimizeLevel::Two => &self.resource.in_memory_bytecode_opt2,
+                };
+
+                if in_memory_bytecode.is_some() {
+                    "in-memory"
+                } else if self.bytecode_path(optimize_level).is_some() {
+                    "filesystem-relative"
+                } else if self.resource.in_memory_source.is_some() {
+                    "in-memory"
+                } else {
+                    "filesystem-relative"
+                }
+            }
+        }
+    }
+}
+
+/// Packed resources data whose resources are resolved on demand.
+///
+/// The data has a lookup table allowing resources to be resolved by name
+/// without parsing the entire resources index.
+#[derive(Debug)]
+struct LazyResources<'a, X>
+where
+    [X]: ToOwned<Owned = Vec<X>>,
+{
+    /// Parser of the data.
+    parser: ResourceParserIterator<'a>,
+
+    /// Resources resolved from each entry of the lookup table.
+    resources: Vec<OnceCell<Resource<'a, X>>>,
+}
+
+impl<'a> LazyResources<'a, u8> {
+    fn new(parser: ResourceParserIterator<'a>) -> Self {
+        let mut resources = Vec::with_capacity(parser.lookup_table_len());
+        resources.resize_with(parser.lookup_table_len(), OnceCell::new);
+
+        Self { parser, resources }
+    }
+
+    /// Resolve the resource of a lookup table entry.
+    fn resolve_entry(&self, entry: usize) -> Option<&Resource<'a, u8>> {
+        let cell = &self.resources[entry];
+
+        if let Some(resource) = cell.get() {
+            return Some(resource);
+        }
+
+        // Entries are validated when the data is loaded. So resolving an
+        // entry in bounds always succeeds.
+        let resource = self
+            .parser
+            .resolve_resource_entry(entry)
+            .expect("lookup table entries are validated at load time")?;
+
+        Some(cell.get_or_init(|| resource))
+    }
+
+    /// Resolve a named resource.
+    fn get(&self, name: &str) -> Option<&Resource<'a, u8>> {
+        self.resolve_entry(self.parser.find_resource_entry(name)?)
+    }
+
+    /// Remove a named resource, returning it if it exists.
+    ///
+    /// The resource is parsed again if it is resolved afterwards.
+    fn take(&mut self, name: &str) -> Option<Resource<'a, u8>> {
+        let entry = self.parser.find_resource_entry(name)?;
+
+        self.resolve_entry(entry)?;
+        self.resources[entry].take()
     }
 }
 
@@ -370,7 +554,46 @@ where
     pub origin: PathBuf,
 
     /// Named resources available for loading.
+    ///
+    /// Resources from packed resources data having a lookup table aren't
+    /// stored here. Use `get_resource()` and `all_resources()` to access
+    /// all known resources.
+    ///
+    /// Entries take precedence over resources in `lazy_sources`.
     pub resources: HashMap<Cow<'a, str>, Resource<'a, X>>,
+
+    /// Packed resources data whose resources are resolved on demand.
+    ///
+    /// Data loaded later takes precedence.
+    lazy_sources: Vec<LazyResources<'a, X>>,
+
+    /// Directory in which in-memory package resources can be materialized.
+    ///
+    /// If set, package resources are written to a directory specific to this
+    /// instance within this directory when a filesystem path to them is
+    /// requested.
+    pub materialization_directory: Option<PathBuf>,
+
+    /// Directory holding materialized package resources, once created.
+    materialized_resources_directory: RefCell<Option<MaterializedResourcesDirectory>>,
+
+    /// Decompressed `in_memory_compressed_source` of modules, keyed by module name.
+    ///
+    /// Cleared whenever resources are added, as they may replace existing ones.
+    decompressed_sources: RefCell<HashMap<String, Vec<u8>>>,
+}
+
+/// A directory holding materialized package resources.
+///
+/// The directory is deleted when this instance is dropped.
+#[derive(Debug)]
+struct MaterializedResourcesDirectory(PathBuf);
+
+impl Drop for MaterializedResourcesDirectory {
+    fn drop(&mut self) {
+        // There is nobody to report errors to at this point.
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
 }
 
 impl<'a> Default for PythonResourcesState<'a, u8> {
@@ -379,6 +602,10 @@ impl<'a> Default for PythonResourcesState<'a, u8> {
             current_exe: PathBuf::new(),
             origin: PathBuf::new(),
             resources: HashMap::new(),
+            lazy_sources: vec![],
+            materialization_directory: None,
+            materialized_resources_directory: RefCell::new(None),
+            decompressed_sources: RefCell::new(HashMap::new()),
         }
     }
 }
@@ -395,7 +622,7 @@ impl<'a> PythonResourcesState<'a, u8> {
         Ok(Self {
             current_exe: exe,
             origin,
-            resources: Default::default(),
+            ..Self::default()
         })
     }
 
@@ -420,18 +647,76 @@ impl<'a> PythonResourcesState<'a, u8> {
         &mut self,
         resource: Resource<'resource, u8>,
     ) -> Result<(), &'static str> {
+        self.decompressed_sources.get_mut().clear();
         self.resources.insert(resource.name.clone(), resource);
 
         Ok(())
     }
 
+    /// Resolve a named resource.
+    ///
+    /// Resources from packed resources data having a lookup table are
+    /// parsed the first time they are requested.
+    pub fn get_resource(&self, name: &str) -> Option<&Resource<'a, u8>> {
+        if let Some(resource) = self.resources.get(name) {
+            return Some(resource);
+        }
+
+        self.lazy_sources
+            .iter()
+            .rev()
+            .find_map(|source| source.get(name))
+    }
+
+    /// Obtain references to all known resources.
+    ///
+    /// This parses all resources not yet resolved from packed resources data
+    /// having a lookup table.
+    pub fn all_resources(&self) -> Vec<&Resource<'a, u8>> {
+        let mut resources = self.resources.values().collect::<Vec<_>>();
+        let mut seen = self
+            .resources
+            .keys()
+            .map(|name| name.as_ref())
+            .collect::<HashSet<_>>();
+
+        for source in self.lazy_sources.iter().rev() {
+            for entry in 0..source.resources.len() {
+                if let Some(resource) = source.resolve_entry(entry) {
+                    if seen.insert(resource.name.as_ref()) {
+                        resources.push(resource);
+                    }
+                }
+            }
+        }
+
+        resources
+    }
+
+    /// Move a lazily resolved resource into `resources` so it can be mutated.
+    fn materialize_resource(&mut self, name: &str) {
+        if self.resources.contains_key(name) {
+            return;
+        }
+
+        let resource = self
+            .lazy_sources
+            .iter_mut()
+            .rev()
+            .find_map(|source| source.take(name));
+
+        if let Some(resource) = resource {
+            self.resources.insert(resource.name.clone(), resource);
+        }
+    }
+
     /// Attempt to resolve an importable Python module.
     pub fn resolve_importable_module(
         &self,
         name: &str,
         optimize_level: OptimizeLevel,
     ) -> Option<ImportablePythonModule<u8>> {
-        let resource = match self.resources.get(name) {
+        let resource = match self.get_resource(name) {
             Some(entry) => entry,
             None => return None,
         };
@@ -467,6 +752,7 @@ impl<'a> PythonResourcesState<'a, u8> {
                 resource,
                 current_exe: &self.current_exe,
                 origin: &self.origin,
+                decompressed_sources: &self.decompressed_sources,
                 flavor: ModuleFlavor::Builtin,
                 is_package: resource.is_package,
             })
@@ -475,6 +761,7 @@ impl<'a> PythonResourcesState<'a, u8> {
                 resource,
                 current_exe: &self.current_exe,
                 origin: &self.origin,
+                decompressed_sources: &self.decompressed_sources,
                 flavor: ModuleFlavor::Frozen,
                 is_package: resource.is_package,
             })
@@ -483,6 +770,7 @@ impl<'a> PythonResourcesState<'a, u8> {
                 resource,
                 current_exe: &self.current_exe,
                 origin: &self.origin,
+                decompressed_sources: &self.decompressed_sources,
                 flavor: ModuleFlavor::Extension,
                 is_package: resource.is_package,
             })
@@ -492,6 +780,7 @@ impl<'a> PythonResourcesState<'a, u8> {
                     resource,
                     current_exe: &self.current_exe,
                     origin: &self.origin,
+                    decompressed_sources: &self.decompressed_sources,
                     flavor: ModuleFlavor::SourceBytecode,
                     is_package: resource.is_package,
                 })
@@ -514,17 +803,26 @@ impl<'a> PythonResourcesState<'a, u8> {
         package: &str,
         resource_name: &str,
     ) -> PyResult<Option<PyObject>> {
-        let entry = match self.resources.get(package) {
+        let entry = match self.get_resource(package) {
             Some(entry) => entry,
             None => return Ok(None),
         };
 
         if let Some(resources) = &entry.in_memory_package_resources {
             if let Some(data) = resources.get(resource_name) {
+                let data = entry
+                    .field_data(ResourceField::InMemoryResourcesData, data)
+                    .map_err(|e| {
+                        PyErr::new::<OSError, _>(
+                            py,
+                            format!("error resolving resource {}: {}", resource_name, e),
+                        )
+                    })?;
+
                 let io_module = py.import("io")?;
                 let bytes_io = io_module.get(py, "BytesIO")?;
 
-                let data = PyBytes::new(py, data);
+                let data = PyBytes::new(py, &data);
                 return Ok(Some(bytes_io.call(py, (data,), None)?));
             }
         }
@@ -548,7 +846,7 @@ impl<'a> PythonResourcesState<'a, u8> {
 
     /// Determines whether a specific package + name pair is a known Python package resource.
     pub fn is_package_resource(&self, package: &str, resource_name: &str) -> bool {
-        if let Some(entry) = self.resources.get(package) {
+        if let Some(entry) = self.get_resource(package) {
             if let Some(resources) = &entry.in_memory_package_resources {
                 if resources.contains_key(resource_name) {
                     return true;
@@ -569,7 +867,7 @@ impl<'a> PythonResourcesState<'a, u8> {
     ///
     /// The names are returned in sorted order.
     pub fn package_resource_names(&self, py: Python, package: &str) -> PyResult<PyObject> {
-        let entry = match self.resources.get(package) {
+        let entry = match self.get_resource(package) {
             Some(entry) => entry,
             None => return Ok(PyList::new(py, &[]).into_object()),
         };
@@ -592,6 +890,114 @@ impl<'a> PythonResourcesState<'a, u8> {
         Ok(PyList::new(py, &names).into_object())
     }
 
+    /// Obtain a filesystem path to a single named resource in a package.
+    ///
+    /// Filesystem relative resources resolve to their existing path. In-memory
+    /// resources are written to a directory within `materialization_directory`
+    /// the first time they are requested. `Ok(None)` is returned if the resource
+    /// does not exist or can't be materialized.
+    pub fn resolve_package_resource_path(
+        &self,
+        package: &str,
+        resource_name: &str,
+    ) -> std::io::Result<Option<PathBuf>> {
+        let entry = match self.get_resource(package) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        if let Some(resources) = &entry.relative_path_package_resources {
+            if let Some(path) = resources.get(resource_name) {
+                return Ok(Some(self.origin.join(path)));
+            }
+        }
+
+        let data = match &entry.in_memory_package_resources {
+            Some(resources) => match resources.get(resource_name) {
+                Some(data) => data,
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+
+        let root = match &self.materialization_directory {
+            Some(root) => root,
+            None => return Ok(None),
+        };
+
+        // Resource names are normalized to POSIX separators. Don't allow them
+        // to escape the materialization directory.
+        if resource_name
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..")
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("illegal resource name: {}", resource_name),
+            ));
+        }
+
+        let mut materialized = self.materialized_resources_directory.borrow_mut();
+
+        let mut path = match materialized.as_ref() {
+            Some(directory) => directory.0.clone(),
+            None => {
+                let path = root.join(format!("pyembed-resources-{}", uuid::Uuid::new_v4()));
+                std::fs::create_dir_all(&path)?;
+                materialized.replace(MaterializedResourcesDirectory(path.clone()));
+
+                path
+            }
+        };
+
+        path.extend(package.split('.'));
+        path.extend(resource_name.split('/'));
+
+        // Resources are immutable. So only write them once.
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            let data = entry
+                .field_data(ResourceField::InMemoryResourcesData, data)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+            std::fs::write(&path, data)?;
+        }
+
+        Ok(Some(path))
+    }
+
+    /// Obtain the names of all resources in a Python package.
+    ///
+    /// Names of in-memory and filesystem relative resources are combined.
+    /// The names are returned in sorted order.
+    pub fn package_resource_names_all(&self, package: &str) -> Vec<&str> {
+        let entry = match self.get_resource(package) {
+            Some(entry) => entry,
+            None => return vec![],
+        };
+
+        let mut names = entry
+            .in_memory_package_resources
+            .iter()
+            .flat_map(|resources| resources.keys())
+            .chain(
+                entry
+                    .relative_path_package_resources
+                    .iter()
+                    .flat_map(|resources| resources.keys()),
+            )
+            .map(|name| name.as_ref())
+            .collect::<Vec<_>>();
+
+        names.sort_unstable();
+        names.dedup();
+
+        names
+    }
+
     /// Attempt to resolve a PyBytes for resource data given a relative path.
     ///
     /// Raises OSerror on failure.
@@ -696,11 +1102,15 @@ impl<'a> PythonResourcesState<'a, u8> {
             let resource_name = name_parts.join("/");
             let resource_name_ref: &str = &resource_name;
 
-            if let Some(entry) = self.resources.get(package_name_ref) {
+            if let Some(entry) = self.get_resource(package_name_ref) {
                 if check_in_memory {
                     if let Some(resources) = &entry.in_memory_package_resources {
                         if let Some(data) = resources.get(resource_name_ref) {
-                            return Ok(PyBytes::new(py, data).into_object());
+                            let data = entry
+                                .field_data(ResourceField::InMemoryResourcesData, data)
+                                .map_err(|e| PyErr::new::<OSError, _>(py, (libc::EIO, e, path)))?;
+
+                            return Ok(PyBytes::new(py, &data).into_object());
                         }
                     }
                 }
@@ -743,23 +1153,40 @@ impl<'a> PythonResourcesState<'a, u8> {
     /// Obtain a PyList of pkgutil.ModuleInfo for known resources.
     ///
     /// This is intended to be used as the implementation for Finder.iter_modules().
+    ///
+    /// If `package` is defined, only the direct children of that package are
+    /// returned and they are named relative to it. An empty `package` refers to
+    /// top-level modules.
     pub fn pkgutil_modules_infos(
         &self,
         py: Python,
+        package: Option<&str>,
         prefix: Option<String>,
         optimize_level: OptimizeLevel,
     ) -> PyResult<PyObject> {
         let infos: PyResult<Vec<PyObject>> = self
-            .resources
-            .values()
+            .all_resources()
+            .into_iter()
             .filter(|r| {
                 r.is_extension_module || (r.is_module && is_module_importable(r, optimize_level))
             })
-            .map(|r| {
+            .filter_map(|r| {
+                let name = match package {
+                    Some(package) => match r.name.rfind('.') {
+                        Some(pos) if &r.name[0..pos] == package => &r.name[pos + 1..],
+                        None if package.is_empty() => &r.name,
+                        _ => return None,
+                    },
+                    None => &r.name,
+                };
+
+                Some((r, name))
+            })
+            .map(|(r, name)| {
                 let name = if let Some(prefix) = &prefix {
-                    format!("{}{}", prefix, r.name)
+                    format!("{}{}", prefix, name)
                 } else {
-                    r.name.to_string()
+                    name.to_string()
                 };
 
                 let name = name.to_py_object(py).into_object();
@@ -795,6 +1222,7 @@ impl<'a> PythonResourcesState<'a, u8> {
 
             // Module can be defined by embedded resources data. If exists, just
             // update the big.
+            self.materialize_resource(name_str);
             if let Some(mut entry) = self.resources.get_mut(name_str) {
                 entry.is_builtin_extension_module = true;
             } else {
@@ -831,6 +1259,7 @@ impl<'a> PythonResourcesState<'a, u8> {
 
             // Module can be defined by embedded resources data. If exists, just
             // update the big.
+            self.materialize_resource(name_str);
             if let Some(mut entry) = self.resources.get_mut(name_str) {
                 entry.is_frozen_module = true;
             } else {
@@ -849,9 +1278,24 @@ impl<'a> PythonResourcesState<'a, u8> {
     }
 
     /// Load resources by parsing a blob.
-    fn load_resources(&mut self, data: &'a [u8]) -> Result<(), &'static str> {
+    ///
+    /// Resources replace existing entries having the same name.
+    pub fn load_resources(&mut self, data: &'a [u8]) -> Result<(), &'static str> {
         let resources = python_packed_resources::parser::load_resources(data)?;
 
+        self.decompressed_sources.get_mut().clear();
+
+        // Data having a lookup table is resolved on demand. This avoids the
+        // overhead of parsing every resource at startup. Existing resources
+        // having the same name are replaced by removing them, as they would
+        // otherwise take precedence.
+        if resources.has_lookup_table() {
+            self.resources
+                .retain(|name, _| resources.find_resource_entry(name).is_none());
+            self.lazy_sources.push(LazyResources::new(resources));
+            return Ok(());
+        }
+
         // Reserve space for expected number of incoming items so we can avoid extra
         // allocations.
         self.resources.reserve(resources.expected_resources_count());
@@ -875,8 +1319,8 @@ impl<'a> PythonResourcesState<'a, u8> {
         ignore_frozen: bool,
     ) -> Result<Vec<u8>> {
         let mut resources = self
-            .resources
-            .values()
+            .all_resources()
+            .into_iter()
             .filter(|resource| {
                 // This assumes builtins and frozen are mutually exclusive with other types.
                 !((resource.is_builtin_extension_module && ignore_builtin)
@@ -1028,6 +1472,21 @@ py_class!(pub class OxidizedResource |py| {
         }
     }
 
+    @property def in_memory_compressed_source(&self) -> PyResult<Option<PyBytes>> {
+        Ok(self.resource(py).borrow().in_memory_compressed_source.as_ref().map(|x| PyBytes::new(py, x)))
+    }
+
+    @in_memory_compressed_source.setter def set_in_memory_compressed_source(&self, value: Option<PyObject>) -> PyResult<()> {
+        if let Some(value) = value {
+            self.resource(py).borrow_mut().in_memory_compressed_source =
+                pyobject_to_owned_bytes_optional(py, &value)?
+                    .map(Cow::Owned);
+            Ok(())
+        } else {
+            Err(PyErr::new::<TypeError, _>(py, "cannot delete in_memory_compressed_source"))
+        }
+    }
+
     @property def in_memory_bytecode(&self) -> PyResult<Option<PyBytes>> {
         Ok(self.resource(py).borrow().in_memory_bytecode.as_ref().map(|x| PyBytes::new(py, x)))
     }
@@ -1313,11 +1772,39 @@ py_class!(pub class OxidizedResource |py| {
         }
     }
 
+    @property def annotations(&self) -> PyResult<Option<HashMap<String, PyBytes>>> {
+        Ok(self.resource(py).borrow().annotations.as_ref().map(|x| {
+            HashMap::from_iter(x.iter().map(|(k, v)| (k.to_string(), PyBytes::new(py, v))))
+        }))
+    }
+
+    @annotations.setter def set_annotations(&self, value: Option<PyObject>) -> PyResult<()> {
+        if let Some(value) = value {
+            self.resource(py).borrow_mut().annotations =
+                pyobject_optional_resources_map_to_owned_bytes(py, &value)?
+                    .map(|x| HashMap::from_iter(
+                        x.iter().map(|(k, v)| (Cow::Owned(k.to_owned()), Cow::Owned(v.to_owned())))
+                     ));
+
+            Ok(())
+        } else {
+            Err(PyErr::new::<TypeError, _>(py, "cannot delete annotations"))
+        }
+    }
+
 });
 
 /// Convert a Resource to an OxidizedResource.
 pub fn resource_to_pyobject(py: Python, resource: &Resource<u8>) -> PyResult<PyObject> {
-    let resource = OxidizedResource::create_instance(py, RefCell::new(resource.to_owned()))?;
+    let mut resource = resource.to_owned();
+    resource.decompress().map_err(|e| {
+        PyErr::new::<ValueError, _>(
+            py,
+            format!("error decompressing resource {}: {}", resource.name, e),
+        )
+    })?;
+
+    let resource = OxidizedResource::create_instance(py, RefCell::new(resource))?;
 
     Ok(resource.into_object())
 }
@@ -21,8 +21,8 @@ There exist several
 CPython defines multiple memory allocator *domains* and it is possible to
 use a custom memory allocator for each using the `PyMem_SetAllocator()` API.
 
-We support having the *raw* memory allocator use either `jemalloc`, Rust's
-global allocator, or the system allocator.
+We support having the *raw* memory allocator use either `jemalloc`,
+`mimalloc`, `snmalloc`, Rust's global allocator, or the system allocator.
 
 The `pyalloc` module defines types that serve as interfaces between the
 `jemalloc` library and Rust's allocator. The reason we call into
@@ -31,6 +31,13 @@ why involve an extra layer of abstraction when it isn't needed. To register
 a custom allocator, we simply instantiate an instance of the custom allocator
 type and tell Python about it via `PyMem_SetAllocator()`.
 
+Rust's allocator requires the size and alignment of an allocation when
+freeing or reallocating it. But Python's allocator API only passes a pointer.
+So the Rust allocator stores the size of each allocation in a header
+preceding the memory handed to Python. This avoids a shared lookup table,
+which would require synchronization since Python may call the raw
+allocator from multiple threads without holding the GIL.
+
 # Module Importing
 
 The module importing mechanisms provided by this crate are one of the
@@ -0,0 +1,204 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    crate::{BytecodeOptimizationLevel, OxidizedPythonInterpreterConfig},
+    std::path::PathBuf,
+};
+
+/// Create an empty directory for a test.
+fn test_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir()
+        .join(format!("pyembed-config-overrides-{}", std::process::id()))
+        .join(name);
+
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    std::fs::create_dir_all(&path).unwrap();
+
+    path
+}
+
+fn new_config<'a>(origin: &PathBuf, filename: &str) -> OxidizedPythonInterpreterConfig<'a> {
+    let mut config = OxidizedPythonInterpreterConfig::default();
+    config.origin = Some(origin.clone());
+    config.config_overrides_path = Some(PathBuf::from(format!("$ORIGIN/{}", filename)));
+    config.config_overrides_allowed_fields = Some(vec![
+        "module_search_paths".to_string(),
+        "optimization_level".to_string(),
+        "run_module".to_string(),
+        "sys_frozen".to_string(),
+    ]);
+
+    config
+}
+
+#[test]
+fn test_missing_file() {
+    let dir = test_dir("missing");
+    let mut config = new_config(&dir, "overrides.toml");
+
+    assert_eq!(config.apply_config_overrides(), Ok(None));
+    assert_eq!(config.interpreter_config.run_module, None);
+
+    // Nothing is applied unless a path is configured.
+    std::fs::write(dir.join("overrides.toml"), "run_module = \"foo\"\n").unwrap();
+    config.config_overrides_path = None;
+    assert_eq!(config.apply_config_overrides(), Ok(None));
+    assert_eq!(config.interpreter_config.run_module, None);
+}
+
+#[test]
+fn test_toml() {
+    let dir = test_dir("toml");
+    let path = dir.join("overrides.toml");
+    std::fs::write(
+        &path,
+        "module_search_paths = [\"$ORIGIN/lib\"]\n\
+         optimization_level = 2\n\
+         run_module = \"myapp.main\"\n\
+         sys_frozen = true\n",
+    )
+    .unwrap();
+
+    let mut config = new_config(&dir, "overrides.toml");
+    assert_eq!(config.apply_config_overrides(), Ok(Some(path)));
+
+    assert_eq!(
+        config.interpreter_config.module_search_paths,
+        Some(vec![PathBuf::from("$ORIGIN/lib")])
+    );
+    assert_eq!(
+        config.interpreter_config.optimization_level,
+        Some(BytecodeOptimizationLevel::Two)
+    );
+    assert_eq!(
+        config.interpreter_config.run_module,
+        Some("myapp.main".to_string())
+    );
+    assert!(config.sys_frozen);
+
+    // $ORIGIN in overridden values is expanded by resolve().
+    let config = config.resolve().unwrap();
+    assert_eq!(
+        config.interpreter_config.module_search_paths,
+        Some(vec![dir.join("lib")])
+    );
+}
+
+#[test]
+fn test_json() {
+    let dir = test_dir("json");
+    std::fs::write(
+        dir.join("overrides.json"),
+        "{\"run_module\": null, \"optimization_level\": 1}",
+    )
+    .unwrap();
+
+    let mut config = new_config(&dir, "overrides.json");
+    config.interpreter_config.run_module = Some("foo".to_string());
+    assert!(config.apply_config_overrides().unwrap().is_some());
+
+    assert_eq!(config.interpreter_config.run_module, None);
+    assert_eq!(
+        config.interpreter_config.optimization_level,
+        Some(BytecodeOptimizationLevel::One)
+    );
+}
+
+#[test]
+fn test_path_env() {
+    let dir = test_dir("env");
+    let path = dir.join("custom.toml");
+    std::fs::write(&path, "run_module = \"env\"\n").unwrap();
+    std::fs::write(dir.join("overrides.toml"), "run_module = \"sidecar\"\n").unwrap();
+
+    let mut config = new_config(&dir, "overrides.toml");
+    config.config_overrides_path_env = Some("PYEMBED_TEST_CONFIG_OVERRIDES_PATH".to_string());
+
+    // The environment variable takes precedence over the sidecar file.
+    std::env::set_var("PYEMBED_TEST_CONFIG_OVERRIDES_PATH", &path);
+    assert_eq!(config.apply_config_overrides(), Ok(Some(path)));
+    assert_eq!(
+        config.interpreter_config.run_module,
+        Some("env".to_string())
+    );
+
+    // A file referenced by the environment variable must exist.
+    std::env::set_var(
+        "PYEMBED_TEST_CONFIG_OVERRIDES_PATH",
+        dir.join("missing.toml"),
+    );
+    assert!(config.apply_config_overrides().is_err());
+    std::env::remove_var("PYEMBED_TEST_CONFIG_OVERRIDES_PATH");
+}
+
+#[test]
+fn test_path_env_isolated() {
+    let dir = test_dir("env-isolated");
+    let path = dir.join("custom.toml");
+    std::fs::write(&path, "run_module = \"env\"\n").unwrap();
+    std::fs::write(dir.join("overrides.toml"), "run_module = \"sidecar\"\n").unwrap();
+
+    std::env::set_var("PYEMBED_TEST_CONFIG_OVERRIDES_PATH_ISOLATED", &path);
+
+    // The environment variable is ignored if the interpreter doesn't use the
+    // environment.
+    let mut config = new_config(&dir, "overrides.toml");
+    config.config_overrides_path_env =
+        Some("PYEMBED_TEST_CONFIG_OVERRIDES_PATH_ISOLATED".to_string());
+    config.interpreter_config.isolated = Some(true);
+    assert_eq!(
+        config.apply_config_overrides(),
+        Ok(Some(dir.join("overrides.toml")))
+    );
+    assert_eq!(
+        config.interpreter_config.run_module,
+        Some("sidecar".to_string())
+    );
+
+    let mut config = new_config(&dir, "overrides.toml");
+    config.config_overrides_path_env =
+        Some("PYEMBED_TEST_CONFIG_OVERRIDES_PATH_ISOLATED".to_string());
+    config.interpreter_config.use_environment = Some(false);
+    assert_eq!(
+        config.apply_config_overrides(),
+        Ok(Some(dir.join("overrides.toml")))
+    );
+
+    std::env::remove_var("PYEMBED_TEST_CONFIG_OVERRIDES_PATH_ISOLATED");
+}
+
+#[test]
+fn test_errors() {
+    let dir = test_dir("errors");
+    let mut config = new_config(&dir, "overrides.toml");
+    config.interpreter_config.run_module = Some("original".to_string());
+
+    for content in &[
+        // Not in the allow-list.
+        "run_module = \"foo\"\nverbose = true\n",
+        // Wrong type.
+        "run_module = 42\n",
+        // Invalid value.
+        "optimization_level = 3\n",
+        // Invalid syntax.
+        "run_module = \n",
+    ] {
+        std::fs::write(dir.join("overrides.toml"), content).unwrap();
+        assert!(config.apply_config_overrides().is_err());
+    }
+
+    // Fields that can never be overridden are rejected even if allowed.
+    config.config_overrides_allowed_fields = Some(vec!["packed_resources".to_string()]);
+    std::fs::write(dir.join("overrides.toml"), "packed_resources = []\n").unwrap();
+    assert!(config.apply_config_overrides().is_err());
+
+    // Nothing is applied when an error occurs.
+    assert_eq!(
+        config.interpreter_config.run_module,
+        Some("original".to_string())
+    );
+}
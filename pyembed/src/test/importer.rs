@@ -3,11 +3,12 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
-    crate::{MainPythonInterpreter, OxidizedPythonInterpreterConfig},
+    crate::{MainPythonInterpreter, OxidizedPythonInterpreterConfig, PackedResourcesSource},
     anyhow::{anyhow, Result},
-    cpython::{ObjectProtocol, PyObject},
+    cpython::{ObjectProtocol, PyBytes, PyDict, PyObject},
+    python_packed_resources::data::{Resource, ResourceFlavor},
     rusty_fork::rusty_fork_test,
-    std::path::PathBuf,
+    std::{borrow::Cow, collections::HashMap, path::PathBuf},
 };
 
 fn new_interpreter<'python, 'interpreter, 'resources>(
@@ -106,6 +107,134 @@ rusty_fork_test! {
         );
     }
 
+    /// Packed resources can be loaded from a memory mapped file.
+    #[test]
+    fn packed_resources_memory_mapped_path() {
+        let resource = Resource::<u8> {
+            flavor: ResourceFlavor::Module,
+            name: Cow::Borrowed("memory_mapped_module"),
+            is_module: true,
+            in_memory_source: Some(Cow::Borrowed(b"VALUE = 42")),
+            ..Resource::default()
+        };
+
+        let mut data = Vec::new();
+        python_packed_resources::writer::write_packed_resources_v3(&[resource], &mut data, None)
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!("pyembed-test-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, &data).unwrap();
+
+        let mut config = OxidizedPythonInterpreterConfig::default();
+        config.interpreter_config.parse_argv = Some(false);
+        config.oxidized_importer = true;
+        config.set_missing_path_configuration = false;
+        config.packed_resources = vec![PackedResourcesSource::MemoryMappedPath(path.clone())];
+        let mut interp = MainPythonInterpreter::new(config).unwrap();
+
+        {
+            let py = interp.acquire_gil().unwrap();
+            let module = py.import("memory_mapped_module").unwrap();
+            let value = module.get(py, "VALUE").unwrap().extract::<i64>(py).unwrap();
+            assert_eq!(value, 42);
+        }
+
+        std::mem::drop(interp);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// A missing packed resources file fails interpreter initialization.
+    #[test]
+    fn packed_resources_memory_mapped_path_missing() {
+        let mut config = OxidizedPythonInterpreterConfig::default();
+        config.interpreter_config.parse_argv = Some(false);
+        config.oxidized_importer = true;
+        config.set_missing_path_configuration = false;
+        config.packed_resources = vec![PackedResourcesSource::MemoryMappedPath(
+            PathBuf::from("$ORIGIN/does-not-exist"),
+        )];
+
+        assert!(MainPythonInterpreter::new(config).is_err());
+    }
+
+    /// In-memory package resources are materialized to the filesystem.
+    #[test]
+    fn resources_materialization_directory() {
+        let mut resources = HashMap::new();
+        resources.insert(Cow::Borrowed("resource.txt"), Cow::Borrowed(b"my resource".as_ref()));
+
+        let resource = Resource::<u8> {
+            flavor: ResourceFlavor::Module,
+            name: Cow::Borrowed("materialized_package"),
+            is_module: true,
+            is_package: true,
+            in_memory_source: Some(Cow::Borrowed(b"")),
+            in_memory_package_resources: Some(resources),
+            ..Resource::default()
+        };
+
+        let mut data = Vec::new();
+        python_packed_resources::writer::write_packed_resources_v3(&[resource], &mut data, None)
+            .unwrap();
+
+        let root = std::env::temp_dir().join(format!("pyembed-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&root).unwrap();
+
+        let mut config = OxidizedPythonInterpreterConfig::default();
+        config.interpreter_config.parse_argv = Some(false);
+        config.oxidized_importer = true;
+        config.set_missing_path_configuration = false;
+        config.packed_resources = vec![PackedResourcesSource::Memory(&data)];
+        config.resources_materialization_directory = Some(root.clone());
+        let mut interp = MainPythonInterpreter::new(config).unwrap();
+
+        let path = {
+            let py = interp.acquire_gil().unwrap();
+            let locals = PyDict::new(py);
+            py.run(
+                "import importlib.resources\n\
+                 with importlib.resources.path('materialized_package', 'resource.txt') as p:\n    \
+                     path = str(p)\n    \
+                     with open(p, 'rb') as fh:\n        \
+                         data = fh.read()\n",
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+
+            let data = locals
+                .get_item(py, "data")
+                .unwrap()
+                .cast_into::<PyBytes>(py)
+                .unwrap();
+            assert_eq!(data.data(py), b"my resource");
+
+            PathBuf::from(locals.get_item(py, "path").unwrap().extract::<String>(py).unwrap())
+        };
+
+        let directories = std::fs::read_dir(&root)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(directories.len(), 1);
+        assert!(directories[0]
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("pyembed-resources-"));
+        assert_eq!(
+            path,
+            directories[0].join("materialized_package").join("resource.txt")
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), b"my resource");
+
+        // Materialized resources are removed when the interpreter shuts down.
+        std::mem::drop(interp);
+        assert_eq!(std::fs::read_dir(&root).unwrap().count(), 0);
+
+        std::fs::remove_dir(&root).unwrap();
+    }
+
     /// Run test_importer_builtins.py.
     #[test]
     fn builtins_py() {
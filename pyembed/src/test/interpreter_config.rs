@@ -384,4 +384,24 @@ rusty_fork_test! {
 
         assert_eq!(config.tcl_library, Some(origin.join("lib").join("tcl8.6")));
     }
+
+    #[test]
+    fn test_resources_materialization_directory_origin() {
+        let mut config = OxidizedPythonInterpreterConfig::default();
+        config.resources_materialization_directory =
+            Some(PathBuf::from("$ORIGIN").join("resources"));
+
+        let config = config.resolve().unwrap();
+
+        let origin = std::env::current_exe()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .to_path_buf();
+
+        assert_eq!(
+            config.resources_materialization_directory,
+            Some(origin.join("resources"))
+        );
+    }
 }
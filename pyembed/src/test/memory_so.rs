@@ -0,0 +1,35 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::memory_so::{create_memory_file, memory_file_path};
+
+#[test]
+fn test_memory_file_roundtrip() {
+    let data = b"foo bar baz".to_vec();
+
+    let fd = create_memory_file("test", &data).unwrap();
+    let path = memory_file_path(fd);
+    assert_eq!(path, format!("/proc/self/fd/{}", fd));
+    assert_eq!(std::fs::read(&path).unwrap(), data);
+
+    unsafe {
+        libc::close(fd);
+    }
+}
+
+#[test]
+fn test_memory_file_empty() {
+    let fd = create_memory_file("empty", &[]).unwrap();
+    assert!(std::fs::read(memory_file_path(fd)).unwrap().is_empty());
+
+    unsafe {
+        libc::close(fd);
+    }
+}
+
+#[test]
+fn test_memory_file_invalid_name() {
+    let err = create_memory_file("foo\0bar", b"data").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
@@ -2,5 +2,13 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+#[cfg(feature = "config-overrides")]
+mod config_overrides;
 mod importer;
 mod interpreter_config;
+#[cfg(target_os = "linux")]
+mod memory_so;
+mod pyalloc;
+mod python_eval;
+mod python_resources;
+mod sub_interpreter;
@@ -0,0 +1,207 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    crate::{
+        pyalloc::make_raw_rust_memory_allocator, raw_rust_allocator_stats, MainPythonInterpreter,
+        OxidizedPythonInterpreterConfig, PythonRawAllocator, RawAllocatorStats,
+    },
+    cpython::PyDict,
+    rusty_fork::rusty_fork_test,
+};
+
+rusty_fork_test! {
+    #[test]
+    fn test_rust_allocator_stats() {
+        assert_eq!(raw_rust_allocator_stats(), None);
+
+        let allocator = make_raw_rust_memory_allocator().allocator;
+        let ctx = allocator.ctx;
+        let malloc = allocator.malloc.unwrap();
+        let calloc = allocator.calloc.unwrap();
+        let realloc = allocator.realloc.unwrap();
+        let free = allocator.free.unwrap();
+
+        assert_eq!(raw_rust_allocator_stats(), Some(RawAllocatorStats::default()));
+
+        let a = malloc(ctx, 100);
+        assert!(!a.is_null());
+        assert_eq!(a as usize % 16, 0);
+
+        let b = calloc(ctx, 4, 8);
+        assert!(!b.is_null());
+        assert_eq!(unsafe { std::slice::from_raw_parts(b as *const u8, 32) }, &[0; 32]);
+
+        assert_eq!(
+            raw_rust_allocator_stats(),
+            Some(RawAllocatorStats {
+                allocations: 2,
+                total_allocations: 2,
+                bytes: 132,
+                peak_bytes: 132,
+            })
+        );
+
+        unsafe { std::ptr::write_bytes(a as *mut u8, 42, 100) };
+        let a = realloc(ctx, a, 1000);
+        assert!(!a.is_null());
+        assert_eq!(unsafe { std::slice::from_raw_parts(a as *const u8, 100) }, &[42; 100][..]);
+
+        let a = realloc(ctx, a, 10);
+        assert!(!a.is_null());
+
+        assert_eq!(
+            raw_rust_allocator_stats(),
+            Some(RawAllocatorStats {
+                allocations: 2,
+                total_allocations: 2,
+                bytes: 42,
+                peak_bytes: 1032,
+            })
+        );
+
+        free(ctx, a);
+        free(ctx, b);
+        free(ctx, std::ptr::null_mut());
+
+        // Zero sized requests are allocated as 1 byte.
+        let c = malloc(ctx, 0);
+        assert!(!c.is_null());
+
+        // Requests too large to be represented fail without being counted.
+        assert!(malloc(ctx, usize::MAX).is_null());
+        assert!(calloc(ctx, 1, usize::MAX).is_null());
+        assert!(calloc(ctx, usize::MAX, 2).is_null());
+        assert!(realloc(ctx, c, usize::MAX).is_null());
+
+        assert_eq!(
+            raw_rust_allocator_stats(),
+            Some(RawAllocatorStats {
+                allocations: 1,
+                total_allocations: 3,
+                bytes: 1,
+                peak_bytes: 1032,
+            })
+        );
+
+        free(ctx, c);
+    }
+
+    #[test]
+    fn test_rust_allocator_threads() {
+        let allocator = make_raw_rust_memory_allocator().allocator;
+        // The context pointer isn't Send. But the allocator doesn't use it.
+        assert!(allocator.ctx.is_null());
+        let malloc = allocator.malloc.unwrap();
+        let free = allocator.free.unwrap();
+
+        let threads = (0..8)
+            .map(|i| {
+                std::thread::spawn(move || {
+                    let mut ptrs = vec![];
+
+                    for size in 1..500 {
+                        let ptr = malloc(std::ptr::null_mut(), size + i) as usize;
+                        assert_ne!(ptr, 0);
+                        ptrs.push(ptr);
+                    }
+
+                    for ptr in ptrs {
+                        free(std::ptr::null_mut(), ptr as *mut _);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let stats = raw_rust_allocator_stats().unwrap();
+        assert_eq!(stats.allocations, 0);
+        assert_eq!(stats.total_allocations, 8 * 499);
+        assert_eq!(stats.bytes, 0);
+        assert!(stats.peak_bytes > 0);
+    }
+
+    #[test]
+    fn test_raw_allocator_stats_python() {
+        let mut config = OxidizedPythonInterpreterConfig::default();
+        // Otherwise the Rust arguments are interpreted as Python arguments.
+        config.interpreter_config.parse_argv = Some(false);
+        config.set_missing_path_configuration = false;
+        config.raw_allocator = Some(PythonRawAllocator::rust());
+
+        let mut interp = MainPythonInterpreter::new(config).unwrap();
+        let py = interp.acquire_gil().unwrap();
+
+        let stats = py
+            .import("oxidized_importer")
+            .unwrap()
+            .call(py, "raw_allocator_stats", cpython::NoArgs, None)
+            .unwrap()
+            .cast_into::<PyDict>(py)
+            .unwrap();
+
+        let allocations = stats.get_item(py, "allocations").unwrap().extract::<usize>(py).unwrap();
+        let bytes = stats.get_item(py, "bytes").unwrap().extract::<usize>(py).unwrap();
+        let peak_bytes = stats.get_item(py, "peak_bytes").unwrap().extract::<usize>(py).unwrap();
+
+        assert!(allocations > 0);
+        assert!(bytes > 0);
+        assert!(peak_bytes >= bytes);
+    }
+}
+
+/// Exercise the functions of a raw allocator.
+#[cfg(any(feature = "libmimalloc-sys", feature = "snmalloc-sys"))]
+fn exercise_allocator(allocator: python3_sys::PyMemAllocatorEx) {
+    let ctx = allocator.ctx;
+    let malloc = allocator.malloc.unwrap();
+    let calloc = allocator.calloc.unwrap();
+    let realloc = allocator.realloc.unwrap();
+    let free = allocator.free.unwrap();
+
+    assert!(!malloc(ctx, 0).is_null());
+
+    let a = malloc(ctx, 100);
+    assert!(!a.is_null());
+    unsafe { std::ptr::write_bytes(a as *mut u8, 42, 100) };
+
+    let a = realloc(ctx, a, 1000);
+    assert!(!a.is_null());
+    assert_eq!(
+        unsafe { std::slice::from_raw_parts(a as *const u8, 100) },
+        &[42; 100][..]
+    );
+
+    let b = calloc(ctx, 4, 8);
+    assert!(!b.is_null());
+    assert_eq!(
+        unsafe { std::slice::from_raw_parts(b as *const u8, 32) },
+        &[0; 32]
+    );
+    assert!(!calloc(ctx, 0, 8).is_null());
+    assert!(calloc(ctx, usize::MAX, 2).is_null());
+
+    let c = realloc(ctx, std::ptr::null_mut(), 10);
+    assert!(!c.is_null());
+
+    free(ctx, a);
+    free(ctx, b);
+    free(ctx, c);
+    free(ctx, std::ptr::null_mut());
+}
+
+#[cfg(feature = "libmimalloc-sys")]
+#[test]
+fn test_mimalloc_allocator() {
+    exercise_allocator(crate::pyalloc::make_raw_mimalloc_allocator());
+}
+
+#[cfg(feature = "snmalloc-sys")]
+#[test]
+fn test_snmalloc_allocator() {
+    exercise_allocator(crate::pyalloc::make_raw_snmalloc_allocator());
+}
@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    crate::{EvalError, MainPythonInterpreter, OxidizedPythonInterpreterConfig},
+    cpython::{ObjectProtocol, PyDict},
+    rusty_fork::rusty_fork_test,
+};
+
+fn new_interpreter<'python, 'interpreter, 'resources>(
+) -> MainPythonInterpreter<'python, 'interpreter, 'resources> {
+    let mut config = OxidizedPythonInterpreterConfig::default();
+    // Otherwise the Rust arguments are interpreted as Python arguments.
+    config.interpreter_config.parse_argv = Some(false);
+    config.set_missing_path_configuration = false;
+
+    MainPythonInterpreter::new(config).unwrap()
+}
+
+rusty_fork_test! {
+    #[test]
+    fn test_run_code() {
+        let mut interp = new_interpreter();
+
+        let value = interp.run_code("40 + 2").unwrap();
+        let py = interp.acquire_gil().unwrap();
+        assert_eq!(value.extract::<i64>(py).unwrap(), 42);
+
+        let value = interp.run_code("x = 40\nx += 2").unwrap();
+        let py = interp.acquire_gil().unwrap();
+        assert_eq!(value, py.None());
+
+        // State persists across calls.
+        let value = interp.run_code("x").unwrap();
+        let py = interp.acquire_gil().unwrap();
+        assert_eq!(value.extract::<i64>(py).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_run_code_exception() {
+        let mut interp = new_interpreter();
+
+        match interp.run_code("def f():\n    return 1 / 0\nf()") {
+            Err(EvalError::Python { traceback, .. }) => {
+                assert!(traceback.starts_with("Traceback (most recent call last):"));
+                assert!(traceback.contains("in f"));
+                assert!(traceback.ends_with("ZeroDivisionError: division by zero\n"));
+            }
+            _ => panic!("expected a Python exception"),
+        }
+
+        // The interpreter remains usable.
+        let value = interp.run_code("1").unwrap();
+        let py = interp.acquire_gil().unwrap();
+        assert_eq!(value.extract::<i64>(py).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_run_file() {
+        let td = std::env::temp_dir().join(format!("pyembed-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&td).unwrap();
+
+        let path = td.join("script.py");
+        std::fs::write(&path, "value = __name__\n").unwrap();
+
+        let mut interp = new_interpreter();
+        let globals = interp.run_file(&path).unwrap();
+
+        let py = interp.acquire_gil().unwrap();
+        let globals = globals.cast_into::<PyDict>(py).unwrap();
+        let value = globals.get_item(py, "value").unwrap();
+        assert_eq!(value.extract::<String>(py).unwrap(), "__main__");
+
+        std::mem::drop(interp);
+        std::fs::remove_dir_all(&td).unwrap();
+    }
+
+    #[test]
+    fn test_run_module() {
+        let td = std::env::temp_dir().join(format!("pyembed-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&td).unwrap();
+
+        std::fs::write(td.join("eval_module.py"), "value = 42\n").unwrap();
+
+        let mut interp = new_interpreter();
+
+        {
+            let py = interp.acquire_gil().unwrap();
+            let sys = py.import("sys").unwrap();
+            sys.get(py, "path")
+                .unwrap()
+                .call_method(py, "insert", (0, td.display().to_string()), None)
+                .unwrap();
+        }
+
+        let globals = interp.run_module("eval_module").unwrap();
+        let py = interp.acquire_gil().unwrap();
+        let value = globals.get_item(py, "value").unwrap();
+        assert_eq!(value.extract::<i64>(py).unwrap(), 42);
+
+        assert!(matches!(
+            interp.run_module("missing_module"),
+            Err(EvalError::Python { .. })
+        ));
+
+        std::mem::drop(interp);
+        std::fs::remove_dir_all(&td).unwrap();
+    }
+}
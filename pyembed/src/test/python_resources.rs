@@ -0,0 +1,175 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    crate::python_resources::PythonResourcesState,
+    python_packed_resources::{
+        data::{Resource, ResourceFlavor},
+        writer::{write_packed_resources, WriterOptions},
+    },
+    std::{borrow::Cow, collections::HashMap},
+};
+
+fn packed_modules(modules: &[(&str, &str)], lookup_table: bool) -> Vec<u8> {
+    let resources = modules
+        .iter()
+        .map(|(name, source)| Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::Owned(name.to_string()),
+            in_memory_source: Some(Cow::Owned(source.as_bytes().to_vec())),
+            ..Resource::default()
+        })
+        .collect::<Vec<Resource<u8>>>();
+
+    let options = WriterOptions {
+        lookup_table,
+        ..WriterOptions::default()
+    };
+
+    let mut data = Vec::new();
+    write_packed_resources(&resources, &mut data, &options).unwrap();
+
+    data
+}
+
+fn module_source<'a>(state: &'a PythonResourcesState<u8>, name: &str) -> Option<&'a [u8]> {
+    state
+        .get_resource(name)
+        .and_then(|resource| resource.in_memory_source.as_deref())
+}
+
+#[test]
+fn test_load_resources_precedence() {
+    let eager = packed_modules(&[("foo", "eager"), ("bar", "eager")], false);
+    let lazy = packed_modules(&[("foo", "lazy"), ("baz", "lazy")], true);
+    let lazy2 = packed_modules(&[("baz", "lazy2")], true);
+    let eager2 = packed_modules(&[("baz", "eager2")], false);
+
+    let mut state = PythonResourcesState::default();
+
+    state.load_resources(&eager).unwrap();
+    state.load_resources(&lazy).unwrap();
+    assert_eq!(module_source(&state, "foo"), Some(b"lazy".as_ref()));
+    assert_eq!(module_source(&state, "bar"), Some(b"eager".as_ref()));
+    assert_eq!(module_source(&state, "baz"), Some(b"lazy".as_ref()));
+    assert_eq!(module_source(&state, "missing"), None);
+
+    // Previously resolved resources are replaced.
+    state.load_resources(&lazy2).unwrap();
+    assert_eq!(module_source(&state, "baz"), Some(b"lazy2".as_ref()));
+
+    state.load_resources(&eager2).unwrap();
+    assert_eq!(module_source(&state, "baz"), Some(b"eager2".as_ref()));
+
+    let mut resources = state
+        .all_resources()
+        .iter()
+        .map(|resource| {
+            (
+                resource.name.as_ref(),
+                resource.in_memory_source.as_deref().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    resources.sort_unstable();
+
+    assert_eq!(
+        resources,
+        vec![
+            ("bar", b"eager".as_ref()),
+            ("baz", b"eager2".as_ref()),
+            ("foo", b"lazy".as_ref()),
+        ]
+    );
+}
+
+#[test]
+fn test_load_resources_malformed_lookup_table() {
+    let mut data = packed_modules(&[("foo", "source")], true);
+
+    // Make the blob offset at the end of the lookup table record out of bounds.
+    let len = data.len();
+    data[len - 1] ^= 0xff;
+
+    let mut state = PythonResourcesState::default();
+    assert!(state.load_resources(&data).is_err());
+}
+
+#[test]
+fn test_resolve_package_resource_path_materialized() {
+    let mut resources = HashMap::new();
+    resources.insert(
+        Cow::Borrowed("resource.txt"),
+        Cow::Borrowed(b"my resource".as_ref()),
+    );
+    resources.insert(Cow::Borrowed("child/a.txt"), Cow::Borrowed(b"a".as_ref()));
+    resources.insert(
+        Cow::Borrowed("../escape"),
+        Cow::Borrowed(b"escape".as_ref()),
+    );
+
+    let resource = Resource {
+        flavor: ResourceFlavor::Module,
+        name: Cow::Borrowed("foo"),
+        is_package: true,
+        in_memory_package_resources: Some(resources),
+        ..Resource::default()
+    };
+
+    let mut data = Vec::new();
+    write_packed_resources(&[resource], &mut data, &WriterOptions::default()).unwrap();
+
+    let root = std::env::temp_dir().join(format!("pyembed-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir(&root).unwrap();
+
+    let mut state = PythonResourcesState::default();
+    state.load_resources(&data).unwrap();
+
+    // Nothing is materialized without a materialization directory.
+    assert_eq!(
+        state
+            .resolve_package_resource_path("foo", "resource.txt")
+            .unwrap(),
+        None
+    );
+
+    state.materialization_directory = Some(root.clone());
+
+    let path = state
+        .resolve_package_resource_path("foo", "resource.txt")
+        .unwrap()
+        .unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"my resource");
+
+    let directory = path.parent().unwrap().parent().unwrap().to_path_buf();
+    assert_eq!(directory.parent(), Some(root.as_path()));
+    assert!(directory
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .starts_with("pyembed-resources-"));
+
+    let child = state
+        .resolve_package_resource_path("foo", "child/a.txt")
+        .unwrap()
+        .unwrap();
+    assert_eq!(child, directory.join("foo").join("child").join("a.txt"));
+    assert_eq!(std::fs::read(&child).unwrap(), b"a");
+
+    assert_eq!(
+        state
+            .resolve_package_resource_path("foo", "missing")
+            .unwrap(),
+        None
+    );
+    assert!(state
+        .resolve_package_resource_path("foo", "../escape")
+        .is_err());
+
+    // The materialized resources directory is removed with the state.
+    std::mem::drop(state);
+    assert!(!directory.exists());
+
+    std::fs::remove_dir(&root).unwrap();
+}
@@ -0,0 +1,73 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    crate::{python_eval, MainPythonInterpreter, OxidizedPythonInterpreterConfig},
+    cpython::ObjectProtocol,
+    rusty_fork::rusty_fork_test,
+};
+
+fn new_interpreter<'python, 'interpreter, 'resources>(
+) -> MainPythonInterpreter<'python, 'interpreter, 'resources> {
+    let mut config = OxidizedPythonInterpreterConfig::default();
+    // Otherwise the Rust arguments are interpreted as Python arguments.
+    config.interpreter_config.parse_argv = Some(false);
+    config.oxidized_importer = true;
+    config.set_missing_path_configuration = false;
+
+    MainPythonInterpreter::new(config).unwrap()
+}
+
+rusty_fork_test! {
+    /// Sub-interpreters have their own OxidizedFinder and module state.
+    #[test]
+    fn test_sub_interpreter() {
+        let mut interp = new_interpreter();
+        interp.run_code("value = 'main'").unwrap();
+
+        let sub = interp.new_sub_interpreter().unwrap();
+
+        let finder = interp
+            .with_sub_interpreter(sub, |py| {
+                let sys = py.import("sys").unwrap();
+                let meta_path = sys.get(py, "meta_path").unwrap();
+                let finder = meta_path.get_item(py, 0).unwrap();
+
+                finder.get_type(py).name(py).to_string()
+            })
+            .unwrap();
+        assert_eq!(finder, "OxidizedFinder");
+
+        let value = interp
+            .with_sub_interpreter(sub, |py| {
+                python_eval::run_code(py, "value = 'sub'").unwrap();
+                python_eval::run_code(py, "value")
+                    .unwrap()
+                    .extract::<String>(py)
+                    .unwrap()
+            })
+            .unwrap();
+        assert_eq!(value, "sub");
+
+        let value = interp.run_code("value").unwrap();
+        let py = interp.acquire_gil().unwrap();
+        assert_eq!(value.extract::<String>(py).unwrap(), "main");
+
+        interp.end_sub_interpreter(sub).unwrap();
+        assert!(interp.with_sub_interpreter(sub, |_| ()).is_err());
+        assert!(interp.end_sub_interpreter(sub).is_err());
+    }
+
+    /// Sub-interpreters still alive are ended when the main interpreter is dropped.
+    #[test]
+    fn test_sub_interpreters_dropped() {
+        let mut interp = new_interpreter();
+
+        let a = interp.new_sub_interpreter().unwrap();
+        let b = interp.new_sub_interpreter().unwrap();
+        assert_ne!(a, b);
+
+        std::mem::drop(interp);
+    }
+}
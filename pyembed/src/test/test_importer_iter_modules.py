@@ -2,6 +2,7 @@
 # License, v. 2.0. If a copy of the MPL was not distributed with this
 # file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+import os
 import pathlib
 import pkgutil
 import sys
@@ -10,6 +11,7 @@ import unittest
 
 from oxidized_importer import (
     OxidizedFinder,
+    OxidizedPathEntryFinder,
     OxidizedResourceCollector,
     find_resources_in_path,
 )
@@ -24,10 +26,13 @@ class TestImporterIterModules(unittest.TestCase):
 
         self.old_finders = list(sys.meta_path)
         self.old_path = list(sys.path)
+        self.old_path_hooks = list(sys.path_hooks)
 
     def tearDown(self):
         sys.path[:] = self.old_path
         sys.meta_path[:] = self.old_finders
+        sys.path_hooks[:] = self.old_path_hooks
+        sys.path_importer_cache.clear()
 
         self.raw_temp_dir.cleanup()
         del self.raw_temp_dir
@@ -104,6 +109,63 @@ class TestImporterIterModules(unittest.TestCase):
         self.assertEqual(res[0].name, "foomy_package")
         self.assertTrue(res[0].ispkg)
 
+    def _exe_path(self, f, package):
+        # In-memory packages have a __path__ beneath the current executable.
+        path = f.find_spec(package, None).submodule_search_locations[0]
+        return os.path.dirname(path), path
+
+    def test_path_hook(self):
+        p = self._make_package("my_package.child")
+        with (p.parent / "module.py").open("wb"):
+            pass
+
+        f = self._finder_from_td()
+        exe, package_path = self._exe_path(f, "my_package")
+
+        top = f.path_hook(exe)
+        self.assertIsInstance(top, OxidizedPathEntryFinder)
+        self.assertEqual(top.find_spec("my_package").name, "my_package")
+        self.assertIsNone(top.find_spec("my_package.module"))
+        self.assertIsNone(top.find_spec("missing"))
+        self.assertEqual(top.iter_modules(), [("my_package", True)])
+
+        child = f.path_hook(pathlib.Path(package_path))
+        spec = child.find_spec("my_package.module")
+        self.assertEqual(spec.name, "my_package.module")
+        self.assertIs(spec.loader, f)
+        self.assertIsNone(child.find_spec("my_package"))
+        self.assertEqual(
+            sorted(child.iter_modules("my_package.")),
+            [("my_package.child", True), ("my_package.module", False)],
+        )
+
+        with self.assertRaises(ImportError):
+            f.path_hook(str(self.td))
+        with self.assertRaises(ImportError):
+            f.path_hook(os.path.join(exe, "missing"))
+        with self.assertRaises(ImportError):
+            f.path_hook(os.path.join(package_path, "module"))
+
+    def test_iter_modules_path(self):
+        p = self._make_package("my_package.child")
+        with (p.parent / "module.py").open("wb"):
+            pass
+
+        f = self._finder_from_td()
+        exe, package_path = self._exe_path(f, "my_package")
+
+        sys.meta_path = [f]
+        sys.path = [exe]
+        sys.path_hooks.insert(0, f.path_hook)
+        sys.path_importer_cache.clear()
+
+        res = list(pkgutil.iter_modules([exe]))
+        self.assertEqual([m.name for m in res], ["my_package"])
+        self.assertIsInstance(res[0].module_finder, OxidizedPathEntryFinder)
+
+        res = list(pkgutil.iter_modules([package_path]))
+        self.assertEqual(sorted(m.name for m in res), ["child", "module"])
+
 
 if __name__ == "__main__":
     unittest.main(exit=False)
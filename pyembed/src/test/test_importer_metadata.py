@@ -193,6 +193,92 @@ class TestImporterMetadata(unittest.TestCase):
         self.assertIsInstance(requires, list)
         self.assertEqual(requires, ["foo"])
 
+    def test_files_missing(self):
+        self._write_metadata()
+        f = self._finder_from_td()
+
+        dists = f.find_distributions()
+
+        self.assertIsNone(dists[0].files)
+
+    def test_files_record(self):
+        self._write_metadata()
+
+        package_path = self.td / "my_package"
+        package_path.mkdir()
+
+        with (package_path / "__init__.py").open("wb") as fh:
+            fh.write(b"import io\n")
+
+        with (package_path / "data.txt").open("wb") as fh:
+            fh.write(b"resource data")
+
+        record_path = self.td / "my_package-1.0.dist-info" / "RECORD"
+        with record_path.open("w", encoding="utf-8") as fh:
+            fh.write("my_package/__init__.py,sha256=abcd,10\n")
+            fh.write("my_package/data.txt,,\n")
+            fh.write("my_package-1.0.dist-info/METADATA,,\n")
+            fh.write("my_package-1.0.dist-info/RECORD,,\n")
+
+        f = self._finder_from_td()
+        dists = f.find_distributions()
+
+        files = dists[0].files
+        self.assertIsInstance(files, list)
+        self.assertEqual(len(files), 4)
+
+        for p in files:
+            self.assertIsInstance(p, importlib.metadata.PackagePath)
+            self.assertIs(p.dist, dists[0])
+
+        self.assertEqual(str(files[0]), "my_package/__init__.py")
+        self.assertIsInstance(files[0].hash, importlib.metadata.FileHash)
+        self.assertEqual(files[0].hash.mode, "sha256")
+        self.assertEqual(files[0].hash.value, "abcd")
+        self.assertEqual(files[0].size, 10)
+        self.assertEqual(files[0].read_text(), "import io\n")
+
+        self.assertIsNone(files[1].hash)
+        self.assertIsNone(files[1].size)
+        self.assertEqual(files[1].read_binary(), b"resource data")
+
+        self.assertEqual(files[2].read_text(), "Name: my_package\nVersion: 1.0\n")
+
+    def test_files_sources_txt(self):
+        pkginfo_path = self.td / "my_package-1.0.egg-info" / "PKG-INFO"
+        pkginfo_path.parent.mkdir()
+
+        with pkginfo_path.open("w", encoding="utf-8") as fh:
+            fh.write("Name: my_package\n")
+            fh.write("Version: 1.0\n")
+
+        sources_path = self.td / "my_package-1.0.egg-info" / "SOURCES.txt"
+        with sources_path.open("w", encoding="utf-8") as fh:
+            fh.write("my_package/__init__.py\n")
+            fh.write("my_package-1.0.egg-info/PKG-INFO\n")
+
+        f = self._finder_from_td()
+        dists = f.find_distributions()
+
+        files = dists[0].files
+        self.assertEqual(
+            [str(p) for p in files],
+            ["my_package/__init__.py", "my_package-1.0.egg-info/PKG-INFO"],
+        )
+        self.assertEqual(files[1].read_text(), "Name: my_package\nVersion: 1.0\n")
+
+    def test_locate_file_missing(self):
+        self._write_metadata()
+        f = self._finder_from_td()
+
+        dists = f.find_distributions()
+
+        located = dists[0].locate_file("my_package/missing.txt")
+        self.assertFalse(located.exists())
+
+        with self.assertRaises(FileNotFoundError):
+            located.read_bytes()
+
 
 if __name__ == "__main__":
     unittest.main(exit=False)
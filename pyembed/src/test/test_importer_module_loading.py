@@ -9,10 +9,12 @@ import os
 import pathlib
 import sys
 import tempfile
+import traceback
 import unittest
 
 from oxidized_importer import (
     OxidizedFinder,
+    OxidizedResource,
     OxidizedResourceCollector,
     find_resources_in_path,
 )
@@ -143,6 +145,168 @@ class TestImporterModuleLoading(unittest.TestCase):
         with self.assertRaises(ImportError):
             f.get_filename("my_package")
 
+    def test_import_trace_disabled(self):
+        self._make_package("my_package")
+
+        f = self._finder_from_td()
+        self.assertEqual(f.import_trace(), [])
+
+        spec = f.find_spec("my_package", None)
+        m = importlib.util.module_from_spec(spec)
+        f.exec_module(m)
+
+        self.assertEqual(f.import_trace(), [])
+
+    def test_import_trace(self):
+        self._make_package("my_package")
+        self._make_package("other_package")
+
+        f = self._finder_from_td()
+        f.enable_import_tracing()
+
+        self.assertIsNone(f.find_spec("missing_package", None))
+        self.assertEqual(f.import_trace(), [])
+
+        spec = f.find_spec("my_package", None)
+        m = importlib.util.module_from_spec(spec)
+        f.exec_module(m)
+        f.find_spec("other_package", None)
+
+        trace = f.import_trace()
+        self.assertEqual(len(trace), 2)
+        self.assertEqual([e["name"] for e in trace], ["my_package", "other_package"])
+        self.assertEqual(
+            set(trace[0].keys()), {"name", "location", "find_seconds", "exec_seconds"}
+        )
+        self.assertEqual(trace[0]["location"], "in-memory")
+        self.assertIsInstance(trace[0]["find_seconds"], float)
+        self.assertIsInstance(trace[0]["exec_seconds"], float)
+        self.assertIsNone(trace[1]["exec_seconds"])
+
+        f.disable_import_tracing()
+        f.find_spec("my_package", None)
+        self.assertEqual(f.import_trace(), trace)
+
+    def _import_with_finder(self, f, name):
+        sys.meta_path.insert(0, f)
+        try:
+            return importlib.import_module(name)
+        finally:
+            sys.meta_path.remove(f)
+            sys.modules.pop(name, None)
+
+    def test_lazy_module(self):
+        p = self._make_package("lazy_package")
+
+        with (p / "__init__.py").open("wb") as fh:
+            fh.write(b"import sys\nsys.lazy_package_executed = True\nvalue = 42\n")
+
+        f = self._finder_from_td()
+        f.set_lazy_module_prefixes(["lazy_package"])
+
+        try:
+            m = self._import_with_finder(f, "lazy_package")
+            self.assertFalse(hasattr(sys, "lazy_package_executed"))
+
+            self.assertEqual(m.value, 42)
+            self.assertTrue(sys.lazy_package_executed)
+        finally:
+            if hasattr(sys, "lazy_package_executed"):
+                del sys.lazy_package_executed
+
+    def test_lazy_module_prefix_mismatch(self):
+        p = self._make_package("eager_package")
+
+        with (p / "__init__.py").open("wb") as fh:
+            fh.write(b"import sys\nsys.eager_package_executed = True\n")
+
+        f = self._finder_from_td()
+        f.set_lazy_module_prefixes(["eager"])
+
+        try:
+            self._import_with_finder(f, "eager_package")
+            self.assertTrue(sys.eager_package_executed)
+        finally:
+            if hasattr(sys, "eager_package_executed"):
+                del sys.eager_package_executed
+
+    def test_compressed_source_traceback(self):
+        source = b'def f():\n    raise ValueError("boom")\n'
+
+        r = OxidizedResource()
+        r.is_module = True
+        r.name = "compressed_module"
+        r.in_memory_bytecode = marshal.dumps(
+            compile(source, "compressed_module", "exec")
+        )
+        # A zstd frame holding `source`.
+        r.in_memory_compressed_source = (
+            b"(\xb5/\xfd\x04X1\x01\x00" + source + b":\x1f\xcb^"
+        )
+
+        f = OxidizedFinder()
+        f.add_resource(r)
+
+        self.assertEqual(f.get_source("compressed_module"), source.decode("utf-8"))
+
+        m = self._import_with_finder(f, "compressed_module")
+
+        try:
+            m.f()
+        except ValueError:
+            formatted = traceback.format_exc()
+
+        self.assertIn('raise ValueError("boom")', formatted)
+
+    @unittest.skipUnless(
+        sys.platform.startswith("linux"), "loading from memory requires Linux"
+    )
+    def test_extension_module_in_memory(self):
+        # Find an extension module of the running Python that isn't imported yet.
+        candidates = {
+            "_bisect": "bisect_left",
+            "_heapq": "heappush",
+            "_json": "scanstring",
+            "_csv": "reader",
+            "_random": "Random",
+        }
+        for name, attr in candidates.items():
+            if name in sys.modules:
+                continue
+
+            spec = importlib.machinery.PathFinder.find_spec(name)
+            if spec and isinstance(
+                spec.loader, importlib.machinery.ExtensionFileLoader
+            ):
+                break
+        else:
+            self.skipTest("no unimported extension module available")
+
+        with open(spec.origin, "rb") as fh:
+            data = fh.read()
+
+        r = OxidizedResource()
+        r.is_extension_module = True
+        r.name = name
+        r.in_memory_extension_module_shared_library = data
+
+        f = OxidizedFinder()
+        f.add_resource(r)
+
+        spec = f.find_spec(name, None)
+        self.assertIsNotNone(spec)
+
+        m = self._import_with_finder(f, name)
+        self.assertEqual(m.__name__, name)
+        self.assertIs(m.__loader__, f)
+        self.assertTrue(callable(getattr(m, attr)))
+
+        # The library should be mapped from an anonymous memory file, not
+        # from the file we read it from.
+        with open("/proc/self/maps", "r") as fh:
+            maps = fh.read()
+        self.assertIn("/memfd:%s" % name, maps)
+
 
 if __name__ == "__main__":
     unittest.main(exit=False)
@@ -2,6 +2,7 @@
 # License, v. 2.0. If a copy of the MPL was not distributed with this
 # file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+import importlib
 import io
 import pathlib
 import sys
@@ -10,6 +11,7 @@ import unittest
 
 from oxidized_importer import (
     OxidizedFinder,
+    OxidizedPkgResourcesProvider,
     OxidizedResourceCollector,
     OxidizedResourceReader,
     find_resources_in_path,
@@ -125,6 +127,203 @@ class TestImporterResourceReading(unittest.TestCase):
         self.assertEqual(r.open_resource("child0/a.txt").getvalue(), b"a")
         self.assertEqual(r.open_resource("child1/b.txt").getvalue(), b"b")
 
+    def test_files(self):
+        p = self._make_package("my_package")
+
+        with (p / "resource.txt").open("wb") as fh:
+            fh.write(b"my resource")
+
+        f = self._finder_from_td()
+        r = f.get_resource_reader("my_package")
+
+        root = r.files()
+        self.assertEqual(root.name, "my_package")
+        self.assertTrue(root.is_dir())
+        self.assertFalse(root.is_file())
+
+        with self.assertRaises(FileNotFoundError):
+            root.read_bytes()
+
+        children = list(root.iterdir())
+        self.assertEqual([c.name for c in children], ["resource.txt"])
+
+        resource = root / "resource.txt"
+        self.assertEqual(resource.name, "resource.txt")
+        self.assertTrue(resource.is_file())
+        self.assertFalse(resource.is_dir())
+        self.assertEqual(resource.read_bytes(), b"my resource")
+        self.assertEqual(resource.read_text(), "my resource")
+        self.assertEqual(resource.read_text(encoding="utf-8"), "my resource")
+
+        with resource.open("rb") as fh:
+            self.assertEqual(fh.read(), b"my resource")
+
+        with resource.open() as fh:
+            self.assertEqual(fh.read(), "my resource")
+
+        with resource.open("r", encoding="utf-8") as fh:
+            self.assertEqual(fh.read(), "my resource")
+
+        with self.assertRaises(ValueError):
+            resource.open("w")
+
+        missing = root.joinpath("missing")
+        self.assertFalse(missing.is_file())
+        self.assertFalse(missing.is_dir())
+
+        with self.assertRaises(FileNotFoundError):
+            missing.read_bytes()
+
+        with self.assertRaises(FileNotFoundError):
+            list(missing.iterdir())
+
+    def test_files_child_directory(self):
+        p = self._make_package("my_package")
+
+        child_path = p / "child0" / "grandchild"
+        child_path.mkdir(parents=True)
+
+        with (p / "child0" / "a.txt").open("wb") as fh:
+            fh.write(b"a")
+        with (child_path / "b.txt").open("wb") as fh:
+            fh.write(b"b")
+
+        f = self._finder_from_td()
+        root = f.get_resource_reader("my_package").files()
+
+        self.assertEqual([c.name for c in root.iterdir()], ["child0"])
+
+        child = root / "child0"
+        self.assertTrue(child.is_dir())
+        self.assertFalse(child.is_file())
+        self.assertEqual([c.name for c in child.iterdir()], ["a.txt", "grandchild"])
+        self.assertEqual((child / "a.txt").read_bytes(), b"a")
+
+        grandchild = child.joinpath("grandchild")
+        self.assertTrue(grandchild.is_dir())
+        self.assertEqual([c.name for c in grandchild.iterdir()], ["b.txt"])
+
+        self.assertEqual(root.joinpath("child0/grandchild/b.txt").read_bytes(), b"b")
+        self.assertEqual(
+            root.joinpath("child0", "grandchild", "b.txt").read_bytes(), b"b"
+        )
+        self.assertEqual(
+            (root / pathlib.PurePosixPath("child0/a.txt")).read_bytes(), b"a"
+        )
+
+        with self.assertRaises(ValueError):
+            root.joinpath("../other")
+
+    def test_pkg_resources_provider_registration(self):
+        with (self.td / "pkg_resources.py").open("wb") as fh:
+            fh.write(
+                b"registered = []\n"
+                b"def register_loader_type(loader_type, provider_factory):\n"
+                b"    registered.append((loader_type, provider_factory))\n"
+            )
+
+        f = self._finder_from_td()
+
+        orig = sys.modules.pop("pkg_resources", None)
+        sys.meta_path.insert(0, f)
+        try:
+            m = importlib.import_module("pkg_resources")
+            self.assertEqual(
+                m.registered, [(OxidizedFinder, OxidizedPkgResourcesProvider)]
+            )
+        finally:
+            sys.meta_path.remove(f)
+            sys.modules.pop("pkg_resources", None)
+            if orig is not None:
+                sys.modules["pkg_resources"] = orig
+
+    def test_pkg_resources_provider_registration_other_finder(self):
+        # The finder doesn't know about the module written afterwards.
+        f = self._finder_from_td()
+
+        with (self.td / "pkg_resources.py").open("wb") as fh:
+            fh.write(
+                b"registered = []\n"
+                b"def register_loader_type(loader_type, provider_factory):\n"
+                b"    registered.append((loader_type, provider_factory))\n"
+            )
+
+        orig = sys.modules.pop("pkg_resources", None)
+        sys.meta_path.insert(0, f)
+        sys.path.insert(0, str(self.td))
+        importlib.invalidate_caches()
+        try:
+            m = importlib.import_module("pkg_resources")
+            self.assertEqual(
+                m.registered, [(OxidizedFinder, OxidizedPkgResourcesProvider)]
+            )
+            self.assertEqual(m.__file__, str(self.td / "pkg_resources.py"))
+            self.assertIsInstance(m.__loader__, importlib.machinery.SourceFileLoader)
+            self.assertIs(m.__spec__.loader, m.__loader__)
+        finally:
+            sys.path.remove(str(self.td))
+            sys.meta_path.remove(f)
+            sys.modules.pop("pkg_resources", None)
+            if orig is not None:
+                sys.modules["pkg_resources"] = orig
+
+    def test_pkg_resources_provider(self):
+        try:
+            import pkg_resources
+        except ImportError:
+            self.skipTest("pkg_resources not available")
+
+        p = self._make_package("my_package")
+        (p / "child0").mkdir()
+
+        with (p / "resource.txt").open("wb") as fh:
+            fh.write(b"my resource")
+        with (p / "child0" / "a.txt").open("wb") as fh:
+            fh.write(b"a")
+        with (self.td / "my_package" / "module.py").open("wb"):
+            pass
+
+        f = self._finder_from_td()
+
+        pkg_resources.register_loader_type(OxidizedFinder, OxidizedPkgResourcesProvider)
+        sys.meta_path.insert(0, f)
+        try:
+            importlib.import_module("my_package.module")
+
+            self.assertIsInstance(
+                pkg_resources.get_provider("my_package"), OxidizedPkgResourcesProvider
+            )
+
+            for name in ("my_package", "my_package.module"):
+                self.assertTrue(pkg_resources.resource_exists(name, "resource.txt"))
+                self.assertFalse(pkg_resources.resource_exists(name, "missing.txt"))
+                self.assertEqual(
+                    pkg_resources.resource_string(name, "resource.txt"), b"my resource"
+                )
+
+            with pkg_resources.resource_stream("my_package", "child0/a.txt") as fh:
+                self.assertEqual(fh.read(), b"a")
+
+            self.assertTrue(pkg_resources.resource_isdir("my_package", "child0"))
+            self.assertFalse(pkg_resources.resource_isdir("my_package", "resource.txt"))
+            self.assertEqual(
+                sorted(pkg_resources.resource_listdir("my_package", "")),
+                ["child0", "resource.txt"],
+            )
+            self.assertEqual(
+                pkg_resources.resource_listdir("my_package", "child0"), ["a.txt"]
+            )
+
+            with self.assertRaises(FileNotFoundError):
+                pkg_resources.resource_string("my_package", "missing.txt")
+            with self.assertRaises(FileNotFoundError):
+                pkg_resources.resource_filename("my_package", "resource.txt")
+        finally:
+            sys.meta_path.remove(f)
+            sys.modules.pop("my_package.module", None)
+            sys.modules.pop("my_package", None)
+            pkg_resources._provider_factories.pop(OxidizedFinder, None)
+
 
 if __name__ == "__main__":
     unittest.main(exit=False)
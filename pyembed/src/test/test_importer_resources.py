@@ -6,6 +6,7 @@ import importlib.machinery
 import marshal
 import pathlib
 import sys
+import tempfile
 import unittest
 
 from oxidized_importer import (
@@ -55,6 +56,7 @@ class TestImporterResources(unittest.TestCase):
         self.assertIsNone(resource.relative_path_extension_module_shared_library)
         self.assertIsNone(resource.relative_path_package_resources)
         self.assertIsNone(resource.relative_path_distribution_resources)
+        self.assertIsNone(resource.in_memory_compressed_source)
 
     def test_resources_frozen(self):
         f = OxidizedFinder()
@@ -205,6 +207,18 @@ class TestImporterResources(unittest.TestCase):
         with self.assertRaises(TypeError):
             resource.in_memory_source = "import foo"
 
+    def test_resource_set_in_memory_compressed_source(self):
+        resource = OxidizedResource()
+
+        resource.in_memory_compressed_source = b"compressed"
+        self.assertEqual(resource.in_memory_compressed_source, b"compressed")
+
+        resource.in_memory_compressed_source = None
+        self.assertIsNone(resource.in_memory_compressed_source)
+
+        with self.assertRaises(TypeError):
+            del resource.in_memory_compressed_source
+
     def test_resource_set_in_memory_bytecode(self):
         resource = OxidizedResource()
 
@@ -664,6 +678,65 @@ class TestImporterResources(unittest.TestCase):
         self.assertEqual(modules["my_module"].in_memory_source, b"import io")
         self.assertEqual(modules["module_b"].in_memory_bytecode, b"dummy bytecode")
 
+    def _serialized_module(self, name, source):
+        f = OxidizedFinder()
+
+        m = OxidizedResource()
+        m.is_module = True
+        m.name = name
+        m.in_memory_source = source
+        f.add_resource(m)
+
+        return f.serialize_indexed_resources()
+
+    def test_index_bytes(self):
+        f = OxidizedFinder()
+        f.index_bytes(self._serialized_module("my_module", b"import io"))
+
+        resources = [r for r in f.indexed_resources() if r.name == "my_module"]
+        self.assertEqual(len(resources), 1)
+        self.assertEqual(resources[0].in_memory_source, b"import io")
+
+        spec = f.find_spec("my_module", None)
+        self.assertIsInstance(spec, importlib.machinery.ModuleSpec)
+        self.assertEqual(f.get_source("my_module"), "import io")
+
+    def test_index_bytes_bad_data(self):
+        f = OxidizedFinder()
+
+        with self.assertRaises(ValueError):
+            f.index_bytes(b"foo")
+
+    def test_index_bytes_multiple(self):
+        f = OxidizedFinder()
+        f.index_bytes(self._serialized_module("foo_a", b"import io"))
+        f.index_bytes(self._serialized_module("foo_b", b"import os"))
+
+        self.assertEqual(f.get_source("foo_a"), "import io")
+        self.assertEqual(f.get_source("foo_b"), "import os")
+
+    def test_index_file_memory_mapped(self):
+        with tempfile.TemporaryDirectory(prefix="oxidized_importer-test-") as td:
+            path = pathlib.Path(td) / "resources"
+
+            with path.open("wb") as fh:
+                fh.write(self._serialized_module("my_module", b"import io"))
+
+            f = OxidizedFinder()
+            f.index_file_memory_mapped(path)
+
+            self.assertEqual(f.get_source("my_module"), "import io")
+
+            # The file is memory mapped. So release the finder before the
+            # directory is cleaned up.
+            del f
+
+    def test_index_file_memory_mapped_missing(self):
+        f = OxidizedFinder()
+
+        with self.assertRaises(IOError):
+            f.index_file_memory_mapped("/does/not/exist")
+
 
 if __name__ == "__main__":
     unittest.main(exit=False)
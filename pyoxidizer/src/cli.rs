@@ -4,8 +4,8 @@
 
 use {
     crate::{
-        analyze, environment::PYOXIDIZER_VERSION, logging, project_building, project_layout,
-        projectmgmt,
+        analyze, environment::PYOXIDIZER_VERSION, logging, packed_resources, project_building,
+        project_layout, projectmgmt,
     },
     anyhow::{anyhow, Result},
     clap::{App, AppSettings, Arg, SubCommand},
@@ -176,6 +176,44 @@ pub fn run_cli() -> Result<()> {
                     "Filesystem path to scan for resources. Must be a directory or Python wheel",
                 )),
         )
+        .subcommand(
+            SubCommand::with_name("inspect-resources")
+                .about("Show the resources in a packed resources file")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Emit JSON instead of human readable text"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .required(true)
+                        .value_name("PATH")
+                        .help("Path to packed resources file to inspect"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff-resources")
+                .about("Show differences between two packed resources files")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Emit JSON instead of human readable text"),
+                )
+                .arg(
+                    Arg::with_name("old_path")
+                        .required(true)
+                        .value_name("OLD_PATH")
+                        .help("Path to original packed resources file"),
+                )
+                .arg(
+                    Arg::with_name("new_path")
+                        .required(true)
+                        .value_name("NEW_PATH")
+                        .help("Path to new packed resources file"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("init-config-file")
                 .setting(AppSettings::ArgRequiredElseHelp)
@@ -373,6 +411,14 @@ pub fn run_cli() -> Result<()> {
             )
         }
 
+        ("diff-resources", Some(args)) => {
+            let old_path = args.value_of("old_path").unwrap();
+            let new_path = args.value_of("new_path").unwrap();
+            let json = args.is_present("json");
+
+            packed_resources::diff_resources(Path::new(old_path), Path::new(new_path), json)
+        }
+
         ("find-resources", Some(args)) => {
             let path = if let Some(value) = args.value_of("path") {
                 Some(Path::new(value))
@@ -417,6 +463,13 @@ pub fn run_cli() -> Result<()> {
             projectmgmt::init_config_file(&config_path, code, &pip_install)
         }
 
+        ("inspect-resources", Some(args)) => {
+            let path = args.value_of("path").unwrap();
+            let json = args.is_present("json");
+
+            packed_resources::inspect_resources(Path::new(path), json)
+        }
+
         ("list-targets", Some(args)) => {
             let path = args.value_of("path").unwrap();
 
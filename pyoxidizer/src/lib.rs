@@ -14,6 +14,7 @@ This library exposes that functionality to other tools.
 pub mod analyze;
 pub mod environment;
 pub mod logging;
+pub mod packed_resources;
 pub mod project_building;
 pub mod project_layout;
 pub mod projectmgmt;
@@ -31,6 +31,7 @@ mod analyze;
 mod cli;
 mod environment;
 mod logging;
+mod packed_resources;
 mod project_building;
 mod project_layout;
 mod projectmgmt;
@@ -0,0 +1,553 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Inspect and compare packed resources data.
+
+This module powers the `inspect-resources` and `diff-resources` commands.
+It parses packed resources data with `python_packed_resources` and
+summarizes each resource as its name, flavor, flags, and the sizes of the
+fields it defines. Each summary also carries a SHA-256 content hash over
+all of these, which is what resources are compared by when diffing.
+
+It also determines which optional features of `pyembed` are required to
+read packed resources data.
+*/
+
+use {
+    anyhow::{anyhow, Context, Result},
+    python_packed_resources::{
+        data::{Resource, ResourceFlavor},
+        parser::{load_resources, split_signature},
+    },
+    serde::Serialize,
+    sha2::{Digest, Sha256},
+    std::{borrow::Cow, collections::BTreeMap, collections::HashMap, path::Path},
+};
+
+/// Describes a single resource in packed resources data.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ResourceSummary {
+    /// Name of the resource.
+    pub name: String,
+
+    /// Flavor of the resource.
+    pub flavor: String,
+
+    /// Names of boolean flags set on the resource.
+    pub flags: Vec<String>,
+
+    /// Mapping of field name to size of its data in bytes.
+    ///
+    /// Fields holding multiple entries report the sum of their entries.
+    pub fields: BTreeMap<String, usize>,
+
+    /// Hex encoded SHA-256 of the resource's flavor, flags, and field data.
+    pub sha256: String,
+}
+
+/// Describes a resource whose content differs between two packed resources.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ChangedResource {
+    pub name: String,
+    pub old: ResourceSummary,
+    pub new: ResourceSummary,
+}
+
+/// Describes the differences between two packed resources data.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ResourcesDiff {
+    /// Resources only present in the new data.
+    pub added: Vec<ResourceSummary>,
+
+    /// Resources only present in the old data.
+    pub removed: Vec<ResourceSummary>,
+
+    /// Resources present in both but with different content.
+    pub changed: Vec<ChangedResource>,
+}
+
+impl ResourcesDiff {
+    /// Whether there are no differences.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn flavor_name(flavor: ResourceFlavor) -> &'static str {
+    match flavor {
+        ResourceFlavor::None => "none",
+        ResourceFlavor::Module => "module",
+        ResourceFlavor::BuiltinExtensionModule => "builtin-extension-module",
+        ResourceFlavor::FrozenModule => "frozen-module",
+        ResourceFlavor::Extension => "extension",
+        ResourceFlavor::SharedLibrary => "shared-library",
+    }
+}
+
+/// Entries of a single field, as (key, data) pairs.
+type FieldEntries<'a> = Vec<(&'a str, &'a [u8])>;
+type OwnedEntries = Vec<(String, Vec<u8>)>;
+
+/// Feed a field's data into a content hash.
+fn hash_field(hasher: &mut Sha256, name: &str, entries: &[(&str, &[u8])]) {
+    hasher.update(name.as_bytes());
+    hasher.update([0]);
+    hasher.update((entries.len() as u64).to_le_bytes());
+
+    for (key, value) in entries {
+        hasher.update((key.len() as u64).to_le_bytes());
+        hasher.update(key.as_bytes());
+        hasher.update((value.len() as u64).to_le_bytes());
+        hasher.update(value);
+    }
+}
+
+fn sorted_entries<'a>(map: &'a HashMap<Cow<'a, str>, Cow<'a, [u8]>>) -> FieldEntries<'a> {
+    let mut entries = map
+        .iter()
+        .map(|(k, v)| (k.as_ref(), v.as_ref()))
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    entries
+}
+
+/// Summarize a parsed resource.
+pub fn summarize_resource(resource: &Resource<u8>) -> ResourceSummary {
+    let flags = [
+        ("is_module", resource.is_module),
+        (
+            "is_builtin_extension_module",
+            resource.is_builtin_extension_module,
+        ),
+        ("is_frozen_module", resource.is_frozen_module),
+        ("is_extension_module", resource.is_extension_module),
+        ("is_shared_library", resource.is_shared_library),
+        ("is_utf8_filename_data", resource.is_utf8_filename_data),
+        ("is_package", resource.is_package),
+        ("is_namespace_package", resource.is_namespace_package),
+        ("file_executable", resource.file_executable),
+    ]
+    .iter()
+    .filter(|(_, set)| *set)
+    .map(|(name, _)| name.to_string())
+    .collect::<Vec<_>>();
+
+    let path_bytes = |p: &Path| p.to_string_lossy().into_owned().into_bytes();
+
+    // Every field is normalized to a list of (key, data) entries so single
+    // and multi-valued fields can be sized and hashed the same way.
+    let mut owned: Vec<(&'static str, OwnedEntries)> = vec![];
+    let mut borrowed: Vec<(&'static str, FieldEntries)> = vec![];
+
+    for (name, value) in &[
+        ("in_memory_source", &resource.in_memory_source),
+        ("in_memory_bytecode", &resource.in_memory_bytecode),
+        ("in_memory_bytecode_opt1", &resource.in_memory_bytecode_opt1),
+        ("in_memory_bytecode_opt2", &resource.in_memory_bytecode_opt2),
+        (
+            "in_memory_extension_module_shared_library",
+            &resource.in_memory_extension_module_shared_library,
+        ),
+        (
+            "in_memory_shared_library",
+            &resource.in_memory_shared_library,
+        ),
+        ("file_data_embedded", &resource.file_data_embedded),
+        (
+            "in_memory_compressed_source",
+            &resource.in_memory_compressed_source,
+        ),
+    ] {
+        if let Some(data) = value {
+            borrowed.push((name, vec![("", data.as_ref())]));
+        }
+    }
+
+    if let Some(map) = &resource.in_memory_package_resources {
+        borrowed.push(("in_memory_package_resources", sorted_entries(map)));
+    }
+    if let Some(map) = &resource.in_memory_distribution_resources {
+        borrowed.push(("in_memory_distribution_resources", sorted_entries(map)));
+    }
+    if let Some(map) = &resource.annotations {
+        borrowed.push(("annotations", sorted_entries(map)));
+    }
+
+    for (name, value) in &[
+        (
+            "relative_path_module_source",
+            &resource.relative_path_module_source,
+        ),
+        (
+            "relative_path_module_bytecode",
+            &resource.relative_path_module_bytecode,
+        ),
+        (
+            "relative_path_module_bytecode_opt1",
+            &resource.relative_path_module_bytecode_opt1,
+        ),
+        (
+            "relative_path_module_bytecode_opt2",
+            &resource.relative_path_module_bytecode_opt2,
+        ),
+        (
+            "relative_path_extension_module_shared_library",
+            &resource.relative_path_extension_module_shared_library,
+        ),
+    ] {
+        if let Some(path) = value {
+            owned.push((name, vec![("".to_string(), path_bytes(path))]));
+        }
+    }
+
+    for (name, value) in &[
+        (
+            "relative_path_package_resources",
+            &resource.relative_path_package_resources,
+        ),
+        (
+            "relative_path_distribution_resources",
+            &resource.relative_path_distribution_resources,
+        ),
+    ] {
+        if let Some(map) = value {
+            let mut entries = map
+                .iter()
+                .map(|(k, v)| (k.to_string(), path_bytes(v)))
+                .collect::<Vec<_>>();
+            entries.sort();
+            owned.push((name, entries));
+        }
+    }
+
+    if let Some(names) = &resource.shared_library_dependency_names {
+        owned.push((
+            "shared_library_dependency_names",
+            names
+                .iter()
+                .map(|n| ("".to_string(), n.as_bytes().to_vec()))
+                .collect(),
+        ));
+    }
+
+    if let Some(path) = &resource.file_data_utf8_relative_path {
+        owned.push((
+            "file_data_utf8_relative_path",
+            vec![("".to_string(), path.as_bytes().to_vec())],
+        ));
+    }
+
+    for (name, entries) in &owned {
+        borrowed.push((
+            name,
+            entries
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_slice()))
+                .collect(),
+        ));
+    }
+    borrowed.sort_by(|a, b| a.0.cmp(b.0));
+
+    let flavor = flavor_name(resource.flavor);
+
+    let mut hasher = Sha256::new();
+    hasher.update(flavor.as_bytes());
+    hasher.update([0]);
+    for flag in &flags {
+        hasher.update(flag.as_bytes());
+        hasher.update([0]);
+    }
+
+    let mut fields = BTreeMap::new();
+    for (name, entries) in &borrowed {
+        hash_field(&mut hasher, name, entries);
+        fields.insert(
+            name.to_string(),
+            entries.iter().map(|(_, data)| data.len()).sum(),
+        );
+    }
+
+    ResourceSummary {
+        name: resource.name.to_string(),
+        flavor: flavor.to_string(),
+        flags,
+        fields,
+        sha256: hex::encode(hasher.finalize()),
+    }
+}
+
+/// Parse packed resources data into a list of resource summaries.
+///
+/// A trailing signature, if present, is ignored.
+pub fn summarize_resources(data: &[u8]) -> Result<Vec<ResourceSummary>> {
+    let data = match split_signature(data) {
+        Some((data, _)) => data,
+        None => data,
+    };
+
+    load_resources(data)
+        .map_err(|e| anyhow!("error parsing packed resources: {}", e))?
+        .map(|resource| {
+            let mut resource =
+                resource.map_err(|e| anyhow!("error parsing packed resources: {}", e))?;
+            resource
+                .decompress()
+                .map_err(|e| anyhow!("error decompressing {}: {}", resource.name, e))?;
+
+            Ok(summarize_resource(&resource))
+        })
+        .collect()
+}
+
+/// Whether reading packed resources data requires zstd decompression.
+///
+/// This is the case if a resource holds compressed module source or data
+/// from a compressed blob section. `pyembed` needs its `compression` feature
+/// to read such data.
+pub fn requires_compression(data: &[u8]) -> Result<bool> {
+    let data = match split_signature(data) {
+        Some((data, _)) => data,
+        None => data,
+    };
+
+    for resource in
+        load_resources(data).map_err(|e| anyhow!("error parsing packed resources: {}", e))?
+    {
+        let resource = resource.map_err(|e| anyhow!("error parsing packed resources: {}", e))?;
+
+        if resource.in_memory_compressed_source.is_some() || !resource.compressed_fields.is_empty()
+        {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Compute the differences between two lists of resource summaries.
+///
+/// Resources are matched by name. When a name occurs multiple times,
+/// the last occurrence wins, mirroring how resources are indexed at run-time.
+pub fn diff_summaries(old: &[ResourceSummary], new: &[ResourceSummary]) -> ResourcesDiff {
+    let old = old
+        .iter()
+        .map(|r| (r.name.as_str(), r))
+        .collect::<BTreeMap<_, _>>();
+    let new = new
+        .iter()
+        .map(|r| (r.name.as_str(), r))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut diff = ResourcesDiff::default();
+
+    for (name, old_resource) in &old {
+        match new.get(name) {
+            Some(new_resource) => {
+                if old_resource.sha256 != new_resource.sha256 {
+                    diff.changed.push(ChangedResource {
+                        name: name.to_string(),
+                        old: (*old_resource).clone(),
+                        new: (*new_resource).clone(),
+                    });
+                }
+            }
+            None => diff.removed.push((*old_resource).clone()),
+        }
+    }
+
+    for (name, new_resource) in &new {
+        if !old.contains_key(name) {
+            diff.added.push((*new_resource).clone());
+        }
+    }
+
+    diff
+}
+
+fn read_summaries(path: &Path) -> Result<Vec<ResourceSummary>> {
+    let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+
+    summarize_resources(&data).with_context(|| format!("inspecting {}", path.display()))
+}
+
+fn format_fields(fields: &BTreeMap<String, usize>) -> String {
+    fields
+        .iter()
+        .map(|(name, size)| format!("{}={}", name, size))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Print the resources in a packed resources file.
+pub fn inspect_resources(path: &Path, json: bool) -> Result<()> {
+    let resources = read_summaries(path)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&resources)?);
+        return Ok(());
+    }
+
+    for resource in &resources {
+        println!("{}", resource.name);
+        println!("    flavor: {}", resource.flavor);
+        println!("    flags: {}", resource.flags.join(", "));
+        println!("    fields: {}", format_fields(&resource.fields));
+        println!("    sha256: {}", resource.sha256);
+    }
+
+    println!("{} resources", resources.len());
+
+    Ok(())
+}
+
+/// Print the differences between two packed resources files.
+pub fn diff_resources(old_path: &Path, new_path: &Path, json: bool) -> Result<()> {
+    let diff = diff_summaries(&read_summaries(old_path)?, &read_summaries(new_path)?);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+        return Ok(());
+    }
+
+    for resource in &diff.removed {
+        println!("- {}", resource.name);
+    }
+    for resource in &diff.added {
+        println!("+ {}", resource.name);
+    }
+    for changed in &diff.changed {
+        println!("~ {}", changed.name);
+        println!("    old: {}", format_fields(&changed.old.fields));
+        println!("    new: {}", format_fields(&changed.new.fields));
+    }
+
+    if diff.is_empty() {
+        println!("no differences");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        python_packed_resources::{
+            data::BlobSectionCompression,
+            writer::{write_packed_resources, write_packed_resources_v3, WriterOptions},
+        },
+    };
+
+    fn packed(resources: &[Resource<u8>]) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        write_packed_resources_v3(resources, &mut data, None)?;
+
+        Ok(data)
+    }
+
+    fn module(name: &str, source: &'static [u8]) -> Resource<'static, u8> {
+        Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::Owned(name.to_string()),
+            is_module: true,
+            in_memory_source: Some(Cow::Borrowed(source)),
+            ..Resource::default()
+        }
+    }
+
+    #[test]
+    fn test_summarize() -> Result<()> {
+        let data = packed(&[module("foo", b"import bar")])?;
+        let resources = summarize_resources(&data)?;
+
+        assert_eq!(resources.len(), 1);
+        let r = &resources[0];
+        assert_eq!(r.name, "foo");
+        assert_eq!(r.flavor, "module");
+        assert_eq!(r.flags, vec!["is_module".to_string()]);
+        assert_eq!(r.fields.get("in_memory_source"), Some(&10));
+        assert_eq!(r.sha256.len(), 64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff() -> Result<()> {
+        let old = summarize_resources(&packed(&[
+            module("foo", b"foo"),
+            module("bar", b"bar"),
+            module("removed", b""),
+        ])?)?;
+        let new = summarize_resources(&packed(&[
+            module("foo", b"foo"),
+            module("bar", b"bar2"),
+            module("added", b""),
+        ])?)?;
+
+        let diff = diff_summaries(&old, &new);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].name, "added");
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].name, "removed");
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].name, "bar");
+
+        assert!(diff_summaries(&old, &old).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_annotations() -> Result<()> {
+        let annotated = |value: &'static [u8]| -> Result<Vec<ResourceSummary>> {
+            let mut annotations = HashMap::new();
+            annotations.insert(Cow::Borrowed("key"), Cow::Borrowed(value));
+
+            let mut data = Vec::new();
+            write_packed_resources(
+                &[Resource {
+                    annotations: Some(annotations),
+                    ..module("foo", b"foo")
+                }],
+                &mut data,
+                &WriterOptions::default(),
+            )?;
+
+            summarize_resources(&data)
+        };
+
+        let old = annotated(b"old")?;
+        let new = annotated(b"new")?;
+        assert_eq!(old[0].fields.get("annotations"), Some(&3));
+
+        let diff = diff_summaries(&old, &new);
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].name, "foo");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_requires_compression() -> Result<()> {
+        assert!(!requires_compression(&packed(&[module("foo", b"foo")])?)?);
+
+        let compressed_source = Resource {
+            in_memory_source: None,
+            in_memory_compressed_source: Some(Cow::Borrowed(b"compressed")),
+            ..module("foo", b"")
+        };
+        assert!(requires_compression(&packed(&[compressed_source])?)?);
+
+        let mut options = WriterOptions::default();
+        options.compress_all(BlobSectionCompression::Zstd);
+        let mut data = Vec::new();
+        write_packed_resources(&[module("foo", b"foo")], &mut data, &options)?;
+        assert!(requires_compression(&data)?);
+
+        Ok(())
+    }
+}
@@ -5,6 +5,7 @@
 use {
     crate::{
         environment::{canonicalize_path, MINIMUM_RUST_VERSION},
+        packed_resources::requires_compression,
         project_layout::initialize_project,
         py_packaging::binary::{EmbeddedPythonContext, PythonBinaryBuilder},
         starlark::eval::EvaluationContext,
@@ -160,6 +161,28 @@ pub fn build_executable_with_rust_project(
         features.push("jemalloc");
     }
 
+    if exe.requires_mimalloc() {
+        features.push("mimalloc");
+    }
+
+    if exe.requires_snmalloc() {
+        features.push("snmalloc");
+    }
+
+    if requires_compression(&embedded_data.resources)? {
+        features.push("compression");
+    }
+
+    if embedded_data.config.verify_packed_resources_digests {
+        features.push("digests");
+    }
+
+    if embedded_data.config.config_overrides_path.is_some()
+        || embedded_data.config.config_overrides_path_env.is_some()
+    {
+        features.push("config-overrides");
+    }
+
     let features = features.join(" ");
 
     if !features.is_empty() {
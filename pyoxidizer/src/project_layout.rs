@@ -337,8 +337,16 @@ pub fn update_new_cargo_toml(path: &Path, pyembed_location: &PyembedLocation) ->
 
     content.push_str("\n");
     content.push_str("[features]\n");
-    content.push_str("default = [\"build-mode-pyoxidizer-exe\"]\n");
+    content.push_str(
+        "default = [\"build-mode-pyoxidizer-exe\", \"compression\", \"config-overrides\", \"digests\"]\n",
+    );
     content.push_str("jemalloc = [\"jemallocator-global\", \"pyembed/jemalloc\"]\n");
+    content.push_str("mimalloc = [\"pyembed/mimalloc\"]\n");
+    content.push_str("snmalloc = [\"pyembed/snmalloc\"]\n");
+    content.push_str("compression = [\"pyembed/compression\"]\n");
+    content.push_str("digests = [\"pyembed/digests\"]\n");
+    content.push_str("signatures = [\"pyembed/signatures\"]\n");
+    content.push_str("config-overrides = [\"pyembed/config-overrides\"]\n");
     content.push_str("build-mode-pyoxidizer-exe = [\"pyembed/build-mode-pyoxidizer-exe\"]\n");
     content
         .push_str("build-mode-prebuilt-artifacts = [\"pyembed/build-mode-prebuilt-artifacts\"]\n");
@@ -235,6 +235,12 @@ pub trait PythonBinaryBuilder {
     /// Whether the binary requires the jemalloc library.
     fn requires_jemalloc(&self) -> bool;
 
+    /// Whether the binary requires the mimalloc library.
+    fn requires_mimalloc(&self) -> bool;
+
+    /// Whether the binary requires the snmalloc library.
+    fn requires_snmalloc(&self) -> bool;
+
     /// Obtain an `EmbeddedPythonContext` instance from this one.
     fn to_embedded_python_context(
         &self,
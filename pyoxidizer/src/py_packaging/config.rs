@@ -23,6 +23,9 @@ use {
 };
 
 /// Determine the default raw allocator for a target triple.
+///
+/// Alternative allocators like mimalloc and snmalloc are never the default.
+/// They must be selected explicitly via `PythonInterpreterConfig.raw_allocator`.
 pub fn default_raw_allocator(target_triple: &str) -> MemoryAllocatorBackend {
     // Jemalloc doesn't work on Windows.
     //
@@ -90,6 +93,13 @@ pub struct EmbeddedPythonConfig {
     pub terminfo_resolution: TerminfoResolution,
     pub tcl_library: Option<PathBuf>,
     pub write_modules_directory_env: Option<String>,
+    pub write_import_trace_directory_env: Option<String>,
+    pub lazy_module_prefixes: Option<Vec<String>>,
+    pub config_overrides_path: Option<PathBuf>,
+    pub config_overrides_path_env: Option<String>,
+    pub config_overrides_allowed_fields: Option<Vec<String>>,
+    pub verify_packed_resources_digests: bool,
+    pub resources_materialization_directory: Option<PathBuf>,
 }
 
 impl Default for EmbeddedPythonConfig {
@@ -114,12 +124,22 @@ impl Default for EmbeddedPythonConfig {
             terminfo_resolution: TerminfoResolution::None,
             tcl_library: None,
             write_modules_directory_env: None,
+            write_import_trace_directory_env: None,
+            lazy_module_prefixes: None,
+            config_overrides_path: None,
+            config_overrides_path_env: None,
+            config_overrides_allowed_fields: None,
+            verify_packed_resources_digests: false,
+            resources_materialization_directory: None,
         }
     }
 }
 
 impl EmbeddedPythonConfig {
     /// Convert the instance to Rust code that constructs a `pyembed::OxidizedPythonInterpreterConfig`.
+    ///
+    /// `packed_resources_public_key` is always `None` because PyOxidizer
+    /// doesn't sign the packed resources data it produces.
     pub fn to_oxidized_python_interpreter_config_rs(
         &self,
         packed_resources_path: Option<&Path>,
@@ -192,6 +212,9 @@ impl EmbeddedPythonConfig {
             oxidized_importer: {},\n    \
             filesystem_importer: {},\n    \
             packed_resources: {},\n    \
+            verify_packed_resources_digests: {},\n    \
+            packed_resources_public_key: None,\n    \
+            resources_materialization_directory: {},\n    \
             extra_extension_modules: None,\n    \
             argv: None,\n    \
             argvb: {},\n    \
@@ -200,6 +223,11 @@ impl EmbeddedPythonConfig {
             terminfo_resolution: {},\n    \
             tcl_library: {},\n    \
             write_modules_directory_env: {},\n    \
+            write_import_trace_directory_env: {},\n    \
+            lazy_module_prefixes: {},\n    \
+            config_overrides_path: {},\n    \
+            config_overrides_path_env: {},\n    \
+            config_overrides_allowed_fields: {},\n    \
             }}\n\
             ",
             match self.config.profile {
@@ -312,16 +340,23 @@ impl EmbeddedPythonConfig {
             optional_vec_string_to_string(&self.config.x_options),
             match self.raw_allocator {
                 MemoryAllocatorBackend::Jemalloc => "pyembed::PythonRawAllocator::jemalloc()",
+                MemoryAllocatorBackend::Mimalloc => "pyembed::PythonRawAllocator::mimalloc()",
+                MemoryAllocatorBackend::Snmalloc => "pyembed::PythonRawAllocator::snmalloc()",
                 MemoryAllocatorBackend::Rust => "pyembed::PythonRawAllocator::rust()",
                 MemoryAllocatorBackend::System => "pyembed::PythonRawAllocator::system()",
             },
             self.oxidized_importer,
             self.filesystem_importer,
             if let Some(path) = packed_resources_path {
-                format!("vec![include_bytes!(r#\"{}\"#)]", path.display())
+                format!(
+                    "vec![pyembed::PackedResourcesSource::Memory(include_bytes!(r#\"{}\"#))]",
+                    path.display()
+                )
             } else {
                 "vec![]".to_string()
             },
+            self.verify_packed_resources_digests,
+            optional_pathbuf_to_string(&self.resources_materialization_directory),
             self.argvb,
             self.sys_frozen,
             self.sys_meipass,
@@ -334,6 +369,11 @@ impl EmbeddedPythonConfig {
             },
             optional_pathbuf_to_string(&self.tcl_library),
             optional_string_to_string(&self.write_modules_directory_env),
+            optional_string_to_string(&self.write_import_trace_directory_env),
+            optional_vec_string_to_string(&self.lazy_module_prefixes),
+            optional_pathbuf_to_string(&self.config_overrides_path),
+            optional_string_to_string(&self.config_overrides_path_env),
+            optional_vec_string_to_string(&self.config_overrides_allowed_fields),
         );
 
         Ok(code)
@@ -476,6 +516,13 @@ mod tests {
             terminfo_resolution: TerminfoResolution::Dynamic,
             tcl_library: Some("path".into()),
             write_modules_directory_env: Some("env".into()),
+            write_import_trace_directory_env: Some("trace_env".into()),
+            lazy_module_prefixes: Some(vec!["lazy0".into(), "lazy1".into()]),
+            config_overrides_path: Some("$ORIGIN/overrides.toml".into()),
+            config_overrides_path_env: Some("overrides_env".into()),
+            config_overrides_allowed_fields: Some(vec!["run_module".into()]),
+            verify_packed_resources_digests: true,
+            resources_materialization_directory: Some("$ORIGIN/resources".into()),
         };
 
         let builder = dist.as_python_executable_builder(
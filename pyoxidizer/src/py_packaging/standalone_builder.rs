@@ -693,6 +693,14 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         self.config.raw_allocator == MemoryAllocatorBackend::Jemalloc
     }
 
+    fn requires_mimalloc(&self) -> bool {
+        self.config.raw_allocator == MemoryAllocatorBackend::Mimalloc
+    }
+
+    fn requires_snmalloc(&self) -> bool {
+        self.config.raw_allocator == MemoryAllocatorBackend::Snmalloc
+    }
+
     fn to_embedded_python_context(
         &self,
         logger: &slog::Logger,
@@ -716,14 +724,17 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
             );
         }
 
-        let compiled_resources = {
+        // Resources are streamed so their data isn't all held in memory at once.
+        let mut resources = Vec::new();
+        let (resource_names, resource_installs) = {
             let mut compiler = BytecodeCompiler::new(self.host_python_exe_path())?;
-            self.resources_collector.compile_resources(&mut compiler)?
+            self.resources_collector
+                .write_packed_resources_streaming(&mut compiler, &mut resources)?
         };
 
         let mut extra_files = FileManifest::default();
 
-        for (path, location, executable) in &compiled_resources.extra_files {
+        for (path, location, executable) in &resource_installs {
             extra_files.add_file(
                 path,
                 &FileContent {
@@ -735,14 +746,11 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
 
         let mut module_names = Vec::new();
 
-        for name in compiled_resources.resources.keys() {
+        for name in &resource_names {
             module_names.write_all(name.as_bytes())?;
             module_names.write_all(b"\n")?;
         }
 
-        let mut resources = Vec::new();
-        compiled_resources.write_packed_resources(&mut resources)?;
-
         let linking_info = self.resolve_python_linking_info(logger, opt_level)?;
 
         if self.link_mode == LibpythonLinkMode::Dynamic {
@@ -1054,6 +1062,57 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_embedded_resources_streamed() -> Result<()> {
+        let logger = get_logger()?;
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+        let resource_path = temp_dir.path().join("resource.txt");
+        std::fs::write(&resource_path, b"resource data")?;
+
+        let options = StandalonePythonExecutableBuilderOptions::default();
+        let mut builder = options.new_builder()?;
+        builder.add_python_package_resource(
+            &PythonPackageResource {
+                leaf_package: "testpackage".to_string(),
+                relative_name: "resource.txt".to_string(),
+                data: DataLocation::Path(resource_path),
+                is_stdlib: false,
+                is_test: false,
+            },
+            None,
+        )?;
+
+        let embedded = builder.to_embedded_python_context(&logger, "0")?;
+
+        let module_names = String::from_utf8(embedded.module_names.clone())?;
+        assert!(module_names.lines().any(|name| name == "testpackage"));
+        assert!(module_names.lines().any(|name| name == "json"));
+
+        let resources = python_packed_resources::parser::load_resources(&embedded.resources)
+            .map_err(|e| anyhow!(e))?;
+        assert!(resources.has_lookup_table());
+
+        let resource = resources
+            .find_resource("testpackage")
+            .map_err(|e| anyhow!(e))?
+            .ok_or_else(|| anyhow!("testpackage not found"))?;
+        assert_eq!(
+            resource
+                .in_memory_package_resources
+                .as_ref()
+                .and_then(|resources| resources.get("resource.txt"))
+                .map(|data| data.as_ref()),
+            Some(b"resource data".as_ref())
+        );
+        assert!(resources
+            .find_resource("json")
+            .map_err(|e| anyhow!(e))?
+            .and_then(|resource| resource.in_memory_bytecode)
+            .is_some());
+
+        Ok(())
+    }
+
     #[test]
     fn test_minimal_extensions_present() -> Result<()> {
         let options = StandalonePythonExecutableBuilderOptions::default();
@@ -1327,6 +1386,45 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_linux_dynamic_extension_in_memory_shared_library() -> Result<()> {
+        let options = StandalonePythonExecutableBuilderOptions {
+            target_triple: "x86_64-unknown-linux-gnu".to_string(),
+            extension_module_filter: Some(ExtensionModuleFilter::Minimal),
+            libpython_link_mode: BinaryLibpythonLinkMode::Dynamic,
+            resources_location: Some(ConcreteResourceLocation::InMemory),
+            resources_location_fallback: Some(None),
+            allow_in_memory_shared_library_loading: Some(true),
+            ..StandalonePythonExecutableBuilderOptions::default()
+        };
+
+        let mut builder = options.new_builder()?;
+
+        builder.add_python_extension_module(&EXTENSION_MODULE_SHARED_LIBRARY_ONLY, None)?;
+        assert_extension_shared_library(
+            &builder,
+            &EXTENSION_MODULE_SHARED_LIBRARY_ONLY,
+            ConcreteResourceLocation::InMemory,
+        )?;
+
+        let res = builder.add_python_extension_module(&EXTENSION_MODULE_OBJECT_FILES_ONLY, None);
+        assert!(res.is_err());
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "no shared library data present"
+        );
+
+        builder
+            .add_python_extension_module(&EXTENSION_MODULE_SHARED_LIBRARY_AND_OBJECT_FILES, None)?;
+        assert_extension_shared_library(
+            &builder,
+            &EXTENSION_MODULE_SHARED_LIBRARY_AND_OBJECT_FILES,
+            ConcreteResourceLocation::InMemory,
+        )?;
+
+        Ok(())
+    }
+
     #[test]
     fn test_linux_distribution_extension_filesystem_relative_only() -> Result<()> {
         for libpython_link_mode in vec![
@@ -1321,11 +1321,12 @@ impl PythonDistribution for StandaloneDistribution {
 
     /// Determines whether dynamically linked extension modules can be loaded from memory.
     fn supports_in_memory_shared_library_loading(&self) -> bool {
-        // Loading from memory is only supported on Windows where symbols are
-        // declspec(dllexport) and the distribution is capable of loading
-        // shared library extensions.
-        self.target_triple.contains("pc-windows")
-            && self.python_symbol_visibility == "dllexport"
+        // Loading from memory is supported on Windows where symbols are
+        // declspec(dllexport) and on Linux via memfd_create(). In both cases
+        // the distribution must be capable of loading shared library extensions.
+        (self.target_triple.contains("linux")
+            || (self.target_triple.contains("pc-windows")
+                && self.python_symbol_visibility == "dllexport"))
             && self
                 .extension_module_loading
                 .contains(&"shared-library".to_string())
@@ -197,6 +197,21 @@ impl TypedValue for PythonInterpreterConfigValue {
             "sys_meipass" => Value::from(self.inner.sys_meipass),
             "terminfo_resolution" => self.inner.terminfo_resolution.to_value(),
             "write_modules_directory_env" => self.inner.write_modules_directory_env.to_value(),
+            "write_import_trace_directory_env" => {
+                self.inner.write_import_trace_directory_env.to_value()
+            }
+            "lazy_module_prefixes" => self.inner.lazy_module_prefixes.to_value(),
+            "config_overrides_path" => self.inner.config_overrides_path.to_value(),
+            "config_overrides_path_env" => self.inner.config_overrides_path_env.to_value(),
+            "config_overrides_allowed_fields" => {
+                self.inner.config_overrides_allowed_fields.to_value()
+            }
+            "verify_packed_resources_digests" => {
+                Value::from(self.inner.verify_packed_resources_digests)
+            }
+            "resources_materialization_directory" => {
+                self.inner.resources_materialization_directory.to_value()
+            }
             attr => {
                 return Err(ValueError::OperationNotSupported {
                     op: UnsupportedOperation::GetAttr(attr.to_string()),
@@ -275,6 +290,13 @@ impl TypedValue for PythonInterpreterConfigValue {
             "sys_meipass" => true,
             "terminfo_resolution" => true,
             "write_modules_directory_env" => true,
+            "write_import_trace_directory_env" => true,
+            "lazy_module_prefixes" => true,
+            "config_overrides_path" => true,
+            "config_overrides_path_env" => true,
+            "config_overrides_allowed_fields" => true,
+            "verify_packed_resources_digests" => true,
+            "resources_materialization_directory" => true,
             _ => false,
         })
     }
@@ -552,6 +574,27 @@ impl TypedValue for PythonInterpreterConfigValue {
             "write_modules_directory_env" => {
                 self.inner.write_modules_directory_env = value.to_optional();
             }
+            "write_import_trace_directory_env" => {
+                self.inner.write_import_trace_directory_env = value.to_optional();
+            }
+            "lazy_module_prefixes" => {
+                self.inner.lazy_module_prefixes = value.try_to_optional()?;
+            }
+            "config_overrides_path" => {
+                self.inner.config_overrides_path = value.to_optional();
+            }
+            "config_overrides_path_env" => {
+                self.inner.config_overrides_path_env = value.to_optional();
+            }
+            "config_overrides_allowed_fields" => {
+                self.inner.config_overrides_allowed_fields = value.try_to_optional()?;
+            }
+            "verify_packed_resources_digests" => {
+                self.inner.verify_packed_resources_digests = value.to_bool();
+            }
+            "resources_materialization_directory" => {
+                self.inner.resources_materialization_directory = value.to_optional();
+            }
             attr => {
                 return Err(ValueError::OperationNotSupported {
                     op: UnsupportedOperation::SetAttr(attr.to_string()),
@@ -1141,6 +1184,12 @@ mod tests {
         env.eval("config.raw_allocator = 'jemalloc'")?;
         env.eval_assert("config.raw_allocator == 'jemalloc'")?;
 
+        env.eval("config.raw_allocator = 'mimalloc'")?;
+        env.eval_assert("config.raw_allocator == 'mimalloc'")?;
+
+        env.eval("config.raw_allocator = 'snmalloc'")?;
+        env.eval_assert("config.raw_allocator == 'snmalloc'")?;
+
         env.eval("config.raw_allocator = 'rust'")?;
         env.eval_assert("config.raw_allocator == 'rust'")?;
 
@@ -1215,4 +1264,79 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_write_import_trace_directory_env() -> Result<()> {
+        let mut env = get_env()?;
+
+        env.eval_assert("config.write_import_trace_directory_env == None")?;
+
+        env.eval("config.write_import_trace_directory_env = 'PYOXIDIZER_IMPORT_TRACE_DIR'")?;
+        env.eval_assert(
+            "config.write_import_trace_directory_env == 'PYOXIDIZER_IMPORT_TRACE_DIR'",
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lazy_module_prefixes() -> Result<()> {
+        let mut env = get_env()?;
+
+        env.eval_assert("config.lazy_module_prefixes == None")?;
+
+        env.eval("config.lazy_module_prefixes = ['foo', 'bar.baz']")?;
+        env.eval_assert("config.lazy_module_prefixes == ['foo', 'bar.baz']")?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_overrides() -> Result<()> {
+        let mut env = get_env()?;
+
+        env.eval_assert("config.config_overrides_path == None")?;
+        env.eval_assert("config.config_overrides_path_env == None")?;
+        env.eval_assert("config.config_overrides_allowed_fields == None")?;
+
+        env.eval("config.config_overrides_path = '$ORIGIN/overrides.toml'")?;
+        env.eval_assert("config.config_overrides_path == '$ORIGIN/overrides.toml'")?;
+
+        env.eval("config.config_overrides_path_env = 'APP_CONFIG'")?;
+        env.eval_assert("config.config_overrides_path_env == 'APP_CONFIG'")?;
+
+        env.eval("config.config_overrides_allowed_fields = ['module_search_paths', 'run_module']")?;
+        env.eval_assert(
+            "config.config_overrides_allowed_fields == ['module_search_paths', 'run_module']",
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_packed_resources_digests() -> Result<()> {
+        let mut env = get_env()?;
+
+        env.eval_assert("config.verify_packed_resources_digests == False")?;
+
+        env.eval("config.verify_packed_resources_digests = True")?;
+        env.eval_assert("config.verify_packed_resources_digests == True")?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resources_materialization_directory() -> Result<()> {
+        let mut env = get_env()?;
+
+        env.eval_assert("config.resources_materialization_directory == None")?;
+
+        env.eval("config.resources_materialization_directory = '$ORIGIN/resources'")?;
+        env.eval_assert("config.resources_materialization_directory == '$ORIGIN/resources'")?;
+
+        env.eval("config.resources_materialization_directory = None")?;
+        env.eval_assert("config.resources_materialization_directory == None")?;
+
+        Ok(())
+    }
 }
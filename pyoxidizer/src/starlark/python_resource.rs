@@ -23,7 +23,11 @@ use {
             {Mutable, TypedValue, Value, ValueResult},
         },
     },
-    std::convert::{TryFrom, TryInto},
+    starlark_dialect_build_targets::optional_dict_arg,
+    std::{
+        collections::HashMap,
+        convert::{TryFrom, TryInto},
+    },
 };
 
 #[derive(Clone, Debug)]
@@ -114,9 +118,11 @@ pub trait ResourceCollectionContext {
             "add_location",
             "add_location_fallback",
             "add_source",
+            "add_compressed_source",
             "add_bytecode_optimization_level_zero",
             "add_bytecode_optimization_level_one",
             "add_bytecode_optimization_level_two",
+            "add_annotations",
         ]
     }
 
@@ -146,6 +152,14 @@ pub trait ResourceCollectionContext {
                     None => Value::from(NoneType::None),
                 },
                 "add_source" => Value::new(context.store_source),
+                "add_compressed_source" => Value::new(context.store_compressed_source),
+                "add_annotations" => Value::try_from(
+                    context
+                        .annotations
+                        .iter()
+                        .map(|(k, v)| (k.clone(), String::from_utf8_lossy(v).to_string()))
+                        .collect::<HashMap<String, String>>(),
+                )?,
                 _ => panic!("this should not happen"),
             },
             None => Value::from(NoneType::None),
@@ -214,6 +228,26 @@ pub trait ResourceCollectionContext {
                         context.store_source = value.to_bool();
                         Ok(())
                     }
+                    "add_compressed_source" => {
+                        context.store_compressed_source = value.to_bool();
+                        Ok(())
+                    }
+                    "add_annotations" => {
+                        optional_dict_arg(attribute, "string", "string", &value)?;
+
+                        context.annotations.clear();
+
+                        if value.get_type() == "dict" {
+                            for key in &value.iter()? {
+                                context.annotations.insert(
+                                    key.to_string(),
+                                    value.at(key)?.to_string().into_bytes(),
+                                );
+                            }
+                        }
+
+                        Ok(())
+                    }
                     attr => panic!("set_attr_add_collection_context({}) called when it shouldn't have been", attr)
                 }
             },
@@ -870,6 +904,35 @@ mod tests {
         m.set_attr("add_source", Value::new(false)).unwrap();
         assert_eq!(m.get_attr("add_source").unwrap().to_bool(), false);
 
+        assert!(m.has_attr("add_compressed_source").unwrap());
+        assert_eq!(
+            m.get_attr("add_compressed_source").unwrap().get_type(),
+            "bool"
+        );
+        assert_eq!(
+            m.get_attr("add_compressed_source").unwrap().to_bool(),
+            false
+        );
+        m.set_attr("add_compressed_source", Value::new(true)).unwrap();
+        assert_eq!(m.get_attr("add_compressed_source").unwrap().to_bool(), true);
+
+        assert!(m.has_attr("add_annotations").unwrap());
+        assert_eq!(m.get_attr("add_annotations").unwrap().get_type(), "dict");
+        assert_eq!(m.get_attr("add_annotations").unwrap().length().unwrap(), 0);
+        let annotations = env.eval("{'build_id': '1234'}")?;
+        m.set_attr("add_annotations", annotations).unwrap();
+        assert_eq!(
+            m.get_attr("add_annotations")
+                .unwrap()
+                .at(Value::from("build_id"))
+                .unwrap(),
+            Value::from("1234")
+        );
+        assert!(m.set_attr("add_annotations", Value::from("foo")).is_err());
+        m.set_attr("add_annotations", Value::from(NoneType::None))
+            .unwrap();
+        assert_eq!(m.get_attr("add_annotations").unwrap().length().unwrap(), 0);
+
         assert!(m.has_attr("add_bytecode_optimization_level_zero").unwrap());
         assert_eq!(
             m.get_attr("add_bytecode_optimization_level_zero")
@@ -131,6 +131,12 @@ def make_exe(dist):
     # Use jemalloc as Python's memory allocator
     # python_config.raw_allocator = "jemalloc"
 
+    # Use mimalloc as Python's memory allocator
+    # python_config.raw_allocator = "mimalloc"
+
+    # Use snmalloc as Python's memory allocator
+    # python_config.raw_allocator = "snmalloc"
+
     # Use the system allocator as Python's memory allocator.
     # python_config.raw_allocator = "system"
 
@@ -152,6 +158,21 @@ def make_exe(dist):
     # by the given environment variable.
     # python_config.write_modules_directory_env = "/tmp/oxidized/loaded_modules"
 
+    # Write a JSON file describing where modules were imported from and how
+    # long importing them took to the directory specified by the given
+    # environment variable.
+    # python_config.write_import_trace_directory_env = "PYOXIDIZER_IMPORT_TRACE_DIR"
+
+    # Defer execution of modules in the given packages until first use.
+    # python_config.lazy_module_prefixes = ["<package>"]
+
+    # Allow settings to be overridden at run-time by a TOML file next to the
+    # executable or by a file pointed to by an environment variable. Only the
+    # listed settings can be overridden.
+    # python_config.config_overrides_path = "$ORIGIN/pyoxidizer-config.toml"
+    # python_config.config_overrides_path_env = "PYOXIDIZER_CONFIG"
+    # python_config.config_overrides_allowed_fields = ["module_search_paths", "run_module"]
+
     # Evaluate a string as Python code when the interpreter starts.
     # python_config.run_command = "<code>"
 
@@ -102,6 +102,10 @@ pub enum MemoryAllocatorBackend {
     System,
     /// Use jemalloc.
     Jemalloc,
+    /// Use mimalloc.
+    Mimalloc,
+    /// Use snmalloc.
+    Snmalloc,
     /// Use Rust's global allocator.
     Rust,
 }
@@ -111,6 +115,8 @@ impl ToString for MemoryAllocatorBackend {
         match self {
             Self::System => "system",
             Self::Jemalloc => "jemalloc",
+            Self::Mimalloc => "mimalloc",
+            Self::Snmalloc => "snmalloc",
             Self::Rust => "rust",
         }
         .to_string()
@@ -124,6 +130,8 @@ impl TryFrom<&str> for MemoryAllocatorBackend {
         match value {
             "system" => Ok(Self::System),
             "jemalloc" => Ok(Self::Jemalloc),
+            "mimalloc" => Ok(Self::Mimalloc),
+            "snmalloc" => Ok(Self::Snmalloc),
             "rust" => Ok(Self::Rust),
             _ => Err(format!("{} is not a valid memory allocator backend", value)),
         }
@@ -158,6 +166,20 @@ impl PythonRawAllocator {
         }
     }
 
+    pub fn mimalloc() -> Self {
+        Self {
+            backend: MemoryAllocatorBackend::Mimalloc,
+            ..PythonRawAllocator::default()
+        }
+    }
+
+    pub fn snmalloc() -> Self {
+        Self {
+            backend: MemoryAllocatorBackend::Snmalloc,
+            ..PythonRawAllocator::default()
+        }
+    }
+
     pub fn rust() -> Self {
         Self {
             backend: MemoryAllocatorBackend::Rust,
@@ -14,7 +14,11 @@ use {
         resource_collection::PythonResourceAddCollectionContext,
     },
     anyhow::Result,
-    std::{collections::HashMap, convert::TryFrom, iter::FromIterator},
+    std::{
+        collections::{BTreeMap, HashMap},
+        convert::TryFrom,
+        iter::FromIterator,
+    },
 };
 
 /// Denotes methods to filter extension modules.
@@ -442,9 +446,11 @@ impl PythonPackagingPolicy {
             location,
             location_fallback,
             store_source,
+            store_compressed_source: false,
             optimize_level_zero: self.bytecode_optimize_level_zero,
             optimize_level_one: self.bytecode_optimize_level_one,
             optimize_level_two: self.bytecode_optimize_level_two,
+            annotations: BTreeMap::new(),
         }
     }
 
@@ -21,7 +21,10 @@ use {
         },
     },
     anyhow::{anyhow, Context, Result},
-    python_packed_resources::data::{Resource, ResourceFlavor},
+    python_packed_resources::{
+        data::{Resource, ResourceField, ResourceFlavor},
+        writer::{BlobFiles, StreamingWriter, WriterOptions},
+    },
     std::{
         borrow::Cow,
         collections::{BTreeMap, BTreeSet, HashMap},
@@ -86,9 +89,82 @@ pub struct PrePackagedResource {
     pub file_executable: bool,
     pub file_data_embedded: Option<DataLocation>,
     pub file_data_utf8_relative_path: Option<(PathBuf, DataLocation)>,
+    pub annotations: Option<BTreeMap<String, Vec<u8>>>,
+    // Source code to store compressed.
+    pub in_memory_compressed_source: Option<DataLocation>,
 }
 
 impl PrePackagedResource {
+    /// Convert the instance to a `Resource` whose file backed payloads are streamed.
+    ///
+    /// This is like `to_resource()` except payload data backed by
+    /// `DataLocation::Path` is not read. Instead, the returned `Resource`
+    /// declares these payloads with empty data and the returned `BlobFiles`
+    /// maps them to their files, as expected by
+    /// `StreamingWriter::add_resource_with_files()`.
+    pub fn to_streamed_resource<'a>(
+        &self,
+        compiler: &mut dyn PythonBytecodeCompiler,
+    ) -> Result<(Resource<'a, u8>, BlobFiles, Vec<FileInstall>)> {
+        let mut files = BlobFiles::new();
+        let mut placeholder = self.clone();
+
+        let mut stream = |field: ResourceField, name: Option<&str>, location: &mut DataLocation| {
+            if let DataLocation::Path(path) = location {
+                files.insert((field, name.map(|name| name.to_string())), path.clone());
+                *location = DataLocation::Memory(vec![]);
+            }
+        };
+
+        for (field, location) in [
+            (
+                ResourceField::InMemorySource,
+                &mut placeholder.in_memory_source,
+            ),
+            (
+                ResourceField::InMemoryExtensionModuleSharedLibrary,
+                &mut placeholder.in_memory_extension_module_shared_library,
+            ),
+            (
+                ResourceField::InMemorySharedLibrary,
+                &mut placeholder.in_memory_shared_library,
+            ),
+            (
+                ResourceField::FileDataEmbedded,
+                &mut placeholder.file_data_embedded,
+            ),
+        ]
+        .iter_mut()
+        {
+            if let Some(location) = location {
+                stream(*field, None, location);
+            }
+        }
+
+        for (field, locations) in [
+            (
+                ResourceField::InMemoryResourcesData,
+                &mut placeholder.in_memory_resources,
+            ),
+            (
+                ResourceField::InMemoryDistributionResource,
+                &mut placeholder.in_memory_distribution_resources,
+            ),
+        ]
+        .iter_mut()
+        {
+            if let Some(locations) = locations {
+                for (name, location) in locations.iter_mut() {
+                    stream(*field, Some(name), location);
+                }
+            }
+        }
+
+        let (resource, installs) = placeholder.to_resource(compiler)?;
+
+        Ok((resource, files, installs))
+    }
+
     /// Convert the instance to a `Resource`.
     ///
     /// This will compile bytecode from source code using the specified compiler.
@@ -378,6 +454,22 @@ impl PrePackagedResource {
             } else {
                 None
             },
+            annotations: if let Some(annotations) = &self.annotations {
+                Some(
+                    annotations
+                        .iter()
+                        .map(|(key, value)| (Cow::Owned(key.clone()), Cow::Owned(value.clone())))
+                        .collect(),
+                )
+            } else {
+                None
+            },
+            in_memory_compressed_source: if let Some(location) = &self.in_memory_compressed_source {
+                Some(Cow::Owned(compress_source(&location.resolve()?)?))
+            } else {
+                None
+            },
+            compressed_fields: vec![],
         };
 
         if let Some((prefix, filename, location)) = &self.relative_path_shared_library {
@@ -388,6 +480,19 @@ impl PrePackagedResource {
     }
 }
 
+/// Compress Python module source for storage as in-memory compressed source.
+#[cfg(feature = "compression")]
+fn compress_source(source: &[u8]) -> Result<Vec<u8>> {
+    zstd::stream::encode_all(source, 0).context("zstd compressing module source")
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress_source(_source: &[u8]) -> Result<Vec<u8>> {
+    Err(anyhow!(
+        "compressed module source requires the compression feature"
+    ))
+}
+
 /// Fill in missing data on parent packages.
 ///
 /// When resources are added, their parent packages could be missing
@@ -557,6 +662,13 @@ pub struct PythonResourceAddCollectionContext {
     /// controlling this behavior.
     pub store_source: bool,
 
+    /// Whether to store compressed Python source code for a `PythonModuleSource`.
+    ///
+    /// Compressed source isn't used for importing. It allows source to be
+    /// resolved at run-time, e.g. for tracebacks, without storing plain
+    /// source. Has no effect if `store_source` is true.
+    pub store_compressed_source: bool,
+
     /// Whether to store Python bytecode for optimization level 0.
     pub optimize_level_zero: bool,
 
@@ -565,6 +677,13 @@ pub struct PythonResourceAddCollectionContext {
 
     /// Whether to store Python bytecode for optimization level 2.
     pub optimize_level_two: bool,
+
+    /// Annotations to attach to the resource.
+    ///
+    /// Annotations are recorded on the entry the resource is added to. For
+    /// package resources and package distribution resources, this is the
+    /// entry of the package.
+    pub annotations: BTreeMap<String, Vec<u8>>,
 }
 
 impl PythonResourceAddCollectionContext {
@@ -574,9 +693,11 @@ impl PythonResourceAddCollectionContext {
         self.location = other.location.clone();
         self.location_fallback = other.location_fallback.clone();
         self.store_source = other.store_source;
+        self.store_compressed_source = other.store_compressed_source;
         self.optimize_level_zero = other.optimize_level_zero;
         self.optimize_level_one = other.optimize_level_one;
         self.optimize_level_two = other.optimize_level_two;
+        self.annotations = other.annotations.clone();
     }
 }
 
@@ -592,15 +713,21 @@ pub struct CompiledResourcesCollection<'a> {
 
 impl<'a> CompiledResourcesCollection<'a> {
     /// Write resources to packed resources data, version 1.
+    ///
+    /// A resource lookup table is written so readers can resolve resources
+    /// by name without parsing all resources. With the `digests` feature,
+    /// digests of all blob sections are recorded so readers can verify data
+    /// integrity. Identical blob data is only stored once.
     pub fn write_packed_resources<W: std::io::Write>(&self, writer: &mut W) -> Result<()> {
-        python_packed_resources::writer::write_packed_resources_v3(
-            &self
-                .resources
-                .values()
-                .cloned()
-                .collect::<Vec<Resource<'a, u8>>>(),
+        python_packed_resources::writer::write_packed_resources(
+            &self.resources.values().collect::<Vec<_>>(),
             writer,
-            None,
+            &python_packed_resources::writer::WriterOptions {
+                lookup_table: true,
+                section_digests: cfg!(feature = "digests"),
+                deduplicate_blobs: true,
+                ..Default::default()
+            },
         )
     }
 }
@@ -705,6 +832,23 @@ impl PythonResourceCollector {
         Box::new(self.resources.iter())
     }
 
+    /// Attach annotations to a resource in this collector.
+    ///
+    /// Existing annotations having the same name are replaced. This is a
+    /// no-op if the resource doesn't exist or `annotations` is empty.
+    pub fn annotate_resource(&mut self, name: &str, annotations: &BTreeMap<String, Vec<u8>>) {
+        if annotations.is_empty() {
+            return;
+        }
+
+        if let Some(entry) = self.resources.get_mut(name) {
+            entry
+                .annotations
+                .get_or_insert_with(BTreeMap::new)
+                .extend(annotations.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+    }
+
     /// Add Python module source with a specific location.
     pub fn add_python_module_source(
         &mut self,
@@ -737,12 +881,37 @@ impl PythonResourceCollector {
         Ok(())
     }
 
+    /// Add compressed Python module source.
+    ///
+    /// Compressed source is always stored in memory.
+    pub fn add_python_module_compressed_source(
+        &mut self,
+        module: &PythonModuleSource,
+    ) -> Result<()> {
+        self.check_policy(AbstractResourceLocation::InMemory)?;
+
+        let entry = self
+            .resources
+            .entry(module.name.clone())
+            .or_insert_with(|| PrePackagedResource {
+                name: module.name.clone(),
+                ..PrePackagedResource::default()
+            });
+
+        entry.is_module = true;
+        entry.is_package = module.is_package;
+        entry.in_memory_compressed_source = Some(module.source.clone());
+
+        Ok(())
+    }
+
     /// Add Python module source using an add context to influence operation.
     ///
     /// All of the context's properties are respected. This includes doing
     /// nothing if `include` is false, not adding source if `store_source` is
-    /// false, and automatically deriving a bytecode request if the
-    /// `optimize_level_*` fields are set.
+    /// false, adding compressed source if `store_compressed_source` is true
+    /// and `store_source` is false, and automatically deriving a bytecode
+    /// request if the `optimize_level_*` fields are set.
     ///
     /// This method is a glorified proxy to other `add_*` methods: it
     /// simply contains the logic for expanding the context's wishes into
@@ -762,6 +931,8 @@ impl PythonResourceCollector {
                 &add_context.location,
                 &add_context.location_fallback,
             )?;
+        } else if add_context.store_compressed_source {
+            self.add_python_module_compressed_source(module)?;
         }
 
         // Derive bytecode as requested.
@@ -795,6 +966,8 @@ impl PythonResourceCollector {
             )?;
         }
 
+        self.annotate_resource(&module.name, &add_context.annotations);
+
         Ok(())
     }
 
@@ -903,7 +1076,11 @@ impl PythonResourceCollector {
                     Ok(())
                 }
             }
-        }
+        }?;
+
+        self.annotate_resource(&module.name, &add_context.annotations);
+
+        Ok(())
     }
 
     /// Add Python module bytecode derived from source code to the collection.
@@ -1009,7 +1186,11 @@ impl PythonResourceCollector {
                     Ok(())
                 }
             }
-        }
+        }?;
+
+        self.annotate_resource(&module.name, &add_context.annotations);
+
+        Ok(())
     }
 
     /// Add resource data to a given location.
@@ -1081,7 +1262,11 @@ impl PythonResourceCollector {
             &resource.into(),
             &add_context.location,
             &add_context.location_fallback,
-        )
+        )?;
+
+        self.annotate_resource(&resource.leaf_package, &add_context.annotations);
+
+        Ok(())
     }
 
     /// Add a Python package distribution resource to a given location.
@@ -1152,7 +1337,11 @@ impl PythonResourceCollector {
             &resource.into(),
             &add_context.location,
             &add_context.location_fallback,
-        )
+        )?;
+
+        self.annotate_resource(&resource.package, &add_context.annotations);
+
+        Ok(())
     }
 
     /// Add a Python extension module using an add context.
@@ -1296,6 +1485,7 @@ impl PythonResourceCollector {
             }
 
             self.add_builtin_python_extension_module(extension_module)?;
+            self.annotate_resource(&extension_module.name, &add_context.annotations);
 
             Ok(Some(build_context))
         } else {
@@ -1313,6 +1503,7 @@ impl PythonResourceCollector {
             };
 
             self.add_python_extension_module(extension_module, &location)?;
+            self.annotate_resource(&extension_module.name, &add_context.annotations);
 
             Ok(None)
         }
@@ -1516,7 +1707,11 @@ impl PythonResourceCollector {
             &file.into(),
             &add_context.location,
             &add_context.location_fallback,
-        )
+        )?;
+
+        self.annotate_resource(&file.path_string(), &add_context.annotations);
+
+        Ok(())
     }
 
     fn add_python_resource_with_locations(
@@ -1669,6 +1864,46 @@ impl PythonResourceCollector {
         Ok(res)
     }
 
+    /// Compiles resources and writes them to packed resources data.
+    ///
+    /// This is equivalent to `compile_resources()` followed by
+    /// `CompiledResourcesCollection::write_packed_resources()` except resources
+    /// are compiled and written one at a time and payload data backed by files
+    /// is streamed to `writer` instead of being read into memory. Use this to
+    /// bound memory usage when resources hold large amounts of data.
+    ///
+    /// Returns the names of the written resources and the extra file installs
+    /// for them.
+    pub fn write_packed_resources_streaming<W: std::io::Write>(
+        &self,
+        compiler: &mut dyn PythonBytecodeCompiler,
+        writer: &mut W,
+    ) -> Result<(Vec<String>, Vec<FileInstall>)> {
+        let mut input_resources = self.resources.clone();
+        populate_parent_packages(&mut input_resources)?;
+
+        let mut stream_writer = StreamingWriter::new(&WriterOptions {
+            lookup_table: true,
+            section_digests: cfg!(feature = "digests"),
+            deduplicate_blobs: true,
+            ..Default::default()
+        })?;
+        let mut extra_files = Vec::new();
+
+        for resource in input_resources.values() {
+            let (entry, files, installs) = resource.to_streamed_resource(compiler)?;
+
+            stream_writer
+                .add_resource_with_files(&entry, &files)
+                .with_context(|| format!("writing resource {}", resource.name))?;
+            extra_files.extend(installs);
+        }
+
+        stream_writer.finish(writer)?;
+
+        Ok((input_resources.keys().cloned().collect(), extra_files))
+    }
+
     /// Compiles resources into a finalized collection.
     ///
     /// This will take all resources collected so far and convert them into
@@ -3004,6 +3239,77 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_add_with_context_annotations() -> Result<()> {
+        let mut r = PythonResourceCollector::new(
+            vec![AbstractResourceLocation::InMemory],
+            vec![],
+            false,
+            false,
+            DEFAULT_CACHE_TAG,
+        );
+
+        let module = PythonModuleSource {
+            name: "foo".to_string(),
+            source: DataLocation::Memory(vec![42]),
+            is_package: true,
+            cache_tag: DEFAULT_CACHE_TAG.to_string(),
+            is_stdlib: false,
+            is_test: false,
+        };
+
+        let mut add_context = PythonResourceAddCollectionContext {
+            include: true,
+            location: ConcreteResourceLocation::InMemory,
+            location_fallback: None,
+            store_source: true,
+            store_compressed_source: false,
+            optimize_level_zero: false,
+            optimize_level_one: false,
+            optimize_level_two: false,
+            annotations: BTreeMap::new(),
+        };
+        add_context
+            .annotations
+            .insert("build_id".to_string(), b"1234".to_vec());
+
+        r.add_python_module_source_with_context(&module, &add_context)?;
+
+        // Package resources annotate their package.
+        add_context
+            .annotations
+            .insert("wheel".to_string(), b"foo.whl".to_vec());
+        r.add_python_package_resource_with_context(
+            &PythonPackageResource {
+                leaf_package: "foo".to_string(),
+                relative_name: "resource.txt".to_string(),
+                data: DataLocation::Memory(vec![42]),
+                is_stdlib: false,
+                is_test: false,
+            },
+            &add_context,
+        )?;
+
+        let mut expected = BTreeMap::new();
+        expected.insert("build_id".to_string(), b"1234".to_vec());
+        expected.insert("wheel".to_string(), b"foo.whl".to_vec());
+        assert_eq!(r.resources.get("foo").unwrap().annotations, Some(expected));
+
+        let mut compiler = FakeBytecodeCompiler { magic_number: 42 };
+        let resources = r.compile_resources(&mut compiler)?;
+        let annotations = resources
+            .resources
+            .get("foo")
+            .unwrap()
+            .annotations
+            .as_ref()
+            .unwrap();
+        assert_eq!(annotations.get("build_id").unwrap().as_ref(), b"1234");
+        assert_eq!(annotations.get("wheel").unwrap().as_ref(), b"foo.whl");
+
+        Ok(())
+    }
+
     #[test]
     fn test_add_module_source_with_context() -> Result<()> {
         let mut r = PythonResourceCollector::new(
@@ -3028,9 +3334,11 @@ mod tests {
             location: ConcreteResourceLocation::InMemory,
             location_fallback: None,
             store_source: false,
+            store_compressed_source: false,
             optimize_level_zero: false,
             optimize_level_one: false,
             optimize_level_two: false,
+            annotations: BTreeMap::new(),
         };
 
         // include=false is a noop.
@@ -3122,6 +3430,75 @@ mod tests {
         r.resources.clear();
         add_context.optimize_level_two = false;
 
+        // store_compressed_source stores compressed source.
+        add_context.store_compressed_source = true;
+        r.add_python_module_source_with_context(&module, &add_context)?;
+        assert_eq!(
+            r.resources.get(&module.name),
+            Some(&PrePackagedResource {
+                is_module: true,
+                name: module.name.clone(),
+                is_package: module.is_package,
+                in_memory_compressed_source: Some(module.source.clone()),
+                ..PrePackagedResource::default()
+            })
+        );
+
+        r.resources.clear();
+
+        // store_compressed_source is ignored when source is stored.
+        add_context.store_source = true;
+        r.add_python_module_source_with_context(&module, &add_context)?;
+        assert_eq!(
+            r.resources.get(&module.name),
+            Some(&PrePackagedResource {
+                is_module: true,
+                name: module.name.clone(),
+                is_package: module.is_package,
+                in_memory_source: Some(module.source.clone()),
+                ..PrePackagedResource::default()
+            })
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compile_compressed_source() -> Result<()> {
+        let mut r = PythonResourceCollector::new(
+            vec![AbstractResourceLocation::InMemory],
+            vec![],
+            false,
+            false,
+            DEFAULT_CACHE_TAG,
+        );
+
+        r.add_python_module_compressed_source(&PythonModuleSource {
+            name: "foo".to_string(),
+            source: DataLocation::Memory(b"import io".to_vec()),
+            is_package: false,
+            cache_tag: DEFAULT_CACHE_TAG.to_string(),
+            is_stdlib: false,
+            is_test: false,
+        })?;
+
+        let mut compiler = FakeBytecodeCompiler { magic_number: 42 };
+        let resources = r.compile_resources(&mut compiler)?;
+
+        let resource = resources.resources.get("foo").unwrap();
+        assert!(resource.in_memory_source.is_none());
+        assert_eq!(
+            zstd::stream::decode_all(
+                resource
+                    .in_memory_compressed_source
+                    .as_ref()
+                    .unwrap()
+                    .as_ref()
+            )?,
+            b"import io"
+        );
+
         Ok(())
     }
 
@@ -3256,9 +3633,11 @@ mod tests {
             location: ConcreteResourceLocation::InMemory,
             location_fallback: None,
             store_source: false,
+            store_compressed_source: false,
             optimize_level_zero: false,
             optimize_level_one: false,
             optimize_level_two: false,
+            annotations: BTreeMap::new(),
         };
 
         // include=false is a noop.
@@ -3454,9 +3833,11 @@ mod tests {
             location: ConcreteResourceLocation::InMemory,
             location_fallback: None,
             store_source: false,
+            store_compressed_source: false,
             optimize_level_zero: false,
             optimize_level_one: false,
             optimize_level_two: false,
+            annotations: BTreeMap::new(),
         };
 
         // include=false is a noop.
@@ -3710,9 +4091,11 @@ mod tests {
             location: ConcreteResourceLocation::InMemory,
             location_fallback: None,
             store_source: false,
+            store_compressed_source: false,
             optimize_level_zero: false,
             optimize_level_one: false,
             optimize_level_two: false,
+            annotations: BTreeMap::new(),
         };
 
         // include=false is a noop.
@@ -3932,9 +4315,11 @@ mod tests {
             location: ConcreteResourceLocation::InMemory,
             location_fallback: None,
             store_source: false,
+            store_compressed_source: false,
             optimize_level_zero: false,
             optimize_level_one: false,
             optimize_level_two: false,
+            annotations: BTreeMap::new(),
         };
 
         // include=false is a noop.
@@ -4484,9 +4869,11 @@ mod tests {
             location: ConcreteResourceLocation::InMemory,
             location_fallback: None,
             store_source: false,
+            store_compressed_source: false,
             optimize_level_zero: false,
             optimize_level_one: false,
             optimize_level_two: false,
+            annotations: BTreeMap::new(),
         };
 
         // include=false is a noop.
@@ -4531,6 +4918,69 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_write_packed_resources_streaming() -> Result<()> {
+        let td = tempdir::TempDir::new("pyoxidizer-test")?;
+        let resource_path = td.path().join("resource.txt");
+        std::fs::write(&resource_path, b"resource data")?;
+
+        let mut r = PythonResourceCollector::new(
+            vec![AbstractResourceLocation::InMemory],
+            vec![],
+            false,
+            false,
+            DEFAULT_CACHE_TAG,
+        );
+        r.add_python_module_source(
+            &PythonModuleSource {
+                name: "foo.bar".to_string(),
+                source: DataLocation::Memory(b"import foo".to_vec()),
+                is_package: false,
+                cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                is_stdlib: false,
+                is_test: false,
+            },
+            &ConcreteResourceLocation::InMemory,
+        )?;
+        r.add_python_package_resource(
+            &PythonPackageResource {
+                leaf_package: "foo".to_string(),
+                relative_name: "resource.txt".to_string(),
+                data: DataLocation::Path(resource_path),
+                is_stdlib: false,
+                is_test: false,
+            },
+            &ConcreteResourceLocation::InMemory,
+        )?;
+
+        let mut compiler = FakeBytecodeCompiler { magic_number: 42 };
+
+        let mut data = Vec::new();
+        let (names, installs) = r.write_packed_resources_streaming(&mut compiler, &mut data)?;
+        assert!(installs.is_empty());
+
+        let compiled = r.compile_resources(&mut compiler)?;
+        assert_eq!(
+            names,
+            compiled.resources.keys().cloned().collect::<Vec<_>>()
+        );
+
+        let mut expected = Vec::new();
+        compiled.write_packed_resources(&mut expected)?;
+        assert_eq!(data.len(), expected.len());
+
+        let resources = python_packed_resources::parser::load_resources(&data)
+            .map_err(|e| anyhow!(e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!(e))?;
+        assert_eq!(
+            resources,
+            compiled.resources.values().cloned().collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_find_dunder_file() -> Result<()> {
         let mut r = PythonResourceCollector::new(
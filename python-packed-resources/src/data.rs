@@ -9,6 +9,38 @@ use std::{borrow::Cow, collections::HashMap, convert::TryFrom, iter::FromIterato
 /// Header value for version 2 of resources payload.
 pub const HEADER_V3: &[u8] = b"pyembed\x03";
 
+/// Header value for version 4 of resources payload.
+///
+/// Version 4 records the length of the value of every index field, allowing
+/// parsers to skip fields they don't know about.
+pub const HEADER_V4: &[u8] = b"pyembed\x04";
+
+/// Trailer identifying an ed25519 signature appended to packed resources data.
+///
+/// Signed data consists of the packed resources data, followed by a 64 byte
+/// ed25519 signature over that data, followed by this trailer.
+pub const SIGNATURE_TRAILER_ED25519: &[u8] = b"pyembed-ed25519\x01";
+
+/// Resource field type value of the blob section holding the resource lookup table.
+///
+/// This value only appears in the blob index. It never appears in the
+/// resources index.
+pub const LOOKUP_TABLE_BLOB_SECTION: u8 = 0xfe;
+
+/// Compute the hash of a resource name as used by the resource lookup table.
+///
+/// This is the 64-bit FNV-1a hash of the UTF-8 encoded name.
+pub fn resource_name_hash(name: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+
+    for b in name.as_bytes() {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    hash
+}
+
 /// Defines the type of a resource.
 ///
 /// This is deprecated in favor of individual boolean fields on resources
@@ -81,6 +113,50 @@ impl Into<u8> for &BlobInteriorPadding {
     }
 }
 
+/// Defines the compression applied to entries in a blob section.
+///
+/// Each entry in a compressed blob section is compressed independently so
+/// individual resources can be decompressed without touching their neighbors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlobSectionCompression {
+    /// No compression.
+    ///
+    /// Entries hold their raw data.
+    None = 0x01,
+
+    /// Zstandard compression.
+    ///
+    /// Every entry is a standalone zstd frame.
+    Zstd = 0x02,
+}
+
+impl Default for BlobSectionCompression {
+    fn default() -> Self {
+        BlobSectionCompression::None
+    }
+}
+
+impl Into<u8> for &BlobSectionCompression {
+    fn into(self) -> u8 {
+        match self {
+            BlobSectionCompression::None => 0x01,
+            BlobSectionCompression::Zstd => 0x02,
+        }
+    }
+}
+
+impl TryFrom<u8> for BlobSectionCompression {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(BlobSectionCompression::None),
+            0x02 => Ok(BlobSectionCompression::Zstd),
+            _ => Err("invalid value for compression field"),
+        }
+    }
+}
+
 /// Describes a blob section field type in the blob index.
 #[derive(Debug, PartialEq, PartialOrd)]
 pub enum BlobSectionField {
@@ -90,6 +166,9 @@ pub enum BlobSectionField {
     ResourceFieldType = 0x03,
     RawPayloadLength = 0x04,
     InteriorPadding = 0x05,
+    Compression = 0x06,
+    Sha256Digest = 0x07,
+    Deduplicated = 0x08,
 }
 
 impl Into<u8> for BlobSectionField {
@@ -100,6 +179,9 @@ impl Into<u8> for BlobSectionField {
             BlobSectionField::ResourceFieldType => 0x02,
             BlobSectionField::RawPayloadLength => 0x03,
             BlobSectionField::InteriorPadding => 0x04,
+            BlobSectionField::Compression => 0x05,
+            BlobSectionField::Sha256Digest => 0x06,
+            BlobSectionField::Deduplicated => 0x07,
             BlobSectionField::EndOfEntry => 0xff,
         }
     }
@@ -115,6 +197,9 @@ impl TryFrom<u8> for BlobSectionField {
             0x02 => Ok(BlobSectionField::ResourceFieldType),
             0x03 => Ok(BlobSectionField::RawPayloadLength),
             0x04 => Ok(BlobSectionField::InteriorPadding),
+            0x05 => Ok(BlobSectionField::Compression),
+            0x06 => Ok(BlobSectionField::Sha256Digest),
+            0x07 => Ok(BlobSectionField::Deduplicated),
             0xff => Ok(BlobSectionField::EndOfEntry),
             _ => Err("invalid blob index field type"),
         }
@@ -156,6 +241,8 @@ pub enum ResourceField {
     FileExecutable = 0x1c,
     FileDataEmbedded = 0x1d,
     FileDataUtf8RelativePath = 0x1e,
+    Annotations = 0x1f,
+    InMemoryCompressedSource = 0x20,
 }
 
 impl Into<u8> for ResourceField {
@@ -192,6 +279,8 @@ impl Into<u8> for ResourceField {
             ResourceField::FileExecutable => 0x1c,
             ResourceField::FileDataEmbedded => 0x1d,
             ResourceField::FileDataUtf8RelativePath => 0x1e,
+            ResourceField::Annotations => 0x1f,
+            ResourceField::InMemoryCompressedSource => 0x20,
             ResourceField::EndOfEntry => 0xff,
         }
     }
@@ -233,12 +322,35 @@ impl TryFrom<u8> for ResourceField {
             0x1c => Ok(ResourceField::FileExecutable),
             0x1d => Ok(ResourceField::FileDataEmbedded),
             0x1e => Ok(ResourceField::FileDataUtf8RelativePath),
+            0x1f => Ok(ResourceField::Annotations),
+            0x20 => Ok(ResourceField::InMemoryCompressedSource),
             0xff => Ok(ResourceField::EndOfEntry),
             _ => Err("invalid field type"),
         }
     }
 }
 
+impl ResourceField {
+    /// Whether blob data for this field may be stored compressed.
+    ///
+    /// Only fields holding opaque data payloads can be compressed. Names and
+    /// paths are always stored raw so they can be referenced without copying.
+    pub fn supports_compression(self) -> bool {
+        matches!(
+            self,
+            ResourceField::InMemorySource
+                | ResourceField::InMemoryBytecode
+                | ResourceField::InMemoryBytecodeOpt1
+                | ResourceField::InMemoryBytecodeOpt2
+                | ResourceField::InMemoryExtensionModuleSharedLibrary
+                | ResourceField::InMemoryResourcesData
+                | ResourceField::InMemoryDistributionResource
+                | ResourceField::InMemorySharedLibrary
+                | ResourceField::FileDataEmbedded
+        )
+    }
+}
+
 /// Represents an indexed resource.
 ///
 /// The resource has a name and type affinity via various `is_*` fields.
@@ -351,6 +463,29 @@ where
 
     /// Holds arbitrary file data in a relative path encoded in UTF-8.
     pub file_data_utf8_relative_path: Option<Cow<'a, str>>,
+
+    /// Mapping of annotation names to values.
+    ///
+    /// Annotations hold arbitrary metadata attached to the resource by its
+    /// producer, such as a build identifier or the name of the wheel the
+    /// resource came from. They have no meaning to the parser.
+    pub annotations: Option<HashMap<Cow<'a, str>, Cow<'a, [X]>>>,
+
+    /// Python module source code compressed as a zstd frame.
+    ///
+    /// Unlike `in_memory_source`, this isn't used to import the module. It
+    /// allows source code to be obtained on demand (e.g. for tracebacks)
+    /// without storing plain source. The data is stored as-is and is only
+    /// decompressed by whoever consumes it.
+    pub in_memory_compressed_source: Option<Cow<'a, [X]>>,
+
+    /// Fields whose values hold zstd compressed data.
+    ///
+    /// The parser doesn't decompress data read from compressed blob sections.
+    /// Instead, it records the affected fields here so data is only
+    /// decompressed when it is accessed. Use `field_data()` or `decompress()`
+    /// to obtain the actual values of these fields.
+    pub compressed_fields: Vec<ResourceField>,
 }
 
 impl<'a, X> Default for Resource<'a, X>
@@ -388,6 +523,9 @@ where
             file_executable: false,
             file_data_embedded: None,
             file_data_utf8_relative_path: None,
+            annotations: None,
+            in_memory_compressed_source: None,
+            compressed_fields: vec![],
         }
     }
 }
@@ -512,6 +650,112 @@ where
                 .file_data_utf8_relative_path
                 .as_ref()
                 .map(|value| Cow::Owned(value.clone().into_owned())),
+            annotations: self.annotations.as_ref().map(|value| {
+                HashMap::from_iter(value.iter().map(|(k, v)| {
+                    (
+                        Cow::Owned(k.clone().into_owned()),
+                        Cow::Owned(v.clone().into_owned()),
+                    )
+                }))
+            }),
+            in_memory_compressed_source: self
+                .in_memory_compressed_source
+                .as_ref()
+                .map(|value| Cow::Owned(value.clone().into_owned())),
+            compressed_fields: self.compressed_fields.clone(),
+        }
+    }
+}
+
+/// Decompress a zstd frame holding the value of a resource field.
+#[cfg(feature = "compression")]
+fn decompress_zstd(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    zstd::stream::decode_all(data).map_err(|_| "error decompressing blob data")
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress_zstd(_data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    Err("zstd decompression support not enabled")
+}
+
+/// Decompress the value of a resource field in place.
+fn decompress_value(value: &mut Option<Cow<[u8]>>) -> Result<(), &'static str> {
+    if let Some(data) = value {
+        *data = Cow::Owned(decompress_zstd(data)?);
+    }
+
+    Ok(())
+}
+
+/// Decompress every value of a resource field holding named entries in place.
+fn decompress_map(value: &mut Option<HashMap<Cow<str>, Cow<[u8]>>>) -> Result<(), &'static str> {
+    if let Some(map) = value {
+        for data in map.values_mut() {
+            *data = Cow::Owned(decompress_zstd(data)?);
         }
     }
+
+    Ok(())
+}
+
+impl<'a> Resource<'a, u8> {
+    /// Decompress the module source held in `in_memory_compressed_source`.
+    ///
+    /// Returns `Ok(None)` if the resource doesn't have compressed source.
+    pub fn decompressed_source(&self) -> Result<Option<Vec<u8>>, &'static str> {
+        match &self.in_memory_compressed_source {
+            Some(data) => Ok(Some(decompress_zstd(data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Resolve the actual data of a value of a field, decompressing it if necessary.
+    ///
+    /// `data` must be the value of `field` of this resource or, for fields
+    /// holding named entries, the value of one of its entries.
+    pub fn field_data<'d>(
+        &self,
+        field: ResourceField,
+        data: &'d [u8],
+    ) -> Result<Cow<'d, [u8]>, &'static str> {
+        if self.compressed_fields.contains(&field) {
+            Ok(Cow::Owned(decompress_zstd(data)?))
+        } else {
+            Ok(Cow::Borrowed(data))
+        }
+    }
+
+    /// Decompress the values of all fields holding compressed data.
+    ///
+    /// Afterwards, `compressed_fields` is empty.
+    pub fn decompress(&mut self) -> Result<(), &'static str> {
+        for field in std::mem::take(&mut self.compressed_fields) {
+            match field {
+                ResourceField::InMemorySource => decompress_value(&mut self.in_memory_source)?,
+                ResourceField::InMemoryBytecode => decompress_value(&mut self.in_memory_bytecode)?,
+                ResourceField::InMemoryBytecodeOpt1 => {
+                    decompress_value(&mut self.in_memory_bytecode_opt1)?
+                }
+                ResourceField::InMemoryBytecodeOpt2 => {
+                    decompress_value(&mut self.in_memory_bytecode_opt2)?
+                }
+                ResourceField::InMemoryExtensionModuleSharedLibrary => {
+                    decompress_value(&mut self.in_memory_extension_module_shared_library)?
+                }
+                ResourceField::InMemoryResourcesData => {
+                    decompress_map(&mut self.in_memory_package_resources)?
+                }
+                ResourceField::InMemoryDistributionResource => {
+                    decompress_map(&mut self.in_memory_distribution_resources)?
+                }
+                ResourceField::InMemorySharedLibrary => {
+                    decompress_value(&mut self.in_memory_shared_library)?
+                }
+                ResourceField::FileDataEmbedded => decompress_value(&mut self.file_data_embedded)?,
+                _ => return Err("compression not supported for resource field"),
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -6,9 +6,11 @@
 
 use {
     super::data::{
-        BlobInteriorPadding, BlobSectionField, Resource, ResourceField, ResourceFlavor, HEADER_V3,
+        resource_name_hash, BlobInteriorPadding, BlobSectionCompression, BlobSectionField,
+        Resource, ResourceField, ResourceFlavor, HEADER_V3, HEADER_V4, LOOKUP_TABLE_BLOB_SECTION,
+        SIGNATURE_TRAILER_ED25519,
     },
-    byteorder::{LittleEndian, ReadBytesExt},
+    byteorder::{ByteOrder, LittleEndian, ReadBytesExt},
     std::{
         borrow::Cow,
         collections::{HashMap, HashSet},
@@ -19,6 +21,10 @@ use {
     },
 };
 
+#[cfg(feature = "signatures")]
+use ed25519_dalek::{PublicKey, Signature};
+#[cfg(feature = "digests")]
+use sha2::{Digest, Sha256};
 #[cfg(unix)]
 use std::os::unix::ffi::OsStrExt;
 #[cfg(windows)]
@@ -26,21 +32,53 @@ use {std::ffi::OsString, std::os::windows::ffi::OsStringExt, std::path::PathBuf}
 
 /// Represents a blob section in the blob index.
 #[derive(Debug)]
-struct BlobSection {
+struct BlobSection<'a> {
     resource_field: u8,
     raw_payload_length: usize,
     interior_padding: Option<BlobInteriorPadding>,
+    compression: Option<BlobSectionCompression>,
+    sha256: Option<&'a [u8]>,
+    deduplicated: bool,
+}
+
+/// Holds data needed to verify the integrity of a blob section.
+#[derive(Clone, Copy, Debug)]
+struct BlobSectionIntegrity<'a> {
+    /// The section's data. `None` if it extends beyond the end of the data.
+    data: Option<&'a [u8]>,
+    /// The recorded SHA-256 digest of the section.
+    sha256: Option<&'a [u8]>,
 }
 
 /// Holds state used to read an individual blob section.
 #[derive(Clone, Copy, Debug)]
 struct BlobSectionReadState {
+    start_offset: usize,
+    end_offset: usize,
     offset: usize,
     interior_padding: BlobInteriorPadding,
+    compression: BlobSectionCompression,
+    /// Whether entries are referenced through a table of offsets.
+    deduplicated: bool,
 }
 
+/// An entry in the resource lookup table.
+#[derive(Clone, Copy, Debug)]
+struct LookupEntry<'a> {
+    /// Name of the resource this entry refers to.
+    name: &'a str,
+    /// Lookup record data following the record's name hash.
+    record: &'a [u8],
+}
+
+/// Holds the validated content of the resource lookup table blob section.
+///
+/// Contains an entry for every bucket in the table.
+type LookupTable<'a> = Vec<Option<LookupEntry<'a>>>;
+
 pub type PythonPackageResources<'a> = HashMap<&'a str, &'a [u8]>;
 
+#[derive(Clone)]
 pub struct ResourceParserIterator<'a> {
     done: bool,
     data: &'a [u8],
@@ -48,6 +86,23 @@ pub struct ResourceParserIterator<'a> {
     blob_sections: [Option<BlobSectionReadState>; 256],
     claimed_resources_count: usize,
     read_resources_count: usize,
+    resources_index_offset: usize,
+    resources_index_end_offset: usize,
+    lookup_table: Option<LookupTable<'a>>,
+    blob_sections_integrity: Vec<BlobSectionIntegrity<'a>>,
+    /// Whether index fields record the length of their value.
+    field_lengths: bool,
+}
+
+impl<'a> std::fmt::Debug for ResourceParserIterator<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResourceParserIterator")
+            .field("done", &self.done)
+            .field("claimed_resources_count", &self.claimed_resources_count)
+            .field("read_resources_count", &self.read_resources_count)
+            .field("lookup_table", &self.lookup_table.is_some())
+            .finish()
+    }
 }
 
 impl<'a> ResourceParserIterator<'a> {
@@ -56,6 +111,239 @@ impl<'a> ResourceParserIterator<'a> {
         self.claimed_resources_count
     }
 
+    /// Whether the data has a resource lookup table.
+    ///
+    /// If true, `find_resource()` can resolve resources by name without
+    /// parsing the entire resources index.
+    pub fn has_lookup_table(&self) -> bool {
+        self.lookup_table.is_some()
+    }
+
+    /// Verify the integrity of blob sections using their recorded digests.
+    ///
+    /// Blob sections without a recorded digest are ignored unless `require`
+    /// is true, in which case their presence is an error.
+    ///
+    /// Only blob section data is covered, which includes the resource lookup
+    /// table. The header, blob index, and resources index are not covered,
+    /// so this doesn't detect tampering with them. Use `verify_signature()`
+    /// to verify all data.
+    #[cfg(feature = "digests")]
+    pub fn verify_digests(&self, require: bool) -> Result<(), &'static str> {
+        for section in &self.blob_sections_integrity {
+            let expected = match section.sha256 {
+                Some(digest) => digest,
+                None if require => return Err("blob section does not have a digest"),
+                None => continue,
+            };
+
+            let data = section
+                .data
+                .ok_or("blob section extends beyond end of data")?;
+
+            if Sha256::digest(data).as_slice() != expected {
+                return Err("blob section digest mismatch");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The number of entries in the resource lookup table.
+    ///
+    /// Entries are identified by an index less than this value. Not every
+    /// entry refers to a resource. Returns 0 if the data doesn't have a
+    /// lookup table.
+    pub fn lookup_table_len(&self) -> usize {
+        self.lookup_table.as_ref().map_or(0, |table| table.len())
+    }
+
+    /// Find the lookup table entry referring to the named resource.
+    ///
+    /// Returns `None` if the resource doesn't exist or if the data doesn't
+    /// have a lookup table.
+    pub fn find_resource_entry(&self, name: &str) -> Option<usize> {
+        let table = self.lookup_table.as_ref()?;
+
+        let mut bucket = (resource_name_hash(name) % table.len() as u64) as usize;
+
+        for _ in 0..table.len() {
+            match &table[bucket] {
+                Some(entry) if entry.name == name => return Some(bucket),
+                Some(_) => {}
+                None => return None,
+            }
+
+            bucket = (bucket + 1) % table.len();
+        }
+
+        None
+    }
+
+    /// Resolve the resource referred to by a lookup table entry.
+    ///
+    /// Returns `Ok(None)` if the entry doesn't refer to a resource. Entries
+    /// are validated when the data is loaded, so resolving an entry only
+    /// fails if `entry` is out of bounds. Iteration state is not affected.
+    pub fn resolve_resource_entry(
+        &self,
+        entry: usize,
+    ) -> Result<Option<Resource<'a, u8>>, &'static str> {
+        let entry = self
+            .lookup_table
+            .as_ref()
+            .and_then(|table| table.get(entry))
+            .ok_or("lookup table entry out of bounds")?;
+
+        match entry {
+            Some(entry) => Ok(Some(self.parse_lookup_record(entry.record)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Resolve a resource by name using the resource lookup table.
+    ///
+    /// Returns `Ok(None)` if the resource doesn't exist or if the data
+    /// doesn't have a lookup table. Iteration state is not affected.
+    pub fn find_resource(&self, name: &str) -> Result<Option<Resource<'a, u8>>, &'static str> {
+        match self.find_resource_entry(name) {
+            Some(entry) => self.resolve_resource_entry(entry),
+            None => Ok(None),
+        }
+    }
+
+    /// Validate the content of the resource lookup table blob section.
+    ///
+    /// Every record is bounds checked and the resource entry it refers to is
+    /// parsed, so later lookups can't encounter malformed data.
+    fn read_lookup_table(&self, data: &'a [u8]) -> Result<LookupTable<'a>, &'static str> {
+        if data.len() < 4 {
+            return Err("lookup table too short");
+        }
+
+        let bucket_count = LittleEndian::read_u32(&data[0..4]) as usize;
+
+        if bucket_count == 0 || data.len() < 4 + bucket_count * 4 {
+            return Err("invalid lookup table bucket count");
+        }
+
+        let slots = &data[4..4 + bucket_count * 4];
+        let records = &data[4 + bucket_count * 4..];
+
+        let mut table = Vec::with_capacity(bucket_count);
+
+        for slot in slots.chunks_exact(4) {
+            let slot = LittleEndian::read_u32(slot) as usize;
+
+            if slot == 0 {
+                table.push(None);
+                continue;
+            }
+
+            if slot > records.len() {
+                return Err("lookup table slot out of bounds");
+            }
+
+            let mut record = Cursor::new(&records[slot - 1..]);
+
+            let hash = record
+                .read_u64::<LittleEndian>()
+                .map_err(|_| "failed reading lookup record name hash")?;
+
+            let record = &records[slot - 1 + 8..];
+
+            let name = match self.parse_lookup_record(record)?.name {
+                Cow::Borrowed(name) => name,
+                Cow::Owned(_) => return Err("lookup record resource name not borrowed"),
+            };
+
+            if resource_name_hash(name) != hash {
+                return Err("lookup record name hash mismatch");
+            }
+
+            table.push(Some(LookupEntry { name, record }));
+        }
+
+        // Lookups stop probing at the first empty bucket. So every entry must
+        // be reachable from its name's bucket without crossing one.
+        for (bucket, entry) in table.iter().enumerate() {
+            if let Some(entry) = entry {
+                let mut probe = (resource_name_hash(entry.name) % bucket_count as u64) as usize;
+
+                while probe != bucket {
+                    if table[probe].is_none() {
+                        return Err("lookup table entry not reachable");
+                    }
+
+                    probe = (probe + 1) % bucket_count;
+                }
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// Parse the resource referenced by a lookup table record.
+    ///
+    /// `record` holds the record data following its name hash.
+    fn parse_lookup_record(&self, record: &'a [u8]) -> Result<Resource<'a, u8>, &'static str> {
+        let mut record = Cursor::new(record);
+
+        let index_offset = record
+            .read_u32::<LittleEndian>()
+            .map_err(|_| "failed reading lookup record index offset")?
+            as usize;
+
+        if self.resources_index_offset + index_offset >= self.resources_index_end_offset {
+            return Err("lookup record index offset out of bounds");
+        }
+        let blob_offsets_count = record
+            .read_u8()
+            .map_err(|_| "failed reading lookup record blob offsets count")?;
+
+        let mut blob_sections = self.blob_sections;
+
+        for _ in 0..blob_offsets_count {
+            let field = record
+                .read_u8()
+                .map_err(|_| "failed reading lookup record resource field")?;
+            let offset = record
+                .read_u64::<LittleEndian>()
+                .map_err(|_| "failed reading lookup record blob offset")?
+                as usize;
+
+            let state = blob_sections[field as usize]
+                .as_mut()
+                .ok_or("lookup record references unknown blob section")?;
+
+            if offset > state.end_offset - state.start_offset {
+                return Err("lookup record blob offset out of bounds");
+            }
+
+            state.offset = state.start_offset + offset;
+        }
+
+        let mut reader = Cursor::new(self.data);
+        reader.set_position((self.resources_index_offset + index_offset) as u64);
+
+        let mut iter = ResourceParserIterator {
+            done: false,
+            data: self.data,
+            reader,
+            blob_sections,
+            claimed_resources_count: 1,
+            read_resources_count: 0,
+            resources_index_offset: self.resources_index_offset,
+            resources_index_end_offset: self.resources_index_end_offset,
+            lookup_table: None,
+            blob_sections_integrity: vec![],
+            field_lengths: self.field_lengths,
+        };
+
+        iter.parse_next()?
+            .ok_or("lookup record does not reference a resource entry")
+    }
+
     /// Resolve a slice to an individual blob's data.
     ///
     /// This accepts a reference to the original blobs payload, an array of
@@ -66,6 +354,15 @@ impl<'a> ResourceParserIterator<'a> {
             .as_mut()
             .expect("blob state not found");
 
+        if state.deduplicated {
+            let start = state.start_offset
+                + LittleEndian::read_u64(&self.data[state.offset..state.offset + 8]) as usize;
+
+            state.offset += 8;
+
+            return &self.data[start..start + length];
+        }
+
         let blob = &self.data[state.offset..state.offset + length];
 
         let increment = match &state.interior_padding {
@@ -78,6 +375,32 @@ impl<'a> ResourceParserIterator<'a> {
         blob
     }
 
+    /// Resolve an individual blob's payload data for a resource.
+    ///
+    /// Data is always borrowed from the original payload. Data from compressed
+    /// blob sections isn't decompressed. Instead, the field is recorded in
+    /// the resource's `compressed_fields` so the data can be decompressed
+    /// when it is accessed.
+    fn resolve_blob_payload(
+        &mut self,
+        resource: &mut Resource<'a, u8>,
+        resource_field: ResourceField,
+        length: usize,
+    ) -> Cow<'a, [u8]> {
+        let compression = self.blob_sections[resource_field as usize]
+            .as_ref()
+            .expect("blob state not found")
+            .compression;
+
+        if compression != BlobSectionCompression::None
+            && !resource.compressed_fields.contains(&resource_field)
+        {
+            resource.compressed_fields.push(resource_field);
+        }
+
+        Cow::Borrowed(self.resolve_blob_data(resource_field, length))
+    }
+
     #[cfg(unix)]
     fn resolve_path(&mut self, resource_field: ResourceField, length: usize) -> Cow<'a, Path> {
         let path_str = OsStr::from_bytes(self.resolve_blob_data(resource_field, length));
@@ -106,10 +429,21 @@ impl<'a> ResourceParserIterator<'a> {
                 .read_u8()
                 .map_err(|_| "failed reading field type")?;
 
-            let field_type = ResourceField::try_from(field_type)?;
+            let field_end = read_field_end(&mut self.reader, self.field_lengths)?;
+
+            let field_type = match (ResourceField::try_from(field_type), field_end) {
+                (Ok(field_type), _) => field_type,
+                // Fields we don't know about are skipped if we know their length.
+                (Err(_), Some(end)) => {
+                    self.reader.set_position(end);
+                    continue;
+                }
+                (Err(e), None) => return Err(e),
+            };
 
             match field_type {
                 ResourceField::EndOfIndex => {
+                    finish_field(&mut self.reader, field_end)?;
                     self.done = true;
 
                     if self.read_resources_count != self.claimed_resources_count {
@@ -125,6 +459,8 @@ impl<'a> ResourceParserIterator<'a> {
                 }
 
                 ResourceField::EndOfEntry => {
+                    finish_field(&mut self.reader, field_end)?;
+
                     let res = if let Some(name) = current_resource_name {
                         Ok(Some(current_resource))
                     } else {
@@ -173,7 +509,7 @@ impl<'a> ResourceParserIterator<'a> {
                         as usize;
 
                     current_resource.in_memory_source =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_payload(&mut current_resource, field_type, l));
                 }
                 ResourceField::InMemoryBytecode => {
                     let l = self
@@ -183,7 +519,7 @@ impl<'a> ResourceParserIterator<'a> {
                         as usize;
 
                     current_resource.in_memory_bytecode =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_payload(&mut current_resource, field_type, l));
                 }
                 ResourceField::InMemoryBytecodeOpt1 => {
                     let l = self
@@ -193,7 +529,7 @@ impl<'a> ResourceParserIterator<'a> {
                         as usize;
 
                     current_resource.in_memory_bytecode_opt1 =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_payload(&mut current_resource, field_type, l));
                 }
                 ResourceField::InMemoryBytecodeOpt2 => {
                     let l = self
@@ -203,7 +539,7 @@ impl<'a> ResourceParserIterator<'a> {
                         as usize;
 
                     current_resource.in_memory_bytecode_opt2 =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_payload(&mut current_resource, field_type, l));
                 }
                 ResourceField::InMemoryExtensionModuleSharedLibrary => {
                     let l = self
@@ -213,7 +549,7 @@ impl<'a> ResourceParserIterator<'a> {
                         as usize;
 
                     current_resource.in_memory_extension_module_shared_library =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_payload(&mut current_resource, field_type, l));
                 }
 
                 ResourceField::InMemoryResourcesData => {
@@ -244,10 +580,13 @@ impl<'a> ResourceParserIterator<'a> {
                             .map_err(|_| "failed reading resource length")?
                             as usize;
 
-                        let resource_data = self.resolve_blob_data(field_type, resource_length);
+                        let resource_data = self.resolve_blob_payload(
+                            &mut current_resource,
+                            field_type,
+                            resource_length,
+                        );
 
-                        resources
-                            .insert(Cow::Borrowed(resource_name), Cow::Borrowed(resource_data));
+                        resources.insert(Cow::Borrowed(resource_name), resource_data);
                     }
 
                     current_resource.in_memory_package_resources = Some(resources);
@@ -280,9 +619,13 @@ impl<'a> ResourceParserIterator<'a> {
                                 "failed reading package distribution resource length"
                             })? as usize;
 
-                        let resource_data = self.resolve_blob_data(field_type, resource_length);
+                        let resource_data = self.resolve_blob_payload(
+                            &mut current_resource,
+                            field_type,
+                            resource_length,
+                        );
 
-                        resources.insert(Cow::Borrowed(name), Cow::Borrowed(resource_data));
+                        resources.insert(Cow::Borrowed(name), resource_data);
                     }
 
                     current_resource.in_memory_distribution_resources = Some(resources);
@@ -296,7 +639,7 @@ impl<'a> ResourceParserIterator<'a> {
                         as usize;
 
                     current_resource.in_memory_shared_library =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_payload(&mut current_resource, field_type, l));
                 }
 
                 ResourceField::SharedLibraryDependencyNames => {
@@ -484,7 +827,7 @@ impl<'a> ResourceParserIterator<'a> {
                         as usize;
 
                     current_resource.file_data_embedded =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_payload(&mut current_resource, field_type, l));
                 }
 
                 ResourceField::FileDataUtf8RelativePath => {
@@ -498,9 +841,92 @@ impl<'a> ResourceParserIterator<'a> {
                         std::str::from_utf8_unchecked(self.resolve_blob_data(field_type, l))
                     }));
                 }
+
+                ResourceField::Annotations => {
+                    let annotations_count = self
+                        .reader
+                        .read_u32::<LittleEndian>()
+                        .map_err(|_| "failed reading annotations length")?
+                        as usize;
+
+                    let mut annotations = HashMap::with_capacity(annotations_count);
+
+                    for _ in 0..annotations_count {
+                        let name_length = self
+                            .reader
+                            .read_u16::<LittleEndian>()
+                            .map_err(|_| "failed reading annotation name length")?
+                            as usize;
+
+                        let name = unsafe {
+                            std::str::from_utf8_unchecked(
+                                self.resolve_blob_data(field_type, name_length),
+                            )
+                        };
+
+                        let value_length = self
+                            .reader
+                            .read_u32::<LittleEndian>()
+                            .map_err(|_| "failed reading annotation value length")?
+                            as usize;
+
+                        let value = self.resolve_blob_data(field_type, value_length);
+
+                        annotations.insert(Cow::Borrowed(name), Cow::Borrowed(value));
+                    }
+
+                    current_resource.annotations = Some(annotations);
+                }
+
+                ResourceField::InMemoryCompressedSource => {
+                    let l = self
+                        .reader
+                        .read_u32::<LittleEndian>()
+                        .map_err(|_| "failed reading compressed source length")?
+                        as usize;
+
+                    current_resource.in_memory_compressed_source =
+                        Some(self.resolve_blob_payload(&mut current_resource, field_type, l));
+                }
             }
+
+            finish_field(&mut self.reader, field_end)?;
+        }
+    }
+}
+
+/// Read the length of the value of an index field, if the format records it.
+///
+/// Returns the offset at which the field's value ends.
+fn read_field_end(
+    reader: &mut Cursor<&[u8]>,
+    field_lengths: bool,
+) -> Result<Option<u64>, &'static str> {
+    if field_lengths {
+        let length = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|_| "failed reading index field length")?;
+
+        Ok(Some(reader.position() + length as u64))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Advance past the end of the value of an index field.
+///
+/// Values may be longer than what we read from them, as future versions may
+/// append data to existing fields. But they may not be shorter.
+fn finish_field(reader: &mut Cursor<&[u8]>, field_end: Option<u64>) -> Result<(), &'static str> {
+    if let Some(end) = field_end {
+        if reader.position() > end {
+            return Err("index field value exceeds its recorded length");
         }
+
+        reader.set_position(end);
     }
+
+    Ok(())
 }
 
 impl<'a> Iterator for ResourceParserIterator<'a> {
@@ -521,6 +947,42 @@ impl<'a> Iterator for ResourceParserIterator<'a> {
     }
 }
 
+/// Split signed packed resources data into the signed data and its signature.
+///
+/// Returns `None` if the data doesn't have a signature.
+pub fn split_signature(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let trailer_offset = data.len().checked_sub(SIGNATURE_TRAILER_ED25519.len())?;
+
+    if &data[trailer_offset..] != SIGNATURE_TRAILER_ED25519 {
+        return None;
+    }
+
+    let signature_offset = trailer_offset.checked_sub(64)?;
+
+    Some((
+        &data[0..signature_offset],
+        &data[signature_offset..trailer_offset],
+    ))
+}
+
+/// Verify the ed25519 signature of signed packed resources data.
+///
+/// `public_key` is the 32 byte ed25519 public key the data should be signed
+/// with. On success, returns the signed data without its signature.
+#[cfg(feature = "signatures")]
+pub fn verify_signature<'a>(data: &'a [u8], public_key: &[u8]) -> Result<&'a [u8], &'static str> {
+    let (signed, signature) = split_signature(data).ok_or("packed resources data is not signed")?;
+
+    let public_key = PublicKey::from_bytes(public_key).map_err(|_| "invalid ed25519 public key")?;
+    let signature = Signature::try_from(signature).map_err(|_| "invalid ed25519 signature")?;
+
+    public_key
+        .verify_strict(signed, &signature)
+        .map_err(|_| "packed resources signature verification failed")?;
+
+    Ok(signed)
+}
+
 pub fn load_resources<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &'static str> {
     if data.len() < HEADER_V3.len() {
         return Err("error reading 8 byte header");
@@ -529,13 +991,19 @@ pub fn load_resources<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>,
     let header = &data[0..8];
 
     if header == HEADER_V3 {
-        load_resources_v3(&data[8..])
+        load_resources_v3(&data[8..], false)
+    } else if header == HEADER_V4 {
+        // Version 4 only differs from version 3 by recording index field lengths.
+        load_resources_v3(&data[8..], true)
     } else {
         Err("unrecognized file format")
     }
 }
 
-fn load_resources_v3<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &'static str> {
+fn load_resources_v3<'a>(
+    data: &'a [u8],
+    field_lengths: bool,
+) -> Result<ResourceParserIterator<'a>, &'static str> {
     let mut reader = Cursor::new(data);
 
     let blob_section_count = reader
@@ -555,6 +1023,9 @@ fn load_resources_v3<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
     let mut current_blob_field = None;
     let mut current_blob_raw_payload_length = None;
     let mut current_blob_interior_padding = None;
+    let mut current_blob_compression = None;
+    let mut current_blob_sha256 = None;
+    let mut current_blob_deduplicated = false;
     let mut blob_entry_count = 0;
     let mut blob_sections = Vec::with_capacity(blob_section_count as usize);
 
@@ -564,15 +1035,31 @@ fn load_resources_v3<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
                 .read_u8()
                 .map_err(|_| "failed reading blob section field type")?;
 
-            let field_type = BlobSectionField::try_from(field_type)?;
+            let field_end = read_field_end(&mut reader, field_lengths)?;
+
+            let field_type = match (BlobSectionField::try_from(field_type), field_end) {
+                (Ok(field_type), _) => field_type,
+                // Fields we don't know about are skipped if we know their length.
+                (Err(_), Some(end)) => {
+                    reader.set_position(end);
+                    continue;
+                }
+                (Err(e), None) => return Err(e),
+            };
 
             match field_type {
-                BlobSectionField::EndOfIndex => break,
+                BlobSectionField::EndOfIndex => {
+                    finish_field(&mut reader, field_end)?;
+                    break;
+                }
                 BlobSectionField::StartOfEntry => {
                     blob_entry_count += 1;
                     current_blob_field = None;
                     current_blob_raw_payload_length = None;
                     current_blob_interior_padding = None;
+                    current_blob_compression = None;
+                    current_blob_sha256 = None;
+                    current_blob_deduplicated = false;
                 }
                 BlobSectionField::EndOfEntry => {
                     if current_blob_field.is_none() {
@@ -586,11 +1073,17 @@ fn load_resources_v3<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
                         resource_field: current_blob_field.unwrap(),
                         raw_payload_length: current_blob_raw_payload_length.unwrap(),
                         interior_padding: current_blob_interior_padding,
+                        compression: current_blob_compression,
+                        sha256: current_blob_sha256,
+                        deduplicated: current_blob_deduplicated,
                     });
 
                     current_blob_field = None;
                     current_blob_raw_payload_length = None;
                     current_blob_interior_padding = None;
+                    current_blob_compression = None;
+                    current_blob_sha256 = None;
+                    current_blob_deduplicated = false;
                 }
                 BlobSectionField::ResourceFieldType => {
                     let field = reader
@@ -615,7 +1108,28 @@ fn load_resources_v3<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
                         _ => return Err("invalid value for interior padding field"),
                     });
                 }
+                BlobSectionField::Compression => {
+                    let compression = reader
+                        .read_u8()
+                        .map_err(|_| "failed reading compression field value")?;
+
+                    current_blob_compression = Some(BlobSectionCompression::try_from(compression)?);
+                }
+                BlobSectionField::Sha256Digest => {
+                    let start = reader.position() as usize;
+                    let digest = data
+                        .get(start..start + 32)
+                        .ok_or("failed reading SHA-256 digest value")?;
+                    reader.set_position((start + 32) as u64);
+
+                    current_blob_sha256 = Some(digest);
+                }
+                BlobSectionField::Deduplicated => {
+                    current_blob_deduplicated = true;
+                }
             }
+
+            finish_field(&mut reader, field_end)?;
         }
     }
 
@@ -636,26 +1150,80 @@ fn load_resources_v3<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
     // Current offset from start of blobs data.
     let mut current_blob_offset = 0;
 
+    let mut lookup_table = None;
+    let mut blob_sections_integrity = Vec::with_capacity(blob_sections.len());
+
     for section in &blob_sections {
         let section_start_offset = blob_start_offset + current_blob_offset;
+        current_blob_offset += section.raw_payload_length;
+
+        blob_sections_integrity.push(BlobSectionIntegrity {
+            data: data.get(section_start_offset..current_blob_offset + blob_start_offset),
+            sha256: section.sha256,
+        });
+
+        if section.resource_field == LOOKUP_TABLE_BLOB_SECTION {
+            lookup_table = Some(
+                data.get(section_start_offset..section_start_offset + section.raw_payload_length)
+                    .ok_or("lookup table extends beyond end of data")?,
+            );
+
+            continue;
+        }
+
+        if let Some(compression) = section.compression {
+            // Sections of fields we don't know about are never read. So only
+            // validate sections of known fields in formats allowing unknown fields.
+            let supports_compression = match ResourceField::try_from(section.resource_field) {
+                Ok(field) => field.supports_compression(),
+                Err(_) if field_lengths => true,
+                Err(e) => return Err(e),
+            };
+
+            if compression != BlobSectionCompression::None && !supports_compression {
+                return Err("compression not supported for blob section");
+            }
+
+            if compression == BlobSectionCompression::Zstd
+                && !cfg!(feature = "compression")
+                && ResourceField::try_from(section.resource_field).is_ok()
+            {
+                return Err("zstd decompression support not enabled");
+            }
+        }
+
         blob_offsets[section.resource_field as usize] = Some(BlobSectionReadState {
+            start_offset: section_start_offset,
+            end_offset: section_start_offset + section.raw_payload_length,
             offset: section_start_offset,
             interior_padding: match section.interior_padding {
                 Some(padding) => padding,
                 None => BlobInteriorPadding::None,
             },
+            compression: section.compression.unwrap_or_default(),
+            deduplicated: section.deduplicated,
         });
-        current_blob_offset += section.raw_payload_length;
     }
 
-    Ok(ResourceParserIterator {
+    let mut iter = ResourceParserIterator {
         done: resources_index_length == 0 || resources_count == 0,
         data,
         reader,
         blob_sections: blob_offsets,
         claimed_resources_count: resources_count,
         read_resources_count: 0,
-    })
+        resources_index_offset: 1 + 4 + 4 + 4 + blob_index_length,
+        resources_index_end_offset: blob_start_offset,
+        lookup_table: None,
+        blob_sections_integrity,
+        field_lengths,
+    };
+
+    if let Some(table) = lookup_table {
+        iter.lookup_table = Some(iter.read_lookup_table(table)?);
+    }
+
+    Ok(iter)
 }
 
 #[cfg(test)]
@@ -663,10 +1231,28 @@ mod tests {
     use {
         super::*,
         crate::data::{BlobInteriorPadding, Resource},
-        crate::writer::write_packed_resources_v3,
+        crate::writer::{
+            write_packed_resources, write_packed_resources_v3, FormatVersion, WriterOptions,
+        },
         std::collections::BTreeMap,
     };
 
+    /// Compression formats supported by this build.
+    fn compressions() -> Vec<BlobSectionCompression> {
+        let mut compressions = vec![BlobSectionCompression::None];
+        if cfg!(feature = "compression") {
+            compressions.push(BlobSectionCompression::Zstd);
+        }
+
+        compressions
+    }
+
+    /// Decompress the data of a resource obtained from the parser.
+    fn decompress(mut resource: Resource<u8>) -> Resource<u8> {
+        resource.decompress().unwrap();
+        resource
+    }
+
     #[test]
     fn test_too_short_header() {
         let data = b"foo";
@@ -681,7 +1267,7 @@ mod tests {
         let res = load_resources(data);
         assert_eq!(res.err(), Some("unrecognized file format"));
 
-        let data = b"pyembed\x04";
+        let data = b"pyembed\x05";
         let res = load_resources(data);
         assert_eq!(res.err(), Some("unrecognized file format"));
     }
@@ -1074,6 +1660,73 @@ mod tests {
         assert_eq!(resources.get("another").unwrap().as_ref(), b"value2");
     }
 
+    #[test]
+    fn test_annotations() {
+        let mut annotations = HashMap::new();
+        annotations.insert(Cow::from("build_id"), Cow::from(b"1234".to_vec()));
+        annotations.insert(
+            Cow::from("wheel"),
+            Cow::from(b"foo-1.0-py3-none-any.whl".to_vec()),
+        );
+
+        let resource = Resource {
+            name: Cow::from("foo"),
+            annotations: Some(annotations),
+            ..Resource::default()
+        };
+
+        let mut data = Vec::new();
+        write_packed_resources_v3(&[resource], &mut data, None).unwrap();
+        let resources = load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+
+        assert_eq!(resources.len(), 1);
+
+        let entry = &resources[0];
+
+        let annotations = entry.annotations.as_ref().unwrap();
+        assert_eq!(annotations.len(), 2);
+        assert_eq!(annotations.get("build_id").unwrap().as_ref(), b"1234");
+        assert_eq!(
+            annotations.get("wheel").unwrap().as_ref(),
+            b"foo-1.0-py3-none-any.whl"
+        );
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_in_memory_compressed_source() {
+        let compressed = zstd::stream::encode_all(&b"import io"[..], 0).unwrap();
+
+        let resource = Resource {
+            name: Cow::from("foo"),
+            in_memory_compressed_source: Some(Cow::from(compressed.clone())),
+            ..Resource::default()
+        };
+
+        let mut data = Vec::new();
+        write_packed_resources_v3(&[resource], &mut data, None).unwrap();
+        let resources = load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+
+        assert_eq!(resources.len(), 1);
+
+        let entry = &resources[0];
+
+        // The parser doesn't decompress the data.
+        let source = entry.in_memory_compressed_source.as_ref().unwrap();
+        assert_eq!(source.as_ref(), compressed.as_slice());
+        assert!(matches!(source, Cow::Borrowed(_)));
+        assert_eq!(
+            entry.decompressed_source().unwrap(),
+            Some(b"import io".to_vec())
+        );
+    }
+
     #[test]
     fn test_in_memory_shared_library() {
         let resource = Resource {
@@ -1383,6 +2036,9 @@ mod tests {
             Cow::from(Path::new("package/resource.txt")),
         );
 
+        let mut annotations = HashMap::new();
+        annotations.insert(Cow::from("build_id"), Cow::from(b"1234".to_vec()));
+
         let resource = Resource {
             flavor: ResourceFlavor::Module,
             name: Cow::from("module"),
@@ -1413,6 +2069,9 @@ mod tests {
             file_executable: true,
             file_data_embedded: Some(Cow::from(b"file_data_embedded".to_vec())),
             file_data_utf8_relative_path: Some(Cow::from("file_data_utf8_relative_path")),
+            annotations: Some(annotations),
+            in_memory_compressed_source: Some(Cow::from(b"compressed_source".to_vec())),
+            compressed_fields: vec![],
         };
 
         let mut data = Vec::new();
@@ -1528,6 +2187,15 @@ mod tests {
             entry.file_data_utf8_relative_path.as_ref().unwrap(),
             "file_data_utf8_relative_path"
         );
+
+        let annotations = entry.annotations.as_ref().unwrap();
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations.get("build_id").unwrap().as_ref(), b"1234");
+
+        assert_eq!(
+            entry.in_memory_compressed_source.as_ref().unwrap().as_ref(),
+            b"compressed_source"
+        );
     }
 
     #[test]
@@ -1556,4 +2224,552 @@ mod tests {
 
         assert_eq!(resources, loaded);
     }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compressed_fields() {
+        let mut in_memory_resources = HashMap::new();
+        in_memory_resources.insert(Cow::from("foo"), Cow::from(b"foovalue".to_vec()));
+        in_memory_resources.insert(Cow::from("resource2"), Cow::from(b"value2".to_vec()));
+
+        let resources: Vec<Resource<u8>> = vec![
+            Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from("foo"),
+                in_memory_source: Some(Cow::from(b"import io".to_vec())),
+                in_memory_bytecode: Some(Cow::from(b"fake bytecode".to_vec())),
+                in_memory_package_resources: Some(in_memory_resources),
+                ..Resource::default()
+            },
+            Resource {
+                flavor: ResourceFlavor::None,
+                name: Cow::from("bar.txt"),
+                file_data_embedded: Some(Cow::from(vec![0x42; 8192])),
+                ..Resource::default()
+            },
+            Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from("baz"),
+                in_memory_source: Some(Cow::from(b"".to_vec())),
+                ..Resource::default()
+            },
+        ];
+
+        for padding in &[BlobInteriorPadding::None, BlobInteriorPadding::Null] {
+            let mut options = WriterOptions {
+                interior_padding: Some(*padding),
+                ..WriterOptions::default()
+            };
+            options.compress_all(BlobSectionCompression::Zstd);
+
+            let mut data = Vec::new();
+            write_packed_resources(&resources, &mut data, &options).unwrap();
+            assert!(data.len() < 8192);
+
+            let loaded = load_resources(&data)
+                .unwrap()
+                .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+                .unwrap();
+
+            // Data is only decompressed when it is accessed.
+            assert!(matches!(loaded[0].name, Cow::Borrowed(_)));
+            let source = loaded[0].in_memory_source.as_ref().unwrap();
+            assert!(matches!(source, Cow::Borrowed(_)));
+            assert_ne!(source.as_ref(), b"import io");
+            assert_eq!(
+                loaded[0]
+                    .field_data(ResourceField::InMemorySource, source)
+                    .unwrap()
+                    .as_ref(),
+                b"import io"
+            );
+            assert_eq!(
+                loaded[0].compressed_fields,
+                vec![
+                    ResourceField::InMemorySource,
+                    ResourceField::InMemoryBytecode,
+                    ResourceField::InMemoryResourcesData
+                ]
+            );
+            assert_eq!(
+                loaded[1].compressed_fields,
+                vec![ResourceField::FileDataEmbedded]
+            );
+
+            // Writers store the decompressed data of parsed resources.
+            let mut rewritten = Vec::new();
+            write_packed_resources(&loaded, &mut rewritten, &WriterOptions::default()).unwrap();
+            let reloaded = load_resources(&rewritten)
+                .unwrap()
+                .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+                .unwrap();
+            assert_eq!(resources, reloaded);
+
+            let loaded = loaded.into_iter().map(decompress).collect::<Vec<_>>();
+            assert_eq!(resources, loaded);
+            assert!(loaded[0].compressed_fields.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_v4_unknown_fields() {
+        let resource = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::from("foo"),
+            in_memory_source: Some(Cow::from(b"import io".to_vec())),
+            ..Resource::default()
+        };
+
+        let mut data = Vec::new();
+        write_packed_resources(
+            std::slice::from_ref(&resource),
+            &mut data,
+            &WriterOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(&data[0..8], b"pyembed\x04");
+
+        let blob_index_length = u32::from_le_bytes([data[9], data[10], data[11], data[12]]);
+        let resources_index_length = u32::from_le_bytes([data[17], data[18], data[19], data[20]]);
+
+        // Insert unknown fields after the start of entry fields of the first
+        // resources index entry and the first blob index entry.
+        let position = 8 + 13 + blob_index_length as usize + 5;
+        assert_eq!(data[position - 5], ResourceField::StartOfEntry.into());
+        data.splice(position..position, vec![0xf0, 3, 0, 0, 0, 1, 2, 3]);
+        data[17..21].copy_from_slice(&(resources_index_length + 8).to_le_bytes());
+
+        let position = 8 + 13 + 5;
+        assert_eq!(data[position - 5], BlobSectionField::StartOfEntry.into());
+        data.splice(position..position, vec![0xf0, 2, 0, 0, 0, 9, 9]);
+        data[9..13].copy_from_slice(&(blob_index_length + 7).to_le_bytes());
+
+        let resources = load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+        assert_eq!(resources, vec![resource]);
+
+        // Version 3 doesn't record field lengths, so unknown fields are fatal.
+        data[7] = 0x03;
+        assert!(load_resources(&data).is_err());
+    }
+
+    #[test]
+    fn test_compressed_bad_data() {
+        let resource = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::from("foo"),
+            in_memory_source: Some(Cow::from(b"import io".to_vec())),
+            ..Resource::default()
+        };
+
+        let mut data = Vec::new();
+        let options = WriterOptions {
+            format_version: FormatVersion::V3,
+            ..WriterOptions::default()
+        };
+        write_packed_resources(&[resource], &mut data, &options).unwrap();
+
+        // Mark the in-memory source blob section as compressed. It is the
+        // last entry in the blob index and its raw data isn't a valid zstd frame.
+        let index_length = u32::from_le_bytes([data[9], data[10], data[11], data[12]]);
+        let position = 8 + 1 + 4 + 4 + 4 + index_length as usize - 2;
+        assert_eq!(data[position], BlobSectionField::EndOfEntry.into());
+
+        let compression_value: u8 = (&BlobSectionCompression::Zstd).into();
+        data.splice(
+            position..position,
+            vec![BlobSectionField::Compression.into(), compression_value],
+        );
+        data[9..13].copy_from_slice(&(index_length + 2).to_le_bytes());
+
+        // Data is only decompressed when it is accessed.
+        #[cfg(feature = "compression")]
+        {
+            let mut resources = load_resources(&data)
+                .unwrap()
+                .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+                .unwrap();
+            assert_eq!(
+                resources[0].decompress(),
+                Err("error decompressing blob data")
+            );
+        }
+
+        // Compressed data can't be accessed without zstd support.
+        #[cfg(not(feature = "compression"))]
+        assert_eq!(
+            load_resources(&data).err(),
+            Some("zstd decompression support not enabled")
+        );
+    }
+
+    #[test]
+    fn test_lookup_table() {
+        let mut resources: Vec<Resource<u8>> = (0..100)
+            .map(|i| Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from(format!("module{}", i)),
+                is_package: i % 3 == 0,
+                in_memory_source: if i % 2 == 0 {
+                    Some(Cow::from(format!("# source {}", i).into_bytes()))
+                } else {
+                    None
+                },
+                in_memory_bytecode: Some(Cow::from(format!("bytecode {}", i).into_bytes())),
+                relative_path_module_source: if i % 5 == 0 {
+                    Some(Cow::from(Path::new("foo.py")))
+                } else {
+                    None
+                },
+                ..Resource::default()
+            })
+            .collect();
+
+        // A later resource having the same name wins.
+        resources.push(Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::from("module42"),
+            in_memory_source: Some(Cow::from(b"# replaced".to_vec())),
+            ..Resource::default()
+        });
+
+        for padding in &[BlobInteriorPadding::None, BlobInteriorPadding::Null] {
+            for compression in compressions() {
+                let mut options = WriterOptions {
+                    interior_padding: Some(*padding),
+                    lookup_table: true,
+                    ..WriterOptions::default()
+                };
+                options.compress_all(compression);
+
+                let mut data = Vec::new();
+                write_packed_resources(&resources, &mut data, &options).unwrap();
+
+                let parser = load_resources(&data).unwrap();
+                assert!(parser.has_lookup_table());
+
+                for resource in &resources[0..100] {
+                    if resource.name == "module42" {
+                        continue;
+                    }
+
+                    assert_eq!(
+                        parser
+                            .find_resource(&resource.name)
+                            .unwrap()
+                            .map(decompress)
+                            .as_ref(),
+                        Some(resource)
+                    );
+                }
+
+                assert_eq!(
+                    parser
+                        .find_resource("module42")
+                        .unwrap()
+                        .map(decompress)
+                        .as_ref(),
+                    Some(&resources[100])
+                );
+                assert_eq!(parser.find_resource("missing").unwrap(), None);
+
+                // Every resource has exactly one entry.
+                let entries = (0..parser.lookup_table_len())
+                    .filter_map(|entry| parser.resolve_resource_entry(entry).unwrap())
+                    .count();
+                assert_eq!(entries, 100);
+                assert!(parser
+                    .resolve_resource_entry(parser.lookup_table_len())
+                    .is_err());
+
+                // Linear parsing is unaffected by the lookup table.
+                let loaded = parser
+                    .map(|resource| resource.map(decompress))
+                    .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+                    .unwrap();
+                assert_eq!(resources, loaded);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lookup_table_malformed() {
+        let resource = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::from("foo"),
+            in_memory_source: Some(Cow::from(b"source".to_vec())),
+            ..Resource::default()
+        };
+
+        let options = WriterOptions {
+            lookup_table: true,
+            ..WriterOptions::default()
+        };
+
+        let mut data = Vec::new();
+        write_packed_resources(&[resource], &mut data, &options).unwrap();
+        assert!(load_resources(&data).is_ok());
+
+        // The lookup table is the last blob section. It has 2 buckets and a
+        // single record.
+        let table_offset = data
+            .windows(12)
+            .rposition(|w| {
+                w == [2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]
+                    || w == [2, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0]
+            })
+            .unwrap();
+        let slot_offset = if data[table_offset + 4] == 1 {
+            table_offset + 4
+        } else {
+            table_offset + 8
+        };
+        let record_offset = table_offset + 12;
+
+        let mut bad = data.clone();
+        bad[slot_offset] = 0xff;
+        assert_eq!(
+            load_resources(&bad).unwrap_err(),
+            "lookup table slot out of bounds"
+        );
+
+        let mut bad = data.clone();
+        bad[record_offset] ^= 0xff;
+        assert_eq!(
+            load_resources(&bad).unwrap_err(),
+            "lookup record name hash mismatch"
+        );
+
+        let mut bad = data.clone();
+        bad[record_offset + 8..record_offset + 12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            load_resources(&bad).unwrap_err(),
+            "lookup record index offset out of bounds"
+        );
+
+        let mut bad = data;
+        bad[record_offset + 12] = 0xff;
+        assert!(load_resources(&bad).is_err());
+    }
+
+    #[test]
+    fn test_no_lookup_table() {
+        let resource = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::from("foo"),
+            ..Resource::default()
+        };
+
+        let mut data = Vec::new();
+        write_packed_resources_v3(&[resource], &mut data, None).unwrap();
+
+        let parser = load_resources(&data).unwrap();
+        assert!(!parser.has_lookup_table());
+        assert_eq!(parser.find_resource("foo").unwrap(), None);
+    }
+
+    #[test]
+    fn test_deduplicated_blobs() {
+        let license = vec![0x42; 4096];
+
+        let resources: Vec<Resource<u8>> = (0..10)
+            .map(|i| {
+                let mut distribution_resources = HashMap::new();
+                distribution_resources.insert(Cow::from("LICENSE"), Cow::from(license.clone()));
+                distribution_resources.insert(
+                    Cow::from("METADATA"),
+                    Cow::from(format!("Name: package{}", i).into_bytes()),
+                );
+
+                Resource {
+                    flavor: ResourceFlavor::Module,
+                    name: Cow::from(format!("package{}", i)),
+                    is_package: true,
+                    in_memory_source: Some(Cow::from(b"# package".to_vec())),
+                    in_memory_distribution_resources: Some(distribution_resources),
+                    ..Resource::default()
+                }
+            })
+            .collect();
+
+        for padding in &[BlobInteriorPadding::None, BlobInteriorPadding::Null] {
+            for compression in compressions() {
+                let mut options = WriterOptions {
+                    interior_padding: Some(*padding),
+                    lookup_table: true,
+                    section_digests: cfg!(feature = "digests"),
+                    ..WriterOptions::default()
+                };
+                options.compress_all(compression);
+
+                let mut plain = Vec::new();
+                write_packed_resources(&resources, &mut plain, &options).unwrap();
+
+                options.deduplicate_blobs = true;
+                let mut data = Vec::new();
+                write_packed_resources(&resources, &mut data, &options).unwrap();
+                assert!(data.len() < plain.len());
+
+                let parser = load_resources(&data).unwrap();
+                #[cfg(feature = "digests")]
+                parser.verify_digests(true).unwrap();
+
+                for resource in &resources {
+                    assert_eq!(
+                        parser
+                            .find_resource(&resource.name)
+                            .unwrap()
+                            .map(decompress)
+                            .as_ref(),
+                        Some(resource)
+                    );
+                }
+
+                let loaded = parser
+                    .map(|resource| resource.map(decompress))
+                    .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+                    .unwrap();
+                assert_eq!(resources, loaded);
+            }
+        }
+
+        // Data is only stored once.
+        let mut data = Vec::new();
+        write_packed_resources(
+            &resources,
+            &mut data,
+            &WriterOptions {
+                deduplicate_blobs: true,
+                ..WriterOptions::default()
+            },
+        )
+        .unwrap();
+        assert!(data.len() < license.len() * 2);
+
+        let loaded = load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+        assert_eq!(resources, loaded);
+    }
+
+    #[cfg(feature = "digests")]
+    #[test]
+    fn test_section_digests() {
+        let resources: Vec<Resource<u8>> = vec![
+            Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from("foo"),
+                in_memory_source: Some(Cow::from(b"import io".to_vec())),
+                ..Resource::default()
+            },
+            Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from("bar"),
+                in_memory_bytecode: Some(Cow::from(b"fake bytecode".to_vec())),
+                ..Resource::default()
+            },
+        ];
+
+        let mut data = Vec::new();
+        write_packed_resources_v3(&resources, &mut data, None).unwrap();
+        let parser = load_resources(&data).unwrap();
+        assert_eq!(parser.verify_digests(false), Ok(()));
+        assert_eq!(
+            parser.verify_digests(true),
+            Err("blob section does not have a digest")
+        );
+
+        let options = WriterOptions {
+            lookup_table: true,
+            section_digests: true,
+            ..WriterOptions::default()
+        };
+
+        let mut data = Vec::new();
+        write_packed_resources(&resources, &mut data, &options).unwrap();
+        let parser = load_resources(&data).unwrap();
+        assert_eq!(parser.verify_digests(true), Ok(()));
+        let loaded = parser
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+        assert_eq!(resources, loaded);
+
+        // Corrupt the bytecode data.
+        let position = data
+            .windows(b"fake bytecode".len())
+            .position(|x| x == b"fake bytecode")
+            .unwrap();
+        data[position] = b'F';
+
+        let parser = load_resources(&data).unwrap();
+        assert_eq!(
+            parser.verify_digests(false),
+            Err("blob section digest mismatch")
+        );
+    }
+
+    #[cfg(feature = "signatures")]
+    #[test]
+    fn test_signature() {
+        use {
+            crate::writer::append_signature,
+            ed25519_dalek::{Keypair, SecretKey},
+        };
+
+        let keypair = |seed: u8| {
+            let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+            let public = (&secret).into();
+
+            Keypair { secret, public }
+        };
+
+        let resource = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::from("foo"),
+            in_memory_source: Some(Cow::from(b"import io".to_vec())),
+            ..Resource::default()
+        };
+
+        let mut unsigned = Vec::new();
+        write_packed_resources_v3(&[resource.clone()], &mut unsigned, None).unwrap();
+
+        assert_eq!(split_signature(&unsigned), None);
+        assert_eq!(
+            verify_signature(&unsigned, keypair(1).public.as_bytes()),
+            Err("packed resources data is not signed")
+        );
+
+        let mut data = unsigned.clone();
+        append_signature(&mut data, &keypair(1));
+
+        assert_eq!(
+            verify_signature(&data, keypair(1).public.as_bytes()),
+            Ok(unsigned.as_slice())
+        );
+        assert_eq!(
+            verify_signature(&data, keypair(2).public.as_bytes()),
+            Err("packed resources signature verification failed")
+        );
+
+        // Signed data can be parsed by parsers unaware of signatures.
+        let loaded = load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+        assert_eq!(loaded, vec![resource]);
+
+        // Tampering with the data is detected.
+        let position = data
+            .windows(b"import io".len())
+            .position(|x| x == b"import io")
+            .unwrap();
+        data[position] = b'I';
+        assert_eq!(
+            verify_signature(&data, keypair(1).public.as_bytes()),
+            Err("packed resources signature verification failed")
+        );
+    }
 }
@@ -6,17 +6,36 @@
 
 use {
     super::data::{
-        BlobInteriorPadding, BlobSectionField, Resource, ResourceField, ResourceFlavor, HEADER_V3,
+        resource_name_hash, BlobInteriorPadding, BlobSectionCompression, BlobSectionField,
+        Resource, ResourceField, ResourceFlavor, HEADER_V3, HEADER_V4, LOOKUP_TABLE_BLOB_SECTION,
     },
     anyhow::{anyhow, Context, Result},
     byteorder::{LittleEndian, WriteBytesExt},
-    std::{collections::BTreeMap, convert::TryFrom, io::Write, path::Path},
+    std::{
+        borrow::Cow,
+        collections::{
+            hash_map::{DefaultHasher, Entry},
+            BTreeMap, BTreeSet, HashMap,
+        },
+        convert::TryFrom,
+        fs::File,
+        hash::Hasher,
+        io::{BufReader, Read, Seek, SeekFrom, Write},
+        path::{Path, PathBuf},
+    },
 };
 
+#[cfg(feature = "digests")]
+use sha2::{Digest, Sha256};
 #[cfg(unix)]
 use std::os::unix::ffi::OsStrExt;
 #[cfg(windows)]
 use std::os::windows::ffi::OsStrExt;
+#[cfg(feature = "signatures")]
+use {
+    super::data::SIGNATURE_TRAILER_ED25519,
+    ed25519_dalek::{Keypair, Signer},
+};
 
 #[cfg(unix)]
 fn path_bytes_length(p: &Path) -> usize {
@@ -46,59 +65,387 @@ fn path_to_bytes(p: &Path) -> Vec<u8> {
     }
 }
 
-#[derive(Debug)]
-struct BlobSection {
-    resource_field: ResourceField,
-    raw_payload_length: usize,
-    interior_padding: Option<BlobInteriorPadding>,
+/// Version of the packed resources data format to write.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FormatVersion {
+    /// Version 3.
+    ///
+    /// Parsers reject index fields they don't know about.
+    V3,
+
+    /// Version 4.
+    ///
+    /// Every index field records the length of its value, allowing parsers
+    /// to skip fields introduced after them.
+    V4,
 }
 
-impl BlobSection {
-    /// Compute length of index entry for version 1 payload format.
-    pub fn index_v1_length(&self) -> usize {
-        // Start of index entry.
-        let mut index = 1;
+impl Default for FormatVersion {
+    fn default() -> Self {
+        FormatVersion::V4
+    }
+}
 
-        // Resource type field + its value.
-        index += 2;
+/// Options influencing how packed resources data is written.
+#[derive(Clone, Debug, Default)]
+pub struct WriterOptions {
+    /// Version of the data format to write.
+    ///
+    /// Compression, lookup tables, digests, and deduplication require
+    /// version 4.
+    pub format_version: FormatVersion,
 
-        // Raw payload length field + its value.
-        index += 9;
+    /// Interior padding to use between entries in blob sections.
+    pub interior_padding: Option<BlobInteriorPadding>,
 
-        if self.interior_padding.is_some() {
-            // Field + value.
-            index += 2;
+    /// Compression to apply to blob sections, keyed by resource field.
+    ///
+    /// Blob sections for fields not present are stored uncompressed. Only
+    /// fields for which `ResourceField::supports_compression()` returns true
+    /// may be compressed.
+    pub compression: BTreeMap<ResourceField, BlobSectionCompression>,
+
+    /// Compression level to use for compressed blob sections.
+    ///
+    /// `0` selects the default level of the compression format.
+    pub compression_level: i32,
+
+    /// Whether to write a resource lookup table.
+    ///
+    /// The lookup table allows readers to resolve individual resources by
+    /// name without parsing the entire resources index.
+    pub lookup_table: bool,
+
+    /// Whether to record SHA-256 digests of blob sections.
+    ///
+    /// Digests allow readers to detect corrupted blob section data. The
+    /// header and indices aren't covered. Use `append_signature()` to allow
+    /// readers to detect tampering.
+    pub section_digests: bool,
+
+    /// Whether to store identical blob data only once.
+    ///
+    /// Blob sections containing identical entries are written in a layout
+    /// where each entry references data by offset, allowing data shared by
+    /// multiple resources to be stored once. The layout is only used for
+    /// sections it makes smaller.
+    ///
+    /// Not supported by `StreamingWriter`.
+    pub deduplicate_blobs: bool,
+}
+
+impl WriterOptions {
+    /// Compress all blob sections that support compression with the given format.
+    pub fn compress_all(&mut self, compression: BlobSectionCompression) {
+        for field in BLOB_FIELDS
+            .iter()
+            .filter(|field| field.supports_compression())
+        {
+            self.compression.insert(*field, compression);
         }
+    }
+}
 
-        // End of index entry.
-        index += 1;
+/// Compress a single blob entry.
+#[cfg(feature = "compression")]
+fn compress_blob(data: &[u8], compression: BlobSectionCompression, level: i32) -> Result<Vec<u8>> {
+    match compression {
+        BlobSectionCompression::None => Ok(data.to_vec()),
+        BlobSectionCompression::Zstd => {
+            zstd::stream::encode_all(data, level).context("zstd compressing blob data")
+        }
+    }
+}
 
-        index
+#[cfg(not(feature = "compression"))]
+fn compress_blob(data: &[u8], compression: BlobSectionCompression, _level: i32) -> Result<Vec<u8>> {
+    match compression {
+        BlobSectionCompression::None => Ok(data.to_vec()),
+        BlobSectionCompression::Zstd => Err(anyhow!("zstd compression support not enabled")),
     }
+}
 
-    pub fn write_index_v1<W: Write>(&self, dest: &mut W) -> Result<()> {
-        dest.write_u8(BlobSectionField::StartOfEntry.into())
+/// Obtain a copy of a resource with blob data compressed according to options.
+///
+/// The returned resource holds compressed bytes in place of the original data.
+/// Index lengths derived from it therefore describe the compressed payloads.
+fn compress_resource<'a>(
+    resource: &Resource<'a, u8>,
+    options: &WriterOptions,
+) -> Result<Resource<'a, u8>> {
+    let level = options.compression_level;
+
+    let compress =
+        |field: ResourceField, data: &Option<Cow<'a, [u8]>>| -> Result<Option<Cow<'a, [u8]>>> {
+            match (data, options.compression.get(&field)) {
+                (Some(data), Some(compression)) => {
+                    Ok(Some(Cow::Owned(compress_blob(data, *compression, level)?)))
+                }
+                (data, _) => Ok(data.clone()),
+            }
+        };
+
+    type DataMap<'a> = HashMap<Cow<'a, str>, Cow<'a, [u8]>>;
+
+    let compress_map =
+        |field: ResourceField, data: &Option<DataMap<'a>>| -> Result<Option<DataMap<'a>>> {
+            match (data, options.compression.get(&field)) {
+                (Some(data), Some(compression)) => Ok(Some(
+                    data.iter()
+                        .map(|(key, value)| {
+                            Ok((
+                                key.clone(),
+                                Cow::Owned(compress_blob(value, *compression, level)?),
+                            ))
+                        })
+                        .collect::<Result<HashMap<_, _>>>()?,
+                )),
+                (data, _) => Ok(data.clone()),
+            }
+        };
+
+    Ok(Resource {
+        in_memory_source: compress(ResourceField::InMemorySource, &resource.in_memory_source)?,
+        in_memory_bytecode: compress(
+            ResourceField::InMemoryBytecode,
+            &resource.in_memory_bytecode,
+        )?,
+        in_memory_bytecode_opt1: compress(
+            ResourceField::InMemoryBytecodeOpt1,
+            &resource.in_memory_bytecode_opt1,
+        )?,
+        in_memory_bytecode_opt2: compress(
+            ResourceField::InMemoryBytecodeOpt2,
+            &resource.in_memory_bytecode_opt2,
+        )?,
+        in_memory_extension_module_shared_library: compress(
+            ResourceField::InMemoryExtensionModuleSharedLibrary,
+            &resource.in_memory_extension_module_shared_library,
+        )?,
+        in_memory_package_resources: compress_map(
+            ResourceField::InMemoryResourcesData,
+            &resource.in_memory_package_resources,
+        )?,
+        in_memory_distribution_resources: compress_map(
+            ResourceField::InMemoryDistributionResource,
+            &resource.in_memory_distribution_resources,
+        )?,
+        in_memory_shared_library: compress(
+            ResourceField::InMemorySharedLibrary,
+            &resource.in_memory_shared_library,
+        )?,
+        file_data_embedded: compress(
+            ResourceField::FileDataEmbedded,
+            &resource.file_data_embedded,
+        )?,
+        ..resource.clone()
+    })
+}
+
+/// Obtain a resource whose fields hold decompressed data.
+///
+/// Resources obtained from the parser may hold compressed data. Writers need
+/// the actual data, as compression is applied according to writer options.
+fn decompressed_resource<'r, 'a>(
+    resource: &'r Resource<'a, u8>,
+) -> Result<Cow<'r, Resource<'a, u8>>> {
+    if resource.compressed_fields.is_empty() {
+        Ok(Cow::Borrowed(resource))
+    } else {
+        let mut resource = resource.clone();
+        resource
+            .decompress()
+            .map_err(|e| anyhow!("error decompressing {}: {}", resource.name, e))?;
+
+        Ok(Cow::Owned(resource))
+    }
+}
+
+/// A `Write` feeding all written data into a SHA-256 hasher.
+#[cfg(feature = "digests")]
+struct DigestWriter(Sha256);
+
+/// Digests are never computed without the `digests` feature.
+#[cfg(not(feature = "digests"))]
+enum DigestWriter {}
+
+impl DigestWriter {
+    /// Obtain a hasher for the data of a blob section, if options call for digests.
+    #[cfg(feature = "digests")]
+    fn for_section(options: &WriterOptions) -> Option<Self> {
+        if options.section_digests {
+            Some(Self(Sha256::new()))
+        } else {
+            None
+        }
+    }
+
+    #[cfg(not(feature = "digests"))]
+    fn for_section(_options: &WriterOptions) -> Option<Self> {
+        None
+    }
+
+    /// Obtain the digest of all written data.
+    #[cfg(feature = "digests")]
+    fn finalize(self) -> [u8; 32] {
+        self.0.finalize().into()
+    }
+
+    #[cfg(not(feature = "digests"))]
+    fn finalize(self) -> [u8; 32] {
+        match self {}
+    }
+}
+
+impl Write for DigestWriter {
+    #[cfg(feature = "digests")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    #[cfg(not(feature = "digests"))]
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        match *self {}
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes the fields of index entries using the encoding of a format version.
+///
+/// Version 4 records the length of each field's value after its field type.
+/// So field values are buffered until the next field starts or `finish_field()`
+/// is called.
+struct IndexFieldWriter<'w, W: Write> {
+    dest: &'w mut W,
+    version: FormatVersion,
+    pending: Option<(u8, Vec<u8>)>,
+}
+
+impl<'w, W: Write> IndexFieldWriter<'w, W> {
+    fn new(dest: &'w mut W, version: FormatVersion) -> Self {
+        Self {
+            dest,
+            version,
+            pending: None,
+        }
+    }
+
+    /// Start a new field. Data written afterwards is the value of this field.
+    fn start_field(&mut self, field: impl Into<u8>) -> std::io::Result<()> {
+        self.finish_field()?;
+
+        match self.version {
+            FormatVersion::V3 => self.dest.write_u8(field.into()),
+            FormatVersion::V4 => {
+                self.pending = Some((field.into(), vec![]));
+                Ok(())
+            }
+        }
+    }
+
+    /// Finish writing the current field, if any.
+    fn finish_field(&mut self) -> std::io::Result<()> {
+        if let Some((field, value)) = self.pending.take() {
+            let length = u32::try_from(value.len()).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, "index field too long")
+            })?;
+
+            self.dest.write_u8(field)?;
+            self.dest.write_u32::<LittleEndian>(length)?;
+            self.dest.write_all(&value)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'w, W: Write> Write for IndexFieldWriter<'w, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut self.pending {
+            Some((_, value)) => value.write(buf),
+            None => self.dest.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.dest.flush()
+    }
+}
+
+/// Write the end of index field terminating the blob or resources index.
+fn write_end_of_index<W: Write>(dest: &mut W, version: FormatVersion) -> Result<()> {
+    let mut writer = IndexFieldWriter::new(dest, version);
+    writer
+        .start_field(ResourceField::EndOfIndex)
+        .context("writing end of index field")?;
+    writer
+        .finish_field()
+        .context("writing end of index field")?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct BlobSection {
+    resource_field: u8,
+    raw_payload_length: usize,
+    interior_padding: Option<BlobInteriorPadding>,
+    compression: Option<BlobSectionCompression>,
+    digest: Option<[u8; 32]>,
+    deduplicated: bool,
+}
+
+impl BlobSection {
+    /// Write the blob index entry for this section.
+    pub fn write_index<W: Write>(&self, dest: &mut W, version: FormatVersion) -> Result<()> {
+        let mut dest = IndexFieldWriter::new(dest, version);
+
+        dest.start_field(BlobSectionField::StartOfEntry)
             .context("writing start of index entry")?;
 
-        dest.write_u8(BlobSectionField::ResourceFieldType.into())
+        dest.start_field(BlobSectionField::ResourceFieldType)
             .context("writing resource field type field")?;
-        dest.write_u8(self.resource_field.into())
+        dest.write_u8(self.resource_field)
             .context("writing resource field type value")?;
 
-        dest.write_u8(BlobSectionField::RawPayloadLength.into())
+        dest.start_field(BlobSectionField::RawPayloadLength)
             .context("writing raw payload length field")?;
         dest.write_u64::<LittleEndian>(self.raw_payload_length as u64)
             .context("writing raw payload length")?;
 
         if let Some(padding) = &self.interior_padding {
-            dest.write_u8(BlobSectionField::InteriorPadding.into())
+            dest.start_field(BlobSectionField::InteriorPadding)
                 .context("writing interior padding field")?;
             dest.write_u8(padding.into())
                 .context("writing interior padding value")?;
         }
 
-        dest.write_u8(BlobSectionField::EndOfEntry.into())
+        if let Some(compression) = &self.compression {
+            dest.start_field(BlobSectionField::Compression)
+                .context("writing compression field")?;
+            dest.write_u8(compression.into())
+                .context("writing compression value")?;
+        }
+
+        if let Some(digest) = &self.digest {
+            dest.start_field(BlobSectionField::Sha256Digest)
+                .context("writing SHA-256 digest field")?;
+            dest.write_all(digest)
+                .context("writing SHA-256 digest value")?;
+        }
+
+        if self.deduplicated {
+            dest.start_field(BlobSectionField::Deduplicated)
+                .context("writing deduplicated field")?;
+        }
+
+        dest.start_field(BlobSectionField::EndOfEntry)
             .context("writing end of index entry")?;
+        dest.finish_field().context("writing end of index entry")?;
 
         Ok(())
     }
@@ -131,6 +478,8 @@ where
             || self.relative_path_distribution_resources.is_some()
             || self.file_data_embedded.is_some()
             || self.file_data_utf8_relative_path.is_some()
+            || self.annotations.is_some()
+            || self.in_memory_compressed_source.is_some()
     }
 
     /// Compute length of index entry for version 1 payload format.
@@ -264,6 +613,16 @@ where
             index += 5;
         }
 
+        if let Some(annotations) = &self.annotations {
+            index += 5;
+            // u16 + u32 for annotation name and value.
+            index += 6 * annotations.len();
+        }
+
+        if self.in_memory_compressed_source.is_some() {
+            index += 5;
+        }
+
         // End of index entry.
         index += 1;
 
@@ -427,6 +786,23 @@ where
                     0
                 }
             }
+            ResourceField::Annotations => {
+                if let Some(annotations) = &self.annotations {
+                    annotations
+                        .iter()
+                        .map(|(key, value)| key.len() + value.len())
+                        .sum()
+                } else {
+                    0
+                }
+            }
+            ResourceField::InMemoryCompressedSource => {
+                if let Some(source) = &self.in_memory_compressed_source {
+                    source.len()
+                } else {
+                    0
+                }
+            }
         }
     }
 
@@ -577,6 +953,20 @@ where
                     0
                 }
             }
+            ResourceField::Annotations => {
+                if let Some(annotations) = &self.annotations {
+                    annotations.len() * 2
+                } else {
+                    0
+                }
+            }
+            ResourceField::InMemoryCompressedSource => {
+                if self.in_memory_compressed_source.is_some() {
+                    1
+                } else {
+                    0
+                }
+            }
         };
 
         let overhead = match padding {
@@ -589,75 +979,116 @@ where
 
     /// Write the version 1 index entry for a module instance.
     pub fn write_index_v1<W: Write>(&self, dest: &mut W) -> Result<()> {
+        self.write_index(dest, FormatVersion::V3)
+    }
+
+    /// Write the index entry for a module instance using the given format version.
+    pub fn write_index<W: Write>(&self, dest: &mut W, version: FormatVersion) -> Result<()> {
+        self.write_index_with_lengths(dest, version, &|_, _, length| length)
+    }
+
+    /// Write the index entry, resolving payload lengths via a callback.
+    ///
+    /// `blob_length` receives the field, the entry name for fields holding
+    /// multiple entries, and the length of the in-memory data. It returns the
+    /// length to record in the index.
+    fn write_index_with_lengths<W: Write>(
+        &self,
+        dest: &mut W,
+        version: FormatVersion,
+        blob_length: &dyn Fn(ResourceField, Option<&str>, usize) -> usize,
+    ) -> Result<()> {
+        let mut dest = IndexFieldWriter::new(dest, version);
+
         let name_len =
             u16::try_from(self.name.as_bytes().len()).context("converting name to u16")?;
 
-        dest.write_u8(ResourceField::StartOfEntry.into())
+        dest.start_field(ResourceField::StartOfEntry)
             .context("writing start of index entry")?;
 
         if self.flavor != ResourceFlavor::None {
-            dest.write_u8(ResourceField::Flavor.into())
+            dest.start_field(ResourceField::Flavor)
                 .context("writing flavor field")?;
             dest.write_u8(self.flavor.into())
                 .context("writing flavor value")?;
         }
 
-        dest.write_u8(ResourceField::ModuleName.into())
+        dest.start_field(ResourceField::ModuleName)
             .context("writing module name field")?;
 
         dest.write_u16::<LittleEndian>(name_len)
             .context("writing module name length")?;
 
         if self.is_package {
-            dest.write_u8(ResourceField::IsPackage.into())
+            dest.start_field(ResourceField::IsPackage)
                 .context("writing is_package field")?;
         }
 
         if self.is_namespace_package {
-            dest.write_u8(ResourceField::IsNamespacePackage.into())
+            dest.start_field(ResourceField::IsNamespacePackage)
                 .context("writing is_namespace field")?;
         }
 
         if let Some(source) = &self.in_memory_source {
-            let l =
-                u32::try_from(source.len()).context("converting in-memory source length to u32")?;
-            dest.write_u8(ResourceField::InMemorySource.into())
+            let l = u32::try_from(blob_length(
+                ResourceField::InMemorySource,
+                None,
+                source.len(),
+            ))
+            .context("converting in-memory source length to u32")?;
+            dest.start_field(ResourceField::InMemorySource)
                 .context("writing in-memory source length field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing in-memory source length")?;
         }
 
         if let Some(bytecode) = &self.in_memory_bytecode {
-            let l = u32::try_from(bytecode.len())
-                .context("converting in-memory bytecode length to u32")?;
-            dest.write_u8(ResourceField::InMemoryBytecode.into())
+            let l = u32::try_from(blob_length(
+                ResourceField::InMemoryBytecode,
+                None,
+                bytecode.len(),
+            ))
+            .context("converting in-memory bytecode length to u32")?;
+            dest.start_field(ResourceField::InMemoryBytecode)
                 .context("writing in-memory bytecode length field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing in-memory bytecode length")?;
         }
 
         if let Some(bytecode) = &self.in_memory_bytecode_opt1 {
-            let l = u32::try_from(bytecode.len())
-                .context("converting in-memory bytecode opt 1 length to u32")?;
-            dest.write_u8(ResourceField::InMemoryBytecodeOpt1.into())
+            let l = u32::try_from(blob_length(
+                ResourceField::InMemoryBytecodeOpt1,
+                None,
+                bytecode.len(),
+            ))
+            .context("converting in-memory bytecode opt 1 length to u32")?;
+            dest.start_field(ResourceField::InMemoryBytecodeOpt1)
                 .context("writing in-memory bytecode opt 1 length field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing in-memory bytecode opt 1 length")?;
         }
 
         if let Some(bytecode) = &self.in_memory_bytecode_opt2 {
-            let l = u32::try_from(bytecode.len())
-                .context("converting in-memory bytecode opt 2 length to u32")?;
-            dest.write_u8(ResourceField::InMemoryBytecodeOpt2.into())
+            let l = u32::try_from(blob_length(
+                ResourceField::InMemoryBytecodeOpt2,
+                None,
+                bytecode.len(),
+            ))
+            .context("converting in-memory bytecode opt 2 length to u32")?;
+            dest.start_field(ResourceField::InMemoryBytecodeOpt2)
                 .context("writing in-memory bytecode opt 2 field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing in-memory bytecode opt 2 length")?;
         }
 
         if let Some(library) = &self.in_memory_extension_module_shared_library {
-            let l = u32::try_from(library.len())
-                .context("converting in-memory library length to u32")?;
-            dest.write_u8(ResourceField::InMemoryExtensionModuleSharedLibrary.into())
+            let l = u32::try_from(blob_length(
+                ResourceField::InMemoryExtensionModuleSharedLibrary,
+                None,
+                library.len(),
+            ))
+            .context("converting in-memory library length to u32")?;
+            dest.start_field(ResourceField::InMemoryExtensionModuleSharedLibrary)
                 .context("writing in-memory extension module shared library field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing in-memory extension module shared library length")?;
@@ -666,7 +1097,7 @@ where
         if let Some(resources) = &self.in_memory_package_resources {
             let l = u32::try_from(resources.len())
                 .context("converting in-memory resources data length to u32")?;
-            dest.write_u8(ResourceField::InMemoryResourcesData.into())
+            dest.start_field(ResourceField::InMemoryResourcesData)
                 .context("writing in-memory resources field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing in-memory resources data length")?;
@@ -676,7 +1107,11 @@ where
                     .context("converting resource name length to u16")?;
                 dest.write_u16::<LittleEndian>(name_length)
                     .context("writing resource name length")?;
-                dest.write_u64::<LittleEndian>(value.len() as u64)
+                dest.write_u64::<LittleEndian>(blob_length(
+                    ResourceField::InMemoryResourcesData,
+                    Some(name),
+                    value.len(),
+                ) as u64)
                     .context("writing resource data length")?;
             }
         }
@@ -684,7 +1119,7 @@ where
         if let Some(metadata) = &self.in_memory_distribution_resources {
             let l = u32::try_from(metadata.len())
                 .context("converting in-memory distribution metadata length to u32")?;
-            dest.write_u8(ResourceField::InMemoryDistributionResource.into())
+            dest.start_field(ResourceField::InMemoryDistributionResource)
                 .context("writing in-memory package distribution field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing in-memory package distribution length")?;
@@ -694,15 +1129,23 @@ where
                     .context("converting distribution name length to u16")?;
                 dest.write_u16::<LittleEndian>(name_length)
                     .context("writing distribution name length")?;
-                dest.write_u64::<LittleEndian>(value.len() as u64)
+                dest.write_u64::<LittleEndian>(blob_length(
+                    ResourceField::InMemoryDistributionResource,
+                    Some(name),
+                    value.len(),
+                ) as u64)
                     .context("writing distribution data length")?;
             }
         }
 
         if let Some(library) = &self.in_memory_shared_library {
-            let l = u64::try_from(library.len())
-                .context("converting in-memory shared library length to u64")?;
-            dest.write_u8(ResourceField::InMemorySharedLibrary.into())
+            let l = u64::try_from(blob_length(
+                ResourceField::InMemorySharedLibrary,
+                None,
+                library.len(),
+            ))
+            .context("converting in-memory shared library length to u64")?;
+            dest.start_field(ResourceField::InMemorySharedLibrary)
                 .context("writing in-memory shared library field")?;
             dest.write_u64::<LittleEndian>(l)
                 .context("writing in-memory shared library length")?;
@@ -711,7 +1154,7 @@ where
         if let Some(names) = &self.shared_library_dependency_names {
             let l = u16::try_from(names.len())
                 .context("converting shared library dependency names to u16")?;
-            dest.write_u8(ResourceField::SharedLibraryDependencyNames.into())
+            dest.start_field(ResourceField::SharedLibraryDependencyNames)
                 .context("writing shared library dependency names field")?;
             dest.write_u16::<LittleEndian>(l)
                 .context("writing shared library dependency names length")?;
@@ -727,7 +1170,7 @@ where
         if let Some(path) = &self.relative_path_module_source {
             let l = u32::try_from(path_bytes_length(path))
                 .context("converting module source relative path length to u32")?;
-            dest.write_u8(ResourceField::RelativeFilesystemModuleSource.into())
+            dest.start_field(ResourceField::RelativeFilesystemModuleSource)
                 .context("writing relative path module source field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing relative path module source length")?;
@@ -736,7 +1179,7 @@ where
         if let Some(path) = &self.relative_path_module_bytecode {
             let l = u32::try_from(path_bytes_length(path))
                 .context("converting module bytecode relative path to u32")?;
-            dest.write_u8(ResourceField::RelativeFilesystemModuleBytecode.into())
+            dest.start_field(ResourceField::RelativeFilesystemModuleBytecode)
                 .context("writing relative path module bytecode field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing relative path module bytecode length")?;
@@ -745,7 +1188,7 @@ where
         if let Some(path) = &self.relative_path_module_bytecode_opt1 {
             let l = u32::try_from(path_bytes_length(path))
                 .context("converting module bytecode opt1 relative path to u32")?;
-            dest.write_u8(ResourceField::RelativeFilesystemModuleBytecodeOpt1.into())
+            dest.start_field(ResourceField::RelativeFilesystemModuleBytecodeOpt1)
                 .context("writing relative path module bytecode opt1 field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing relative path module bytecode opt1 length")?;
@@ -754,7 +1197,7 @@ where
         if let Some(path) = &self.relative_path_module_bytecode_opt2 {
             let l = u32::try_from(path_bytes_length(path))
                 .context("converting module bytecode opt2 relative path to u32")?;
-            dest.write_u8(ResourceField::RelativeFilesystemModuleBytecodeOpt2.into())
+            dest.start_field(ResourceField::RelativeFilesystemModuleBytecodeOpt2)
                 .context("writing relative path module bytecode opt2 field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing relative path module bytecode opt2 length")?;
@@ -763,7 +1206,7 @@ where
         if let Some(path) = &self.relative_path_extension_module_shared_library {
             let l = u32::try_from(path_bytes_length(path))
                 .context("converting extension module shared library relative path to u32")?;
-            dest.write_u8(ResourceField::RelativeFilesystemExtensionModuleSharedLibrary.into())
+            dest.start_field(ResourceField::RelativeFilesystemExtensionModuleSharedLibrary)
                 .context("writing relative path extension module shared library field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing relative path extension module shared library length")?;
@@ -772,7 +1215,7 @@ where
         if let Some(resources) = &self.relative_path_package_resources {
             let l = u32::try_from(resources.len())
                 .context("converting relative path resources data length to u32")?;
-            dest.write_u8(ResourceField::RelativeFilesystemPackageResources.into())
+            dest.start_field(ResourceField::RelativeFilesystemPackageResources)
                 .context("writing relative path resources resources field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing relative path resources resources data length")?;
@@ -792,7 +1235,7 @@ where
         if let Some(metadata) = &self.relative_path_distribution_resources {
             let l = u32::try_from(metadata.len())
                 .context("converting relative path distribution length to u32")?;
-            dest.write_u8(ResourceField::RelativeFilesystemDistributionResource.into())
+            dest.start_field(ResourceField::RelativeFilesystemDistributionResource)
                 .context("writing relative path resources resources field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing relative path distribution data length")?;
@@ -810,44 +1253,48 @@ where
         }
 
         if self.is_module {
-            dest.write_u8(ResourceField::IsModule.into())
+            dest.start_field(ResourceField::IsModule)
                 .context("writing is_module field")?;
         }
 
         if self.is_builtin_extension_module {
-            dest.write_u8(ResourceField::IsBuiltinExtensionModule.into())
+            dest.start_field(ResourceField::IsBuiltinExtensionModule)
                 .context("writing is_builtin_extension_module field")?;
         }
 
         if self.is_frozen_module {
-            dest.write_u8(ResourceField::IsFrozenModule.into())
+            dest.start_field(ResourceField::IsFrozenModule)
                 .context("writing is_frozen_module field")?;
         }
 
         if self.is_extension_module {
-            dest.write_u8(ResourceField::IsExtensionModule.into())
+            dest.start_field(ResourceField::IsExtensionModule)
                 .context("writing is_extension_module field")?;
         }
 
         if self.is_shared_library {
-            dest.write_u8(ResourceField::IsSharedLibrary.into())
+            dest.start_field(ResourceField::IsSharedLibrary)
                 .context("writing is_shared_library field")?;
         }
 
         if self.is_utf8_filename_data {
-            dest.write_u8(ResourceField::IsUtf8FilenameData.into())
+            dest.start_field(ResourceField::IsUtf8FilenameData)
                 .context("writing is_utf8_filename_data field")?;
         }
 
         if self.file_executable {
-            dest.write_u8(ResourceField::FileExecutable.into())
+            dest.start_field(ResourceField::FileExecutable)
                 .context("writing file_executable field")?;
         }
 
         if let Some(data) = &self.file_data_embedded {
-            let l =
-                u64::try_from(data.len()).context("converting embedded file data length to u64")?;
-            dest.write_u8(ResourceField::FileDataEmbedded.into())
+            let l = u64::try_from(blob_length(
+                ResourceField::FileDataEmbedded,
+                None,
+                data.len(),
+            ))
+            .context("converting embedded file data length to u64")?;
+            dest.start_field(ResourceField::FileDataEmbedded)
                 .context("writing file_data_embedded field")?;
             dest.write_u64::<LittleEndian>(l)
                 .context("writing file_data_embedded length")?;
@@ -856,324 +1303,1440 @@ where
         if let Some(path) = &self.file_data_utf8_relative_path {
             let l = u32::try_from(path.as_bytes().len())
                 .context("converting embedded file data relative path length to u32")?;
-            dest.write_u8(ResourceField::FileDataUtf8RelativePath.into())
+            dest.start_field(ResourceField::FileDataUtf8RelativePath)
                 .context("writing file_data_utf8_relative_path field")?;
             dest.write_u32::<LittleEndian>(l)
                 .context("writing file_data_utf_relative_path field")?;
         }
 
-        dest.write_u8(ResourceField::EndOfEntry.into())
+        if let Some(annotations) = &self.annotations {
+            let l =
+                u32::try_from(annotations.len()).context("converting annotations length to u32")?;
+            dest.start_field(ResourceField::Annotations)
+                .context("writing annotations field")?;
+            dest.write_u32::<LittleEndian>(l)
+                .context("writing annotations length")?;
+
+            for (name, value) in annotations.iter() {
+                let name_length = u16::try_from(name.as_bytes().len())
+                    .context("converting annotation name length to u16")?;
+                let value_length = u32::try_from(value.len())
+                    .context("converting annotation value length to u32")?;
+                dest.write_u16::<LittleEndian>(name_length)
+                    .context("writing annotation name length")?;
+                dest.write_u32::<LittleEndian>(value_length)
+                    .context("writing annotation value length")?;
+            }
+        }
+
+        if let Some(source) = &self.in_memory_compressed_source {
+            let l = u32::try_from(blob_length(
+                ResourceField::InMemoryCompressedSource,
+                None,
+                source.len(),
+            ))
+            .context("converting in-memory compressed source length to u32")?;
+            dest.start_field(ResourceField::InMemoryCompressedSource)
+                .context("writing in-memory compressed source length field")?;
+            dest.write_u32::<LittleEndian>(l)
+                .context("writing in-memory compressed source length")?;
+        }
+
+        dest.start_field(ResourceField::EndOfEntry)
+            .map_err(|_| anyhow!("error writing end of index entry"))?;
+        dest.finish_field()
             .map_err(|_| anyhow!("error writing end of index entry"))?;
 
         Ok(())
     }
 }
 
-/// Write packed resources data, version 3.
-#[allow(clippy::cognitive_complexity)]
-pub fn write_packed_resources_v3<'a, T: AsRef<Resource<'a, u8>>, W: Write>(
-    modules: &[T],
-    dest: &mut W,
-    interior_padding: Option<BlobInteriorPadding>,
-) -> Result<()> {
-    let mut blob_sections = BTreeMap::new();
+/// A single element of blob data for a field of a resource.
+struct BlobElement<'r> {
+    data: Cow<'r, [u8]>,
 
-    let mut blob_section_count = 0;
-    // 1 for end of index field.
-    let mut blob_index_length = 1;
+    /// Identifies elements holding resource payload data.
+    ///
+    /// The value is the entry name for fields holding multiple entries.
+    /// Elements such as names and paths are `None`.
+    payload: Option<Option<&'r str>>,
+}
 
-    // 1 for end of index field.
-    let mut module_index_length = 1;
+impl<'r> BlobElement<'r> {
+    fn metadata(data: &'r [u8]) -> Self {
+        Self {
+            data: Cow::Borrowed(data),
+            payload: None,
+        }
+    }
 
-    let process_field = |blob_sections: &mut BTreeMap<ResourceField, BlobSection>,
-                         resource: &Resource<u8>,
-                         field: ResourceField| {
-        let padding = match &interior_padding {
-            Some(padding) => *padding,
-            None => BlobInteriorPadding::None,
+    fn path(path: &Path) -> Self {
+        Self {
+            data: Cow::Owned(path_to_bytes(path)),
+            payload: None,
+        }
+    }
+
+    fn payload(data: &'r [u8], name: Option<&'r str>) -> Self {
+        Self {
+            data: Cow::Borrowed(data),
+            payload: Some(name),
+        }
+    }
+}
+
+impl<'a> Resource<'a, u8> {
+    /// Obtain the elements of blob data for a field, in the order they are written.
+    ///
+    /// Each element is followed by interior padding, if any.
+    fn field_blob_elements<'r>(&'r self, field: ResourceField) -> Vec<BlobElement<'r>> {
+        let data = |data: &'r Option<Cow<'a, [u8]>>| -> Vec<BlobElement<'r>> {
+            data.iter()
+                .map(|data| BlobElement::payload(data, None))
+                .collect()
         };
 
-        let l = resource.field_blob_length(field)
-            + resource.field_blob_interior_padding_length(field, padding);
-        if l > 0 {
-            blob_sections
-                .entry(field)
-                .or_insert_with(|| BlobSection {
-                    resource_field: field,
-                    raw_payload_length: 0,
-                    interior_padding,
+        let path = |path: &'r Option<Cow<'a, Path>>| -> Vec<BlobElement<'r>> {
+            path.iter().map(|path| BlobElement::path(path)).collect()
+        };
+
+        let data_map = |map: &'r Option<HashMap<Cow<'a, str>, Cow<'a, [u8]>>>| {
+            map.iter()
+                .flat_map(|map| map.iter())
+                .flat_map(|(key, value)| {
+                    vec![
+                        BlobElement::metadata(key.as_bytes()),
+                        BlobElement::payload(value, Some(key)),
+                    ]
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let path_map = |map: &'r Option<HashMap<Cow<'a, str>, Cow<'a, Path>>>| {
+            map.iter()
+                .flat_map(|map| map.iter())
+                .flat_map(|(key, path)| {
+                    vec![
+                        BlobElement::metadata(key.as_bytes()),
+                        BlobElement::path(path),
+                    ]
+                })
+                .collect::<Vec<_>>()
+        };
+
+        match field {
+            ResourceField::ModuleName => vec![BlobElement::metadata(self.name.as_bytes())],
+            ResourceField::InMemorySource => data(&self.in_memory_source),
+            ResourceField::InMemoryCompressedSource => data(&self.in_memory_compressed_source),
+            ResourceField::InMemoryBytecode => data(&self.in_memory_bytecode),
+            ResourceField::InMemoryBytecodeOpt1 => data(&self.in_memory_bytecode_opt1),
+            ResourceField::InMemoryBytecodeOpt2 => data(&self.in_memory_bytecode_opt2),
+            ResourceField::InMemoryExtensionModuleSharedLibrary => {
+                data(&self.in_memory_extension_module_shared_library)
+            }
+            ResourceField::InMemoryResourcesData => data_map(&self.in_memory_package_resources),
+            ResourceField::InMemoryDistributionResource => {
+                data_map(&self.in_memory_distribution_resources)
+            }
+            ResourceField::InMemorySharedLibrary => data(&self.in_memory_shared_library),
+            ResourceField::SharedLibraryDependencyNames => self
+                .shared_library_dependency_names
+                .iter()
+                .flat_map(|names| names.iter())
+                .map(|name| BlobElement::metadata(name.as_bytes()))
+                .collect(),
+            ResourceField::RelativeFilesystemModuleSource => {
+                path(&self.relative_path_module_source)
+            }
+            ResourceField::RelativeFilesystemModuleBytecode => {
+                path(&self.relative_path_module_bytecode)
+            }
+            ResourceField::RelativeFilesystemModuleBytecodeOpt1 => {
+                path(&self.relative_path_module_bytecode_opt1)
+            }
+            ResourceField::RelativeFilesystemModuleBytecodeOpt2 => {
+                path(&self.relative_path_module_bytecode_opt2)
+            }
+            ResourceField::RelativeFilesystemExtensionModuleSharedLibrary => {
+                path(&self.relative_path_extension_module_shared_library)
+            }
+            ResourceField::RelativeFilesystemPackageResources => {
+                path_map(&self.relative_path_package_resources)
+            }
+            ResourceField::RelativeFilesystemDistributionResource => {
+                path_map(&self.relative_path_distribution_resources)
+            }
+            ResourceField::FileDataEmbedded => data(&self.file_data_embedded),
+            ResourceField::FileDataUtf8RelativePath => self
+                .file_data_utf8_relative_path
+                .iter()
+                .map(|path| BlobElement::metadata(path.as_bytes()))
+                .collect(),
+            ResourceField::Annotations => self
+                .annotations
+                .iter()
+                .flat_map(|map| map.iter())
+                .flat_map(|(key, value)| {
+                    vec![
+                        BlobElement::metadata(key.as_bytes()),
+                        BlobElement::metadata(value),
+                    ]
                 })
-                .raw_payload_length += l;
+                .collect(),
+            _ => vec![],
         }
-    };
+    }
 
-    let add_interior_padding = |dest: &mut W| -> Result<()> {
-        if interior_padding == Some(BlobInteriorPadding::Null) {
-            dest.write_all(b"\0")?;
+    /// Write the blob data for a field of this resource.
+    fn write_field_blob_data<W: Write>(
+        &self,
+        field: ResourceField,
+        dest: &mut W,
+        interior_padding: Option<BlobInteriorPadding>,
+    ) -> Result<()> {
+        for element in self.field_blob_elements(field) {
+            dest.write_all(&element.data)?;
+
+            if interior_padding == Some(BlobInteriorPadding::Null) {
+                dest.write_all(b"\0")?;
+            }
         }
 
         Ok(())
-    };
-
-    for module in modules {
-        let module = module.as_ref();
-        module_index_length += module.index_v1_length();
-
-        process_field(&mut blob_sections, module, ResourceField::ModuleName);
-        process_field(&mut blob_sections, module, ResourceField::InMemorySource);
-        process_field(&mut blob_sections, module, ResourceField::InMemoryBytecode);
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::InMemoryBytecodeOpt1,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::InMemoryBytecodeOpt2,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::InMemoryExtensionModuleSharedLibrary,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::InMemoryResourcesData,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::InMemoryDistributionResource,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::InMemorySharedLibrary,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::SharedLibraryDependencyNames,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::RelativeFilesystemModuleSource,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::RelativeFilesystemModuleBytecode,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::RelativeFilesystemModuleBytecodeOpt1,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::RelativeFilesystemModuleBytecodeOpt2,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::RelativeFilesystemExtensionModuleSharedLibrary,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::RelativeFilesystemPackageResources,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::RelativeFilesystemDistributionResource,
-        );
-        process_field(&mut blob_sections, module, ResourceField::FileDataEmbedded);
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::FileDataUtf8RelativePath,
-        );
     }
+}
 
-    for section in blob_sections.values() {
-        blob_section_count += 1;
-        blob_index_length += section.index_v1_length();
+/// Validate writer options and drop compression settings that are no-ops.
+fn normalize_options(options: &WriterOptions) -> Result<WriterOptions> {
+    let compression = options
+        .compression
+        .iter()
+        .filter(|(_, compression)| **compression != BlobSectionCompression::None)
+        .map(|(field, compression)| (*field, *compression))
+        .collect::<BTreeMap<_, _>>();
+
+    if !compression.is_empty() && !cfg!(feature = "compression") {
+        return Err(anyhow!(
+            "blob section compression requires the compression feature"
+        ));
     }
 
-    dest.write_all(HEADER_V3)?;
-
-    dest.write_u8(blob_section_count)?;
-    dest.write_u32::<LittleEndian>(blob_index_length as u32)?;
-    dest.write_u32::<LittleEndian>(modules.len() as u32)?;
-    dest.write_u32::<LittleEndian>(module_index_length as u32)?;
+    if options.section_digests && !cfg!(feature = "digests") {
+        return Err(anyhow!("section digests require the digests feature"));
+    }
 
-    // Write the blob index.
-    for section in blob_sections.values() {
-        section.write_index_v1(dest)?;
+    // Version 3 parsers reject blob index fields they don't know about.
+    if options.format_version == FormatVersion::V3
+        && (!compression.is_empty()
+            || options.lookup_table
+            || options.section_digests
+            || options.deduplicate_blobs)
+    {
+        return Err(anyhow!(
+            "compression, lookup tables, digests, and deduplication require format version 4"
+        ));
     }
-    dest.write_u8(ResourceField::EndOfIndex.into())?;
 
-    // Write the resources index.
-    for module in modules {
-        module.as_ref().write_index_v1(dest)?;
+    for field in compression.keys() {
+        if !field.supports_compression() {
+            return Err(anyhow!(
+                "compression not supported for resource field {:?}",
+                field
+            ));
+        }
     }
-    dest.write_u8(ResourceField::EndOfIndex.into())?;
 
-    // Write blob data, one field at a time.
-    for module in modules {
-        dest.write_all(module.as_ref().name.as_bytes())?;
-        add_interior_padding(dest)?;
+    Ok(WriterOptions {
+        compression,
+        ..options.clone()
+    })
+}
+
+/// Write packed resources data, version 3.
+pub fn write_packed_resources_v3<'a, T: AsRef<Resource<'a, u8>>, W: Write>(
+    modules: &[T],
+    dest: &mut W,
+    interior_padding: Option<BlobInteriorPadding>,
+) -> Result<()> {
+    write_packed_resources(
+        modules,
+        dest,
+        &WriterOptions {
+            format_version: FormatVersion::V3,
+            interior_padding,
+            ..WriterOptions::default()
+        },
+    )
+}
+
+/// Write packed resources data using the given options.
+///
+/// This is the successor to `write_packed_resources_v3()`. When writing
+/// format version 3 without blob section compression, output is identical
+/// to that function.
+pub fn write_packed_resources<'a, T: AsRef<Resource<'a, u8>>, W: Write>(
+    modules: &[T],
+    dest: &mut W,
+    options: &WriterOptions,
+) -> Result<()> {
+    let options = normalize_options(options)?;
+
+    let modules = modules
+        .iter()
+        .map(|module| decompressed_resource(module.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+
+    if options.compression.is_empty() {
+        write_resources_data(&modules, dest, &options)
+    } else {
+        let compressed = modules
+            .iter()
+            .map(|module| compress_resource(module, &options))
+            .collect::<Result<Vec<_>>>()?;
+
+        write_resources_data(&compressed, dest, &options)
     }
+}
 
-    for module in modules {
-        if let Some(data) = &module.as_ref().in_memory_source {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
+/// Append a detached ed25519 signature to serialized packed resources data.
+///
+/// The signature covers all of `data`. Parsers not verifying signatures
+/// ignore it.
+#[cfg(feature = "signatures")]
+pub fn append_signature(data: &mut Vec<u8>, keypair: &Keypair) {
+    let signature = keypair.sign(data);
+
+    data.extend_from_slice(&signature.to_bytes());
+    data.extend_from_slice(SIGNATURE_TRAILER_ED25519);
+}
+
+/// Resource fields having blob data, in the order their blob sections are written.
+const BLOB_FIELDS: &[ResourceField] = &[
+    ResourceField::ModuleName,
+    ResourceField::InMemorySource,
+    ResourceField::InMemoryBytecode,
+    ResourceField::InMemoryBytecodeOpt1,
+    ResourceField::InMemoryBytecodeOpt2,
+    ResourceField::InMemoryExtensionModuleSharedLibrary,
+    ResourceField::InMemoryResourcesData,
+    ResourceField::InMemoryDistributionResource,
+    ResourceField::InMemorySharedLibrary,
+    ResourceField::SharedLibraryDependencyNames,
+    ResourceField::RelativeFilesystemModuleSource,
+    ResourceField::RelativeFilesystemModuleBytecode,
+    ResourceField::RelativeFilesystemModuleBytecodeOpt1,
+    ResourceField::RelativeFilesystemModuleBytecodeOpt2,
+    ResourceField::RelativeFilesystemExtensionModuleSharedLibrary,
+    ResourceField::RelativeFilesystemPackageResources,
+    ResourceField::RelativeFilesystemDistributionResource,
+    ResourceField::FileDataEmbedded,
+    ResourceField::FileDataUtf8RelativePath,
+    ResourceField::Annotations,
+    ResourceField::InMemoryCompressedSource,
+];
+
+/// Layout of a blob section storing identical entries only once.
+///
+/// The section begins with a table holding a `u64` for every entry, in the
+/// order entries are written. Each value is the offset of the entry's data
+/// from the start of the section. Entry data follows the table, with only
+/// the first occurrence of identical data being stored.
+struct DeduplicatedLayout {
+    /// Offset of each entry's data from the start of the section.
+    references: Vec<u64>,
+    /// Whether each entry is the first occurrence of its data.
+    stored: Vec<bool>,
+    /// Total length of the section.
+    length: usize,
+}
+
+impl DeduplicatedLayout {
+    /// Compute the deduplicated layout of a field's blob section.
+    ///
+    /// Returns `None` if the layout would not make the section smaller.
+    fn new<'a, T: AsRef<Resource<'a, u8>>>(
+        modules: &[T],
+        field: ResourceField,
+        interior_padding: Option<BlobInteriorPadding>,
+    ) -> Option<Self> {
+        let padding = if interior_padding == Some(BlobInteriorPadding::Null) {
+            1
+        } else {
+            0
+        };
+
+        let mut data_offsets = HashMap::new();
+        let mut references = vec![];
+        let mut stored = vec![];
+        let mut data_length = 0;
+        let mut plain_length = 0;
+
+        for module in modules {
+            for element in module.as_ref().field_blob_elements(field) {
+                let length = element.data.len() + padding;
+                plain_length += length;
+
+                match data_offsets.entry(element.data) {
+                    Entry::Occupied(entry) => {
+                        references.push(*entry.get());
+                        stored.push(false);
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(data_length as u64);
+                        references.push(data_length as u64);
+                        stored.push(true);
+                        data_length += length;
+                    }
+                }
+            }
         }
+
+        let table_length = references.len() * 8;
+
+        if table_length + data_length >= plain_length {
+            return None;
+        }
+
+        for reference in references.iter_mut() {
+            *reference += table_length as u64;
+        }
+
+        Some(Self {
+            references,
+            stored,
+            length: table_length + data_length,
+        })
     }
+}
 
-    for module in modules {
-        if let Some(data) = &module.as_ref().in_memory_bytecode {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
+/// Write the data of a field's blob section.
+fn write_blob_section_data<'a, T: AsRef<Resource<'a, u8>>, W: Write>(
+    modules: &[T],
+    field: ResourceField,
+    dest: &mut W,
+    interior_padding: Option<BlobInteriorPadding>,
+    deduplicated_layout: Option<&DeduplicatedLayout>,
+) -> Result<()> {
+    let layout = match deduplicated_layout {
+        Some(layout) => layout,
+        None => {
+            for module in modules {
+                module
+                    .as_ref()
+                    .write_field_blob_data(field, dest, interior_padding)?;
+            }
+
+            return Ok(());
         }
+    };
+
+    for reference in &layout.references {
+        dest.write_u64::<LittleEndian>(*reference)
+            .context("writing blob reference")?;
     }
 
-    for module in modules {
-        if let Some(data) = &module.as_ref().in_memory_bytecode_opt1 {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
+    let elements = modules
+        .iter()
+        .flat_map(|module| module.as_ref().field_blob_elements(field));
+
+    for (element, stored) in elements.zip(&layout.stored) {
+        if *stored {
+            dest.write_all(&element.data)?;
+
+            if interior_padding == Some(BlobInteriorPadding::Null) {
+                dest.write_all(b"\0")?;
+            }
         }
     }
 
+    Ok(())
+}
+
+/// Describes where data for an individual resource lives.
+struct LookupRecord<'a> {
+    name: Cow<'a, str>,
+    /// Offset of the resource's entry within the resources index.
+    index_offset: usize,
+    /// Offset of the resource's data within each blob section it has data in.
+    blob_offsets: Vec<(ResourceField, usize)>,
+}
+
+/// Serialize the resource lookup table blob section.
+fn lookup_table_data(records: &[LookupRecord]) -> Result<Vec<u8>> {
+    // Keep the load factor at or below 0.5 so probe sequences stay short.
+    let bucket_count = (records.len() * 2).next_power_of_two();
+
+    let mut slots = vec![0u32; bucket_count];
+    let mut slot_names: Vec<Option<&str>> = vec![None; bucket_count];
+    let mut records_data = Vec::new();
+
+    for record in records {
+        let record_offset = u32::try_from(records_data.len() + 1)
+            .context("converting lookup record offset to u32")?;
+
+        records_data
+            .write_u64::<LittleEndian>(resource_name_hash(&record.name))
+            .context("writing lookup record name hash")?;
+        records_data
+            .write_u32::<LittleEndian>(
+                u32::try_from(record.index_offset).context("converting index offset to u32")?,
+            )
+            .context("writing lookup record index offset")?;
+        records_data
+            .write_u8(record.blob_offsets.len() as u8)
+            .context("writing lookup record blob offsets count")?;
+        for (field, offset) in &record.blob_offsets {
+            records_data
+                .write_u8((*field).into())
+                .context("writing lookup record resource field")?;
+            records_data
+                .write_u64::<LittleEndian>(*offset as u64)
+                .context("writing lookup record blob offset")?;
+        }
+
+        // Later resources having the same name replace earlier ones.
+        let mut bucket = (resource_name_hash(&record.name) % bucket_count as u64) as usize;
+        while slots[bucket] != 0 && slot_names[bucket] != Some(&record.name) {
+            bucket = (bucket + 1) % bucket_count;
+        }
+
+        slots[bucket] = record_offset;
+        slot_names[bucket] = Some(&record.name);
+    }
+
+    let mut data = Vec::with_capacity(4 + bucket_count * 4 + records_data.len());
+    data.write_u32::<LittleEndian>(bucket_count as u32)
+        .context("writing lookup table bucket count")?;
+    for slot in slots {
+        data.write_u32::<LittleEndian>(slot)
+            .context("writing lookup table slot")?;
+    }
+    data.write_all(&records_data)
+        .context("writing lookup table records")?;
+
+    Ok(data)
+}
+
+/// Obtain the blob section and data for the resource lookup table.
+fn lookup_table_section(
+    records: &[LookupRecord],
+    options: &WriterOptions,
+) -> Result<(BlobSection, Vec<u8>)> {
+    let data = lookup_table_data(records)?;
+
+    let section = BlobSection {
+        resource_field: LOOKUP_TABLE_BLOB_SECTION,
+        raw_payload_length: data.len(),
+        interior_padding: None,
+        compression: None,
+        digest: DigestWriter::for_section(options).map(|mut writer| {
+            writer.write_all(&data).expect("hashing never fails");
+            writer.finalize()
+        }),
+        deduplicated: false,
+    };
+
+    Ok((section, data))
+}
+
+/// Write the header and blob index.
+///
+/// `resources_index_length` includes the end of index field.
+fn write_header_and_blob_index<W: Write>(
+    dest: &mut W,
+    version: FormatVersion,
+    blob_sections: &[&BlobSection],
+    resources_count: usize,
+    resources_index_length: usize,
+) -> Result<()> {
+    let mut blob_index = Vec::new();
+    for section in blob_sections {
+        section.write_index(&mut blob_index, version)?;
+    }
+    write_end_of_index(&mut blob_index, version)?;
+
+    dest.write_all(match version {
+        FormatVersion::V3 => HEADER_V3,
+        FormatVersion::V4 => HEADER_V4,
+    })?;
+
+    dest.write_u8(blob_sections.len() as u8)?;
+    dest.write_u32::<LittleEndian>(blob_index.len() as u32)?;
+    dest.write_u32::<LittleEndian>(resources_count as u32)?;
+    dest.write_u32::<LittleEndian>(resources_index_length as u32)?;
+
+    dest.write_all(&blob_index)?;
+
+    Ok(())
+}
+
+#[allow(clippy::cognitive_complexity)]
+fn write_resources_data<'a, T: AsRef<Resource<'a, u8>>, W: Write>(
+    modules: &[T],
+    dest: &mut W,
+    options: &WriterOptions,
+) -> Result<()> {
+    let interior_padding = options.interior_padding;
+
+    let mut blob_sections = BTreeMap::new();
+
+    let mut resources_index = Vec::new();
+
+    let mut lookup_records = Vec::new();
+
+    let deduplicated_layouts = if options.deduplicate_blobs {
+        BLOB_FIELDS
+            .iter()
+            .filter_map(|field| {
+                DeduplicatedLayout::new(modules, *field, interior_padding)
+                    .map(|layout| (*field, layout))
+            })
+            .collect::<BTreeMap<_, _>>()
+    } else {
+        BTreeMap::new()
+    };
+
+    // Returns the offset of the resource's data within the blob section.
+    //
+    // For deduplicated sections, this is the offset of the resource's first
+    // entry in the table of references.
+    let process_field = |blob_sections: &mut BTreeMap<ResourceField, BlobSection>,
+                         resource: &Resource<u8>,
+                         field: ResourceField|
+     -> Option<usize> {
+        let padding = match &interior_padding {
+            Some(padding) => *padding,
+            None => BlobInteriorPadding::None,
+        };
+
+        let l = if deduplicated_layouts.contains_key(&field) {
+            resource.field_blob_elements(field).len() * 8
+        } else {
+            resource.field_blob_length(field)
+                + resource.field_blob_interior_padding_length(field, padding)
+        };
+        if l > 0 {
+            let section = blob_sections.entry(field).or_insert_with(|| BlobSection {
+                resource_field: field.into(),
+                raw_payload_length: 0,
+                interior_padding,
+                compression: options.compression.get(&field).copied(),
+                digest: None,
+                deduplicated: deduplicated_layouts.contains_key(&field),
+            });
+
+            let offset = section.raw_payload_length;
+            section.raw_payload_length += l;
+
+            Some(offset)
+        } else {
+            None
+        }
+    };
+
     for module in modules {
-        if let Some(data) = &module.as_ref().in_memory_bytecode_opt2 {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
+        let module = module.as_ref();
+
+        let mut record = LookupRecord {
+            name: Cow::Borrowed(&module.name),
+            index_offset: resources_index.len(),
+            blob_offsets: Vec::new(),
+        };
+
+        module.write_index(&mut resources_index, options.format_version)?;
+
+        for field in BLOB_FIELDS {
+            if let Some(offset) = process_field(&mut blob_sections, module, *field) {
+                record.blob_offsets.push((*field, offset));
+            }
+        }
+
+        if options.lookup_table {
+            lookup_records.push(record);
         }
     }
 
-    for module in modules {
-        if let Some(data) = &module.as_ref().in_memory_extension_module_shared_library {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
+    for (field, layout) in &deduplicated_layouts {
+        if let Some(section) = blob_sections.get_mut(field) {
+            section.raw_payload_length = layout.length;
         }
     }
 
-    for module in modules {
-        if let Some(resources) = &module.as_ref().in_memory_package_resources {
-            for (key, value) in resources.iter() {
-                dest.write_all(key.as_bytes())?;
-                add_interior_padding(dest)?;
-                dest.write_all(value)?;
-                add_interior_padding(dest)?;
-            }
+    for (field, section) in blob_sections.iter_mut() {
+        if let Some(mut writer) = DigestWriter::for_section(options) {
+            write_blob_section_data(
+                modules,
+                *field,
+                &mut writer,
+                interior_padding,
+                deduplicated_layouts.get(field),
+            )?;
+
+            section.digest = Some(writer.finalize());
         }
     }
 
-    for module in modules {
-        if let Some(resources) = &module.as_ref().in_memory_distribution_resources {
-            for (key, value) in resources {
-                dest.write_all(key.as_bytes())?;
-                add_interior_padding(dest)?;
-                dest.write_all(value)?;
-                add_interior_padding(dest)?;
+    // The lookup table is always the last blob section.
+    let lookup_table = if options.lookup_table && !modules.is_empty() {
+        Some(lookup_table_section(&lookup_records, options)?)
+    } else {
+        None
+    };
+
+    write_end_of_index(&mut resources_index, options.format_version)?;
+
+    write_header_and_blob_index(
+        dest,
+        options.format_version,
+        &blob_sections
+            .values()
+            .chain(lookup_table.iter().map(|(section, _)| section))
+            .collect::<Vec<_>>(),
+        modules.len(),
+        resources_index.len(),
+    )?;
+
+    dest.write_all(&resources_index)?;
+
+    // Write blob data, one field at a time.
+    for field in blob_sections.keys() {
+        write_blob_section_data(
+            modules,
+            *field,
+            dest,
+            interior_padding,
+            deduplicated_layouts.get(field),
+        )?;
+    }
+
+    if let Some((_, data)) = &lookup_table {
+        dest.write_all(data)?;
+    }
+
+    Ok(())
+}
+
+/// Filesystem paths providing payload data for a resource added to a `StreamingWriter`.
+///
+/// Keys are the resource field and, for fields holding multiple entries
+/// (`InMemoryResourcesData` and `InMemoryDistributionResource`), the entry name.
+pub type BlobFiles = BTreeMap<(ResourceField, Option<String>), PathBuf>;
+
+/// Storage for blob data added to a `StreamingWriter` from memory.
+#[cfg(feature = "spooling")]
+type Spool = File;
+
+/// Storage for blob data added to a `StreamingWriter` from memory.
+#[cfg(not(feature = "spooling"))]
+type Spool = std::io::Cursor<Vec<u8>>;
+
+#[cfg(feature = "spooling")]
+fn new_spool() -> std::io::Result<Spool> {
+    tempfile::tempfile()
+}
+
+#[cfg(not(feature = "spooling"))]
+fn new_spool() -> std::io::Result<Spool> {
+    Ok(std::io::Cursor::new(vec![]))
+}
+
+/// Where data of a blob element added to a `StreamingWriter` is read from.
+#[derive(Clone)]
+enum StreamedData {
+    /// A range of the section's spool.
+    Spool { offset: u64, length: u64 },
+    /// The entire content of a file.
+    File { path: PathBuf, length: u64 },
+}
+
+impl StreamedData {
+    fn length(&self) -> u64 {
+        match self {
+            StreamedData::Spool { length, .. } => *length,
+            StreamedData::File { length, .. } => *length,
+        }
+    }
+
+    /// Obtain a reader of the data.
+    fn open<'s>(&self, spool: &'s mut Option<Spool>) -> Result<(Box<dyn Read + 's>, String)> {
+        match self {
+            StreamedData::Spool { offset, length } => {
+                let spool = spool.as_mut().expect("spool exists");
+                spool
+                    .seek(SeekFrom::Start(*offset))
+                    .context("seeking spool file")?;
+
+                Ok((Box::new(spool.take(*length)), "spool file".to_string()))
             }
+            StreamedData::File { path, length } => Ok((
+                Box::new(
+                    File::open(path)
+                        .with_context(|| format!("opening {}", path.display()))?
+                        .take(*length),
+                ),
+                path.display().to_string(),
+            )),
         }
     }
+}
 
-    for module in modules {
-        if let Some(data) = &module.as_ref().in_memory_shared_library {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
+/// A `Write` feeding all written data into a non-cryptographic hasher.
+struct HashWriter(DefaultHasher);
+
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A `Write` counting the bytes written to an inner writer.
+struct CountingWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.count += count as u64;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Whether two readers yield identical data.
+fn readers_equal(mut a: impl Read, mut b: impl Read) -> std::io::Result<bool> {
+    let mut a_buffer = vec![0; 65536];
+    let mut b_buffer = vec![0; 65536];
+
+    loop {
+        let count = a.read(&mut a_buffer)?;
+        if count == 0 {
+            return Ok(b.read(&mut b_buffer[0..1])? == 0);
+        }
+
+        match b.read_exact(&mut b_buffer[0..count]) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+        }
+
+        if a_buffer[0..count] != b_buffer[0..count] {
+            return Ok(false);
         }
     }
+}
 
-    for module in modules {
-        if let Some(names) = &module.as_ref().shared_library_dependency_names {
-            for name in names {
-                dest.write_all(name.as_bytes())?;
-                add_interior_padding(dest)?;
-            }
+/// A blob element added to a `StreamingWriter`.
+struct StreamedElement {
+    data: StreamedData,
+    /// Index of the first element of the section having identical data.
+    duplicate_of: Option<usize>,
+}
+
+/// Layout of the data of a `StreamingWriter` blob section.
+struct StreamedLayout {
+    /// Offset of each element within the section if it isn't deduplicated.
+    ///
+    /// Has an extra entry holding the section's length.
+    offsets: Vec<u64>,
+    /// Offset of each element's data if the section is deduplicated.
+    ///
+    /// See `DeduplicatedLayout`.
+    references: Option<Vec<u64>>,
+    /// Total length of the section.
+    length: u64,
+}
+
+/// Blob section state accumulated by a `StreamingWriter`.
+struct StreamedSection {
+    /// Data of elements added from memory.
+    ///
+    /// A temporary file with the `spooling` feature. Otherwise held in memory.
+    spool: Option<Spool>,
+    spool_length: u64,
+    elements: Vec<StreamedElement>,
+    /// Indices of elements not duplicating earlier elements, keyed by the
+    /// hash and length of their data.
+    ///
+    /// Only populated when deduplicating blobs.
+    unique_elements: HashMap<(u64, u64), Vec<usize>>,
+}
+
+impl StreamedSection {
+    fn new() -> Self {
+        Self {
+            spool: None,
+            spool_length: 0,
+            elements: vec![],
+            unique_elements: HashMap::new(),
         }
     }
 
-    for module in modules {
-        if let Some(path) = &module.as_ref().relative_path_module_source {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
+    /// Add an element whose data is written to the spool by `write`.
+    fn add_spooled(
+        &mut self,
+        deduplicate: bool,
+        write: impl FnOnce(&mut CountingWriter<&mut Spool>) -> Result<()>,
+    ) -> Result<usize> {
+        if self.spool.is_none() {
+            self.spool = Some(new_spool().context("creating spool file")?);
         }
+
+        let spool = self.spool.as_mut().unwrap();
+        spool
+            .seek(SeekFrom::Start(self.spool_length))
+            .context("seeking spool file")?;
+
+        let mut writer = CountingWriter {
+            inner: spool,
+            count: 0,
+        };
+        write(&mut writer)?;
+
+        let data = StreamedData::Spool {
+            offset: self.spool_length,
+            length: writer.count,
+        };
+        self.spool_length += writer.count;
+
+        self.add_element(data, deduplicate)
     }
 
-    for module in modules {
-        if let Some(path) = &module.as_ref().relative_path_module_bytecode {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
+    /// Add an element whose data is the content of a file.
+    ///
+    /// The file is only read now if deduplicating blobs.
+    fn add_file(&mut self, deduplicate: bool, path: &Path, fh: &File) -> Result<usize> {
+        let length = fh
+            .metadata()
+            .with_context(|| format!("reading metadata of {}", path.display()))?
+            .len();
+
+        self.add_element(
+            StreamedData::File {
+                path: path.to_path_buf(),
+                length,
+            },
+            deduplicate,
+        )
+    }
+
+    /// Record a new element, detecting whether it duplicates an earlier element.
+    ///
+    /// Returns the length of the element's data.
+    fn add_element(&mut self, data: StreamedData, deduplicate: bool) -> Result<usize> {
+        let length = data.length();
+
+        if !deduplicate {
+            self.elements.push(StreamedElement {
+                data,
+                duplicate_of: None,
+            });
+
+            return Ok(length as usize);
         }
+
+        let mut hasher = HashWriter(DefaultHasher::new());
+        let (mut reader, description) = data.open(&mut self.spool)?;
+        std::io::copy(&mut reader, &mut hasher)
+            .with_context(|| format!("reading {}", description))?;
+        drop(reader);
+        let key = (hasher.0.finish(), length);
+
+        let candidates = self.unique_elements.get(&key).cloned().unwrap_or_default();
+
+        for index in candidates {
+            let original = self.elements[index].data.clone();
+
+            if self.same_data(&original, &data)? {
+                // Data of duplicates doesn't need to be retained.
+                if let StreamedData::Spool { offset, .. } = data {
+                    self.spool_length = offset;
+                }
+
+                self.elements.push(StreamedElement {
+                    data: original,
+                    duplicate_of: Some(index),
+                });
+
+                return Ok(length as usize);
+            }
+        }
+
+        self.unique_elements
+            .entry(key)
+            .or_default()
+            .push(self.elements.len());
+        self.elements.push(StreamedElement {
+            data,
+            duplicate_of: None,
+        });
+
+        Ok(length as usize)
     }
 
-    for module in modules {
-        if let Some(path) = &module.as_ref().relative_path_module_bytecode_opt1 {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
+    /// Whether two elements' data is identical.
+    fn same_data(&mut self, a: &StreamedData, b: &StreamedData) -> Result<bool> {
+        match (a, b) {
+            (StreamedData::File { path, length }, other)
+            | (other, StreamedData::File { path, length }) => {
+                let fh = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+                let (reader, description) = other.open(&mut self.spool)?;
+
+                readers_equal(fh.take(*length), reader)
+                    .with_context(|| format!("comparing {} with {}", path.display(), description))
+            }
+            (a, b) => {
+                let mut data = vec![];
+                a.open(&mut self.spool)?
+                    .0
+                    .read_to_end(&mut data)
+                    .context("reading spool file")?;
+
+                readers_equal(&data[..], b.open(&mut self.spool)?.0).context("reading spool file")
+            }
         }
     }
 
-    for module in modules {
-        if let Some(path) = &module.as_ref().relative_path_module_bytecode_opt2 {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
+    /// Discard elements added after the section had `elements_count` elements.
+    fn truncate(&mut self, elements_count: usize, spool_length: u64) {
+        self.elements.truncate(elements_count);
+        self.spool_length = spool_length;
+
+        for indices in self.unique_elements.values_mut() {
+            indices.retain(|index| *index < elements_count);
         }
+        self.unique_elements
+            .retain(|_, indices| !indices.is_empty());
     }
 
-    for module in modules {
-        if let Some(path) = &module
-            .as_ref()
-            .relative_path_extension_module_shared_library
-        {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
+    /// Compute the layout of this section's data.
+    fn layout(&self, options: &WriterOptions) -> StreamedLayout {
+        let padding = if options.interior_padding == Some(BlobInteriorPadding::Null) {
+            1
+        } else {
+            0
+        };
+
+        let mut offsets = Vec::with_capacity(self.elements.len() + 1);
+        let mut plain_length = 0;
+        let mut data_length = 0;
+
+        for element in &self.elements {
+            offsets.push(plain_length);
+            plain_length += element.data.length() + padding;
+
+            if element.duplicate_of.is_none() {
+                data_length += element.data.length() + padding;
+            }
+        }
+        offsets.push(plain_length);
+
+        let table_length = self.elements.len() as u64 * 8;
+
+        if !options.deduplicate_blobs || table_length + data_length >= plain_length {
+            return StreamedLayout {
+                offsets,
+                references: None,
+                length: plain_length,
+            };
+        }
+
+        let mut references: Vec<u64> = Vec::with_capacity(self.elements.len());
+        let mut stored_length = table_length;
+
+        for element in &self.elements {
+            match element.duplicate_of {
+                Some(index) => {
+                    let reference = references[index];
+                    references.push(reference);
+                }
+                None => {
+                    references.push(stored_length);
+                    stored_length += element.data.length() + padding;
+                }
+            }
+        }
+
+        StreamedLayout {
+            offsets,
+            references: Some(references),
+            length: table_length + data_length,
         }
     }
 
-    for module in modules {
-        if let Some(resources) = &module.as_ref().relative_path_package_resources {
-            for (key, path) in resources.iter() {
-                dest.write_all(key.as_bytes())?;
-                add_interior_padding(dest)?;
-                dest.write_all(&path_to_bytes(path))?;
-                add_interior_padding(dest)?;
+    /// Write this section's data.
+    fn write_data<W: Write>(
+        &mut self,
+        dest: &mut W,
+        layout: &StreamedLayout,
+        interior_padding: Option<BlobInteriorPadding>,
+    ) -> Result<()> {
+        if let Some(references) = &layout.references {
+            for reference in references {
+                dest.write_u64::<LittleEndian>(*reference)
+                    .context("writing blob reference")?;
             }
         }
+
+        for element in &self.elements {
+            if layout.references.is_some() && element.duplicate_of.is_some() {
+                continue;
+            }
+
+            let (mut reader, description) = element.data.open(&mut self.spool)?;
+
+            let copied = std::io::copy(&mut reader, dest)
+                .with_context(|| format!("copying data from {}", description))?;
+
+            if copied != element.data.length() {
+                return Err(anyhow!("{} changed while writing resources", description));
+            }
+
+            if interior_padding == Some(BlobInteriorPadding::Null) {
+                dest.write_all(b"\0")?;
+            }
+        }
+
+        Ok(())
     }
+}
 
-    for module in modules {
-        if let Some(resources) = &module.as_ref().relative_path_distribution_resources {
-            for (key, path) in resources {
-                dest.write_all(key.as_bytes())?;
-                add_interior_padding(dest)?;
-                dest.write_all(&path_to_bytes(path))?;
-                add_interior_padding(dest)?;
+/// Describes where data for a resource added to a `StreamingWriter` lives.
+struct StreamedRecord {
+    name: String,
+    /// Offset of the resource's entry within the resources index.
+    index_offset: usize,
+    /// Index of the resource's first element and count of its elements
+    /// within each blob section it has elements in.
+    elements: Vec<(ResourceField, usize, usize)>,
+}
+
+/// Writes packed resources data from resources added one at a time.
+///
+/// Unlike `write_packed_resources()`, this does not require all resources
+/// to be held in memory at once. Blob data of each added resource is
+/// spooled to temporary files and payload data backed by files (see
+/// `add_resource_with_files()`) is not read into memory at all. Only the
+/// resources index and per-resource bookkeeping are retained in memory.
+/// Without the `spooling` feature, blob data not backed by files is
+/// retained in memory as well.
+///
+/// Output is equivalent to that of `write_packed_resources()` given the
+/// same resources and options.
+pub struct StreamingWriter {
+    options: WriterOptions,
+    sections: BTreeMap<ResourceField, StreamedSection>,
+    resources_index: Vec<u8>,
+    records: Vec<StreamedRecord>,
+}
+
+impl StreamingWriter {
+    /// Construct a new instance writing with the given options.
+    pub fn new(options: &WriterOptions) -> Result<Self> {
+        Ok(Self {
+            options: normalize_options(options)?,
+            sections: BTreeMap::new(),
+            resources_index: vec![],
+            records: vec![],
+        })
+    }
+
+    /// Add a resource whose data is held in memory.
+    pub fn add_resource(&mut self, resource: &Resource<u8>) -> Result<()> {
+        self.add_resource_with_files(resource, &BlobFiles::new())
+    }
+
+    /// Add a resource having payload data backed by files.
+    ///
+    /// Each entry in `files` supplies the data of a payload declared by
+    /// `resource`. The payload must still be present on `resource` (e.g. as an
+    /// empty slice) but its in-memory data is ignored. Files are streamed to the
+    /// output by `finish()` and must not change until then.
+    ///
+    /// If an error is returned, the resource is not added and the writer can
+    /// still be used.
+    pub fn add_resource_with_files(
+        &mut self,
+        resource: &Resource<u8>,
+        files: &BlobFiles,
+    ) -> Result<()> {
+        let resource = decompressed_resource(resource)?;
+
+        let compressed;
+        let resource = if self.options.compression.is_empty() {
+            resource.as_ref()
+        } else {
+            compressed = compress_resource(&resource, &self.options)?;
+            &compressed
+        };
+
+        // Validate files before anything is written.
+        let mut declared = BTreeSet::new();
+        for field in BLOB_FIELDS {
+            for element in resource.field_blob_elements(*field) {
+                if let Some(name) = element.payload {
+                    declared.insert((*field, name.map(|name| name.to_string())));
+                }
+            }
+        }
+
+        let mut file_handles = BTreeMap::new();
+        for (key, path) in files {
+            if !declared.contains(key) {
+                return Err(anyhow!(
+                    "resource {} does not declare all payloads provided by files",
+                    resource.name
+                ));
+            }
+
+            let fh = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+            if !fh
+                .metadata()
+                .with_context(|| format!("reading metadata of {}", path.display()))?
+                .is_file()
+            {
+                return Err(anyhow!("{} is not a regular file", path.display()));
+            }
+
+            file_handles.insert(key.clone(), (path, fh));
+        }
+
+        let snapshot = self
+            .sections
+            .iter()
+            .map(|(field, section)| (*field, (section.elements.len(), section.spool_length)))
+            .collect::<BTreeMap<_, _>>();
+
+        match self.add_validated_resource(resource, file_handles) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                // Discard everything recorded for this resource.
+                self.sections = std::mem::take(&mut self.sections)
+                    .into_iter()
+                    .filter(|(field, _)| snapshot.contains_key(field))
+                    .collect();
+                for (field, (elements_count, spool_length)) in snapshot {
+                    if let Some(section) = self.sections.get_mut(&field) {
+                        section.truncate(elements_count, spool_length);
+                    }
+                }
+
+                Err(e)
             }
         }
     }
 
-    for module in modules {
-        if let Some(data) = &module.as_ref().file_data_embedded {
+    /// Record blob data and index entry of a validated resource.
+    fn add_validated_resource(
+        &mut self,
+        resource: &Resource<u8>,
+        mut file_handles: BTreeMap<(ResourceField, Option<String>), (&PathBuf, File)>,
+    ) -> Result<()> {
+        let options = &self.options;
+
+        let mut record = StreamedRecord {
+            name: resource.name.to_string(),
+            index_offset: self.resources_index.len(),
+            elements: vec![],
+        };
+
+        let mut file_lengths = BTreeMap::new();
+
+        for field in BLOB_FIELDS {
+            let elements = resource.field_blob_elements(*field);
+            if elements.is_empty() {
+                continue;
+            }
+
+            let section = self
+                .sections
+                .entry(*field)
+                .or_insert_with(StreamedSection::new);
+
+            let first = section.elements.len();
+
+            for element in &elements {
+                let key = element
+                    .payload
+                    .map(|name| (*field, name.map(|name| name.to_string())));
+
+                match key.and_then(|key| file_handles.remove(&key).map(|file| (key, file))) {
+                    Some((key, (path, fh))) => {
+                        let length = match options.compression.get(field) {
+                            Some(compression) => {
+                                let compression = *compression;
+                                let level = options.compression_level;
+
+                                section.add_spooled(options.deduplicate_blobs, |spool| {
+                                    compress_file(fh, path, spool, compression, level)
+                                })?
+                            }
+                            None => section.add_file(options.deduplicate_blobs, path, &fh)?,
+                        };
+
+                        file_lengths.insert(key, length);
+                    }
+                    None => {
+                        section.add_spooled(options.deduplicate_blobs, |spool| {
+                            spool
+                                .write_all(&element.data)
+                                .context("writing blob data to spool file")
+                        })?;
+                    }
+                }
+            }
+
+            record.elements.push((*field, first, elements.len()));
+        }
+
+        let mut index = vec![];
+        resource.write_index_with_lengths(
+            &mut index,
+            self.options.format_version,
+            &|field, name, length| {
+                *file_lengths
+                    .get(&(field, name.map(|name| name.to_string())))
+                    .unwrap_or(&length)
+            },
+        )?;
+
+        self.resources_index.extend(index);
+        self.records.push(record);
+
+        Ok(())
+    }
+
+    /// Write packed resources data for all added resources.
+    pub fn finish<W: Write>(mut self, dest: &mut W) -> Result<()> {
+        let options = &self.options;
+
+        let mut sections = BTreeMap::new();
+
+        for (field, section) in self.sections.iter_mut() {
+            let layout = section.layout(options);
+
+            // Sections are only emitted if they have data.
+            if layout.length == 0 {
+                continue;
+            }
+
+            let digest = match DigestWriter::for_section(options) {
+                Some(mut writer) => {
+                    section.write_data(&mut writer, &layout, options.interior_padding)?;
+                    Some(writer.finalize())
+                }
+                None => None,
+            };
+
+            let blob_section = BlobSection {
+                resource_field: (*field).into(),
+                raw_payload_length: layout.length as usize,
+                interior_padding: options.interior_padding,
+                compression: options.compression.get(field).copied(),
+                digest,
+                deduplicated: layout.references.is_some(),
+            };
+
+            sections.insert(*field, (blob_section, layout));
+        }
+
+        // The lookup table is always the last blob section.
+        let lookup_table = if options.lookup_table && !self.records.is_empty() {
+            let records = self
+                .records
+                .iter()
+                .map(|record| LookupRecord {
+                    name: Cow::Borrowed(&record.name),
+                    index_offset: record.index_offset,
+                    blob_offsets: record
+                        .elements
+                        .iter()
+                        .filter_map(|(field, first, count)| {
+                            let layout = &sections.get(field)?.1;
+
+                            // For deduplicated sections, this is the offset of
+                            // the resource's first entry in the table of
+                            // references.
+                            if layout.references.is_some() {
+                                Some((*field, *first * 8))
+                            } else if layout.offsets[first + count] > layout.offsets[*first] {
+                                Some((*field, layout.offsets[*first] as usize))
+                            } else {
+                                None
+                            }
+                        })
+                        .collect(),
+                })
+                .collect::<Vec<_>>();
+
+            Some(lookup_table_section(&records, options)?)
+        } else {
+            None
+        };
+
+        write_end_of_index(&mut self.resources_index, options.format_version)?;
+
+        write_header_and_blob_index(
+            dest,
+            options.format_version,
+            &sections
+                .values()
+                .map(|(section, _)| section)
+                .chain(lookup_table.iter().map(|(section, _)| section))
+                .collect::<Vec<_>>(),
+            self.records.len(),
+            self.resources_index.len(),
+        )?;
+
+        dest.write_all(&self.resources_index)?;
+
+        for (field, (_, layout)) in &sections {
+            self.sections.get_mut(field).unwrap().write_data(
+                dest,
+                layout,
+                options.interior_padding,
+            )?;
+        }
+
+        if let Some((_, data)) = &lookup_table {
             dest.write_all(data)?;
-            add_interior_padding(dest)?;
         }
+
+        Ok(())
     }
+}
 
-    for module in modules {
-        if let Some(path) = &module.as_ref().file_data_utf8_relative_path {
-            dest.write_all(path.as_bytes())?;
-            add_interior_padding(dest)?;
+/// Write the compressed content of a file.
+fn compress_file<W: Write>(
+    fh: File,
+    path: &Path,
+    dest: &mut W,
+    compression: BlobSectionCompression,
+    level: i32,
+) -> Result<()> {
+    match compression {
+        BlobSectionCompression::None => {
+            std::io::copy(&mut BufReader::new(fh), dest)
+                .with_context(|| format!("reading {}", path.display()))?;
+        }
+        #[cfg(feature = "compression")]
+        BlobSectionCompression::Zstd => {
+            zstd::stream::copy_encode(fh, dest, level)
+                .with_context(|| format!("zstd compressing {}", path.display()))?;
+        }
+        #[cfg(not(feature = "compression"))]
+        BlobSectionCompression::Zstd => {
+            let _ = (fh, dest, level);
+            return Err(anyhow!("zstd compression support not enabled"));
         }
     }
 
@@ -1251,4 +2814,373 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_is_meaningful() {
+        let resource = Resource::<u8> {
+            name: Cow::from("foo"),
+            ..Resource::default()
+        };
+        assert!(!resource.is_meaningful());
+
+        let mut annotations = HashMap::new();
+        annotations.insert(Cow::from("key"), Cow::from(&b"value"[..]));
+
+        assert!(Resource {
+            annotations: Some(annotations),
+            ..resource.clone()
+        }
+        .is_meaningful());
+
+        assert!(Resource {
+            in_memory_compressed_source: Some(Cow::from(&b"data"[..])),
+            ..resource
+        }
+        .is_meaningful());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_write_compressed() -> Result<()> {
+        let module = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::Owned("foo".to_string()),
+            in_memory_source: Some(Cow::Owned(b"import io".to_vec())),
+            ..Resource::default()
+        };
+
+        let mut options = WriterOptions::default();
+        options
+            .compression
+            .insert(ResourceField::InMemorySource, BlobSectionCompression::Zstd);
+
+        let mut data = Vec::new();
+        write_packed_resources(&[&module], &mut data, &options)?;
+
+        let compressed = zstd::stream::encode_all(&b"import io"[..], 0)?;
+
+        let mut resources = crate::parser::load_resources(&data)
+            .map_err(|e| anyhow!(e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!(e))?;
+        assert_eq!(resources.len(), 1);
+
+        let resource = &mut resources[0];
+        assert_eq!(
+            resource.compressed_fields,
+            vec![ResourceField::InMemorySource]
+        );
+        assert_eq!(
+            resource.in_memory_source.as_deref(),
+            Some(compressed.as_slice())
+        );
+
+        resource.decompress().map_err(|e| anyhow!(e))?;
+        resource.compressed_fields.clear();
+        assert_eq!(resource, &module);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_v3_unsupported_options() {
+        let module = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::Owned("foo".to_string()),
+            in_memory_source: Some(Cow::Owned(b"import io".to_vec())),
+            ..Resource::default()
+        };
+
+        let v3 = WriterOptions {
+            format_version: FormatVersion::V3,
+            ..WriterOptions::default()
+        };
+
+        let mut compressed = v3.clone();
+        compressed.compress_all(BlobSectionCompression::Zstd);
+
+        for options in &[
+            compressed,
+            WriterOptions {
+                lookup_table: true,
+                ..v3.clone()
+            },
+            WriterOptions {
+                section_digests: true,
+                ..v3.clone()
+            },
+            WriterOptions {
+                deduplicate_blobs: true,
+                ..v3.clone()
+            },
+        ] {
+            assert!(write_packed_resources(&[&module], &mut Vec::new(), options).is_err());
+            assert!(StreamingWriter::new(options).is_err());
+        }
+
+        // Options not changing the blob index are fine.
+        let mut none = v3.clone();
+        none.compress_all(BlobSectionCompression::None);
+        assert!(write_packed_resources(&[module], &mut Vec::new(), &none).is_ok());
+    }
+
+    #[test]
+    fn test_write_compressed_unsupported_field() {
+        let module = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::Owned("foo".to_string()),
+            ..Resource::default()
+        };
+
+        let mut options = WriterOptions::default();
+        options
+            .compression
+            .insert(ResourceField::ModuleName, BlobSectionCompression::Zstd);
+
+        assert!(write_packed_resources(&[module], &mut Vec::new(), &options).is_err());
+    }
+
+    #[test]
+    fn test_write_deduplicated_without_duplicates() -> Result<()> {
+        let mut resources = streaming_test_resources();
+        // The last resource duplicates data of the first.
+        resources.pop();
+
+        let mut expected = Vec::new();
+        write_packed_resources(&resources, &mut expected, &WriterOptions::default())?;
+
+        // Sections are only deduplicated if that makes them smaller.
+        let mut data = Vec::new();
+        write_packed_resources(
+            &resources,
+            &mut data,
+            &WriterOptions {
+                deduplicate_blobs: true,
+                ..WriterOptions::default()
+            },
+        )?;
+
+        assert_eq!(data, expected);
+
+        Ok(())
+    }
+
+    fn streaming_test_resources() -> Vec<Resource<'static, u8>> {
+        let mut resources = HashMap::new();
+        resources.insert(Cow::Borrowed("data.txt"), Cow::Borrowed(&b"data"[..]));
+        resources.insert(Cow::Borrowed("big.bin"), Cow::Owned(vec![42u8; 100_000]));
+        let mut copies = HashMap::new();
+        copies.insert(Cow::Borrowed("copy.bin"), Cow::Owned(vec![42u8; 100_000]));
+
+        vec![
+            Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::Borrowed("foo"),
+                is_package: true,
+                in_memory_source: Some(Cow::Borrowed(b"import bar")),
+                in_memory_package_resources: Some(resources),
+                ..Resource::default()
+            },
+            Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::Borrowed("foo.empty"),
+                in_memory_source: Some(Cow::Borrowed(b"")),
+                ..Resource::default()
+            },
+            Resource {
+                flavor: ResourceFlavor::None,
+                name: Cow::Borrowed("file.bin"),
+                file_data_embedded: Some(Cow::Borrowed(b"file data")),
+                relative_path_module_source: Some(Cow::Owned(PathBuf::from("lib/foo.py"))),
+                ..Resource::default()
+            },
+            Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::Borrowed("bar"),
+                is_package: true,
+                in_memory_package_resources: Some(copies),
+                ..Resource::default()
+            },
+        ]
+    }
+
+    fn streaming_test_options() -> Vec<WriterOptions> {
+        let mut options = vec![
+            WriterOptions::default(),
+            WriterOptions {
+                interior_padding: Some(BlobInteriorPadding::Null),
+                lookup_table: true,
+                section_digests: cfg!(feature = "digests"),
+                ..WriterOptions::default()
+            },
+            WriterOptions {
+                lookup_table: true,
+                deduplicate_blobs: true,
+                ..WriterOptions::default()
+            },
+            WriterOptions {
+                interior_padding: Some(BlobInteriorPadding::Null),
+                lookup_table: true,
+                section_digests: cfg!(feature = "digests"),
+                deduplicate_blobs: true,
+                ..WriterOptions::default()
+            },
+        ];
+
+        if cfg!(feature = "compression") {
+            let mut compressed = WriterOptions {
+                lookup_table: true,
+                section_digests: cfg!(feature = "digests"),
+                ..WriterOptions::default()
+            };
+            compressed.compress_all(BlobSectionCompression::Zstd);
+            options.push(compressed);
+        }
+
+        options
+    }
+
+    /// Assert that two packed resources blobs hold the same resources.
+    fn assert_same_resources(data: &[u8], expected: &[u8], options: &WriterOptions) {
+        assert_eq!(data.len(), expected.len());
+
+        let parsed = crate::parser::load_resources(data).unwrap();
+        assert_eq!(parsed.has_lookup_table(), options.lookup_table);
+        #[cfg(feature = "digests")]
+        if options.section_digests {
+            parsed.verify_digests(true).unwrap();
+        }
+
+        assert_eq!(
+            parsed.collect::<Result<Vec<_>, _>>().unwrap(),
+            crate::parser::load_resources(expected)
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_streaming_writer() -> Result<()> {
+        let resources = streaming_test_resources();
+
+        for options in streaming_test_options() {
+            let mut expected = Vec::new();
+            write_packed_resources(&resources, &mut expected, &options)?;
+
+            let mut writer = StreamingWriter::new(&options)?;
+            for resource in &resources {
+                writer.add_resource(resource)?;
+            }
+            let mut data = Vec::new();
+            writer.finish(&mut data)?;
+
+            assert_same_resources(&data, &expected, &options);
+            if options.deduplicate_blobs {
+                assert!(data.len() < 200_000);
+            }
+        }
+
+        let mut data = Vec::new();
+        StreamingWriter::new(&WriterOptions::default())?.finish(&mut data)?;
+        let mut expected = Vec::new();
+        write_packed_resources::<Resource<u8>, _>(&[], &mut expected, &WriterOptions::default())?;
+        assert_eq!(data, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_streaming_writer_files() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let resources = streaming_test_resources();
+
+        let big_path = temp_dir.path().join("big.bin");
+        std::fs::write(
+            &big_path,
+            &resources[0].in_memory_package_resources.as_ref().unwrap()["big.bin"],
+        )?;
+        let source_path = temp_dir.path().join("foo.py");
+        std::fs::write(&source_path, b"import bar")?;
+
+        let mut files = BlobFiles::new();
+        files.insert(
+            (
+                ResourceField::InMemoryResourcesData,
+                Some("big.bin".to_string()),
+            ),
+            big_path,
+        );
+        files.insert((ResourceField::InMemorySource, None), source_path);
+
+        // Payloads backed by files only need to be declared.
+        let mut placeholder = resources[0].clone();
+        placeholder.in_memory_source = Some(Cow::Borrowed(b""));
+        placeholder
+            .in_memory_package_resources
+            .as_mut()
+            .unwrap()
+            .insert(Cow::Borrowed("big.bin"), Cow::Borrowed(b""));
+
+        for options in streaming_test_options() {
+            let mut expected = Vec::new();
+            write_packed_resources(&resources, &mut expected, &options)?;
+
+            let mut writer = StreamingWriter::new(&options)?;
+            writer.add_resource_with_files(&placeholder, &files)?;
+            for resource in &resources[1..] {
+                writer.add_resource(resource)?;
+            }
+            let mut data = Vec::new();
+            writer.finish(&mut data)?;
+
+            assert_same_resources(&data, &expected, &options);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_streaming_writer_rejected_resource() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let resources = streaming_test_resources();
+
+        let data_path = temp_dir.path().join("data.txt");
+        std::fs::write(&data_path, b"data")?;
+
+        let key = (ResourceField::FileDataEmbedded, None);
+        let mut undeclared = BlobFiles::new();
+        undeclared.insert(key.clone(), data_path.clone());
+        undeclared.insert((ResourceField::InMemorySource, None), data_path);
+        let mut missing = BlobFiles::new();
+        missing.insert(key.clone(), temp_dir.path().join("missing"));
+        let mut directory = BlobFiles::new();
+        directory.insert(key, temp_dir.path().to_path_buf());
+
+        for options in streaming_test_options() {
+            let mut expected = Vec::new();
+            write_packed_resources(&resources, &mut expected, &options)?;
+
+            let mut writer = StreamingWriter::new(&options)?;
+            writer.add_resource(&resources[0])?;
+            writer.add_resource(&resources[1])?;
+
+            // Rejected resources have no effect on the output.
+            for files in &[&undeclared, &missing, &directory] {
+                assert!(writer
+                    .add_resource_with_files(&resources[2], files)
+                    .is_err());
+            }
+
+            for resource in &resources[2..] {
+                writer.add_resource(resource)?;
+            }
+            let mut data = Vec::new();
+            writer.finish(&mut data)?;
+
+            assert_same_resources(&data, &expected, &options);
+        }
+
+        Ok(())
+    }
 }